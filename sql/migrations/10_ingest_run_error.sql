@@ -0,0 +1,2 @@
+-- the error an ingest run stopped on, see ingest_report.rs
+ALTER TABLE ingest_runs ADD COLUMN error TEXT;
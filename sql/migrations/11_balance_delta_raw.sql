@@ -0,0 +1,10 @@
+-- exact balances in base units, see coin_normalize.rs. An archive does not
+-- know the balance before the first change of an account in it, so those
+-- rows have before_known = false, and no before balances or change.
+ALTER TABLE balance_deltas ADD COLUMN before_known BOOLEAN;
+
+ALTER TABLE balance_deltas ADD COLUMN before_balance_raw BIGINT;
+
+ALTER TABLE balance_deltas ADD COLUMN after_balance_raw BIGINT;
+
+ALTER TABLE balance_deltas ADD COLUMN change_raw BIGINT;
@@ -0,0 +1,144 @@
+-- addresses are hex literals, e.g. 0x1
+CREATE TABLE accounts (
+    address TEXT PRIMARY KEY
+);
+
+-- one row per transaction, the relation is the Neo4j relationship type
+CREATE TABLE transactions (
+    tx_hash TEXT PRIMARY KEY,
+    sender TEXT NOT NULL REFERENCES accounts (address),
+    recipient TEXT NOT NULL REFERENCES accounts (address),
+    relation TEXT NOT NULL,
+    entry_function TEXT NOT NULL,
+    args TEXT,
+    coins DOUBLE PRECISION,
+    version BIGINT NOT NULL,
+    epoch BIGINT NOT NULL,
+    block_timestamp BIGINT NOT NULL,
+    block_datetime TEXT NOT NULL,
+    sequence_number BIGINT NOT NULL,
+    gas_used BIGINT NOT NULL,
+    gas_unit_price BIGINT NOT NULL,
+    max_gas_amount BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    abort_code BIGINT,
+    abort_location TEXT,
+    framework_version TEXT NOT NULL
+);
+
+CREATE INDEX transactions_sender ON transactions (sender);
+CREATE INDEX transactions_recipient ON transactions (recipient);
+CREATE INDEX transactions_version ON transactions (version);
+
+CREATE TABLE events (
+    tx_hash TEXT NOT NULL,
+    event_index BIGINT NOT NULL,
+    account TEXT NOT NULL REFERENCES accounts (address),
+    event_type TEXT NOT NULL,
+    event_name TEXT NOT NULL,
+    amount BIGINT,
+    data TEXT NOT NULL,
+    PRIMARY KEY (tx_hash, event_index)
+);
+
+CREATE TABLE balance_deltas (
+    tx_hash TEXT NOT NULL,
+    address TEXT NOT NULL REFERENCES accounts (address),
+    version BIGINT NOT NULL,
+    before_balance DOUBLE PRECISION,
+    after_balance DOUBLE PRECISION,
+    change DOUBLE PRECISION,
+    before_unlocked DOUBLE PRECISION,
+    after_unlocked DOUBLE PRECISION,
+    before_transferred DOUBLE PRECISION,
+    after_transferred DOUBLE PRECISION,
+    PRIMARY KEY (tx_hash, address)
+);
+
+CREATE TABLE snapshots (
+    address TEXT NOT NULL REFERENCES accounts (address),
+    version BIGINT NOT NULL,
+    epoch BIGINT NOT NULL,
+    balance DOUBLE PRECISION NOT NULL,
+    sequence_num BIGINT NOT NULL,
+    slow_unlocked DOUBLE PRECISION,
+    slow_transfer DOUBLE PRECISION,
+    slow_wallet BOOLEAN NOT NULL,
+    donor_voice BOOLEAN NOT NULL,
+    miner_height BIGINT,
+    framework_version TEXT NOT NULL,
+    PRIMARY KEY (address, version)
+);
+
+CREATE TABLE epochs (
+    epoch BIGINT PRIMARY KEY,
+    first_version BIGINT NOT NULL,
+    last_version BIGINT,
+    start_timestamp BIGINT NOT NULL,
+    start_datetime TEXT NOT NULL
+);
+
+CREATE TABLE validator_sets (
+    epoch BIGINT NOT NULL REFERENCES epochs (epoch),
+    address TEXT NOT NULL REFERENCES accounts (address),
+    voting_power BIGINT NOT NULL,
+    PRIMARY KEY (epoch, address)
+);
+
+-- exchange users, by their id on the exchange
+CREATE TABLE swap_accounts (
+    swap_id BIGINT PRIMARY KEY
+);
+
+-- exchange orders have no id, so the order itself is the key
+CREATE TABLE exchange_orders (
+    user_id BIGINT NOT NULL REFERENCES swap_accounts (swap_id),
+    accepter BIGINT NOT NULL REFERENCES swap_accounts (swap_id),
+    order_type TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    price DOUBLE PRECISION NOT NULL,
+    created_at TEXT NOT NULL,
+    created_at_ts BIGINT NOT NULL,
+    filled_at TEXT NOT NULL,
+    filled_at_ts BIGINT NOT NULL,
+    accepter_shill_down BOOLEAN NOT NULL,
+    accepter_shill_up BOOLEAN NOT NULL,
+    rms_hour DOUBLE PRECISION NOT NULL,
+    rms_24hour DOUBLE PRECISION NOT NULL,
+    price_vs_rms_hour DOUBLE PRECISION NOT NULL,
+    price_vs_rms_24hour DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (user_id, accepter, order_type, amount, price, created_at_ts, filled_at_ts)
+);
+
+CREATE TABLE user_ledgers (
+    swap_id BIGINT NOT NULL REFERENCES swap_accounts (swap_id),
+    ledger_date TEXT NOT NULL,
+    current_balance DOUBLE PRECISION NOT NULL,
+    total_funded DOUBLE PRECISION NOT NULL,
+    total_inflows DOUBLE PRECISION NOT NULL,
+    total_outflows DOUBLE PRECISION NOT NULL,
+    daily_funding DOUBLE PRECISION NOT NULL,
+    daily_inflows DOUBLE PRECISION NOT NULL,
+    daily_outflows DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (swap_id, ledger_date)
+);
+
+CREATE TABLE owners (
+    owner TEXT NOT NULL,
+    address TEXT NOT NULL REFERENCES accounts (address),
+    PRIMARY KEY (owner, address)
+);
+
+CREATE TABLE onramps (
+    address TEXT NOT NULL REFERENCES accounts (address),
+    swap_id BIGINT NOT NULL REFERENCES swap_accounts (swap_id),
+    PRIMARY KEY (address, swap_id)
+);
+
+-- load progress of each batch of an archive, see queue.rs
+CREATE TABLE queue (
+    archive_id TEXT NOT NULL,
+    batch BIGINT NOT NULL,
+    completed BOOLEAN NOT NULL,
+    PRIMARY KEY (archive_id, batch)
+);
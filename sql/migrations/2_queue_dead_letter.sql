@@ -0,0 +1,4 @@
+-- batches which failed after their retries keep the error of the last
+-- attempt, see retry.rs
+ALTER TABLE queue ADD COLUMN error TEXT;
+ALTER TABLE queue ADD COLUMN attempts BIGINT;
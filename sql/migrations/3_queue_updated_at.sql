@@ -0,0 +1,2 @@
+-- last update of each batch, in unix milliseconds, for `queue status`
+ALTER TABLE queue ADD COLUMN updated_at BIGINT;
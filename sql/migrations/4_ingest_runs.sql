@@ -0,0 +1,27 @@
+-- audit trail of ingest runs, see ingest_report.rs
+CREATE TABLE ingest_runs (
+    run_id TEXT PRIMARY KEY,
+    command TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT,
+    tool_version TEXT NOT NULL,
+    -- JSON list of the CLI arguments, without credentials
+    args TEXT NOT NULL,
+    archive_count BIGINT NOT NULL
+);
+
+CREATE TABLE archive_reports (
+    run_id TEXT NOT NULL REFERENCES ingest_runs (run_id),
+    archive_id TEXT NOT NULL,
+    contents TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    extracted BIGINT NOT NULL,
+    created BIGINT NOT NULL,
+    mismatch BIGINT NOT NULL,
+    unique_accounts BIGINT NOT NULL,
+    created_accounts BIGINT NOT NULL,
+    modified_accounts BIGINT NOT NULL,
+    unchanged_accounts BIGINT NOT NULL,
+    PRIMARY KEY (run_id, archive_id)
+);
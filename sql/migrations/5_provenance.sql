@@ -0,0 +1,23 @@
+-- the archive, manifest and chunk file each record was read from, see provenance.rs
+ALTER TABLE transactions ADD COLUMN archive_id TEXT;
+ALTER TABLE transactions ADD COLUMN manifest_path TEXT;
+ALTER TABLE transactions ADD COLUMN chunk_sha256 TEXT;
+
+ALTER TABLE events ADD COLUMN archive_id TEXT;
+ALTER TABLE events ADD COLUMN manifest_path TEXT;
+ALTER TABLE events ADD COLUMN chunk_sha256 TEXT;
+
+ALTER TABLE balance_deltas ADD COLUMN archive_id TEXT;
+ALTER TABLE balance_deltas ADD COLUMN manifest_path TEXT;
+ALTER TABLE balance_deltas ADD COLUMN chunk_sha256 TEXT;
+
+ALTER TABLE snapshots ADD COLUMN archive_id TEXT;
+ALTER TABLE snapshots ADD COLUMN manifest_path TEXT;
+ALTER TABLE snapshots ADD COLUMN chunk_sha256 TEXT;
+
+ALTER TABLE epochs ADD COLUMN archive_id TEXT;
+ALTER TABLE epochs ADD COLUMN manifest_path TEXT;
+ALTER TABLE epochs ADD COLUMN chunk_sha256 TEXT;
+
+CREATE INDEX transactions_archive_id ON transactions (archive_id);
+CREATE INDEX snapshots_archive_id ON snapshots (archive_id);
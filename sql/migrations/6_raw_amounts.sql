@@ -0,0 +1,4 @@
+-- exact amounts in base units, the DOUBLE PRECISION columns are for display
+ALTER TABLE transactions ADD COLUMN coins_raw BIGINT;
+
+ALTER TABLE snapshots ADD COLUMN balance_raw BIGINT;
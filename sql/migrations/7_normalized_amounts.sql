@@ -0,0 +1,17 @@
+-- amounts in current base units, see coin_normalize.rs. V5 balances were
+-- stored in V5 units, so they are normalized.
+ALTER TABLE transactions ADD COLUMN coins_normalized BIGINT;
+
+ALTER TABLE snapshots ADD COLUMN balance_normalized BIGINT;
+
+UPDATE snapshots
+SET
+    balance_normalized = balance_raw * 35,
+    balance = balance * 35,
+    slow_unlocked = slow_unlocked * 35,
+    slow_transfer = slow_transfer * 35
+WHERE framework_version = 'V5';
+
+UPDATE snapshots
+SET balance_normalized = balance_raw
+WHERE framework_version <> 'V5';
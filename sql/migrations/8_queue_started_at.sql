@@ -0,0 +1,2 @@
+-- start of the write of each started batch, in unix milliseconds, for `db doctor`
+ALTER TABLE queue ADD COLUMN started_at BIGINT;
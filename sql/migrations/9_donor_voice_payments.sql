@@ -0,0 +1,4 @@
+-- the payee and amount, in base units, of proposed donor voice payments
+ALTER TABLE transactions ADD COLUMN payee TEXT;
+
+ALTER TABLE transactions ADD COLUMN payment_coins_raw BIGINT;
@@ -1,15 +1,20 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use log::{error, trace};
-use neo4rs::{Graph, Query};
+use neo4rs::{BoltType, Graph};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::{
     collections::BTreeMap,
     fs::{self, File},
     io::Read,
 };
 
-use crate::schema_exchange_orders::{ExchangeOrder, OrderType};
+use crate::{
+    cypher_templates::{execute_batch, json_list_to_bolt},
+    graph_sink::GraphSink,
+    schema_exchange_orders::{ExchangeOrder, OrderType},
+};
 
 #[cfg(test)]
 use crate::util::parse_date;
@@ -169,43 +174,38 @@ impl BalanceTracker {
         None
     }
 
-    pub fn to_cypher_map(&self, id: u32) -> Result<String> {
+    /// The daily ledger entries of one user as JSON objects.
+    pub fn to_cypher_json(&self, id: u32) -> Result<Vec<Value>> {
         let ul = self.0.get(&id).context("no user")?;
-        let mut list_literal: String = "".to_owned();
-
-        for date in ul.0.keys() {
-            if let Some(acc) = ul.0.get(date) {
-                let obj = format!(
-                    r#"{{ swap_id: {}, date: "{}", current_balance: {}, total_funded: {}, total_inflows: {}, total_outflows: {}, daily_funding: {}, daily_inflows: {}, daily_outflows: {} }}"#,
-                    id,
-                    date.to_rfc3339(),
-                    acc.current_balance,
-                    acc.total_funded,
-                    acc.total_inflows,
-                    acc.total_outflows,
-                    acc.daily_funding,
-                    acc.daily_inflows,
-                    acc.daily_outflows,
-                );
-
-                list_literal.push_str(&obj);
-                list_literal.push(',');
-            } else {
-                continue;
-            }
-        }
 
-        list_literal.pop(); // need to drop last comma ","
-        Ok(format!("[{}]", list_literal))
+        let list: Vec<Value> =
+            ul.0.iter()
+                .map(|(date, acc)| {
+                    json!({
+                        "swap_id": id,
+                        "date": date.to_rfc3339(),
+                        "current_balance": acc.current_balance,
+                        "total_funded": acc.total_funded,
+                        "total_inflows": acc.total_inflows,
+                        "total_outflows": acc.total_outflows,
+                        "daily_funding": acc.daily_funding,
+                        "daily_inflows": acc.daily_inflows,
+                        "daily_outflows": acc.daily_outflows,
+                    })
+                })
+                .collect();
+
+        Ok(list)
     }
 
-    pub async fn submit_one_id(&self, id: u32, pool: &Graph) -> Result<u64> {
-        let data = self.to_cypher_map(id)?;
-        let query_literal = generate_cypher_query(data);
-        let query = Query::new(query_literal);
-        let mut result = pool.execute(query).await?;
+    pub fn to_cypher_map(&self, id: u32) -> Result<BoltType> {
+        Ok(json_list_to_bolt(&self.to_cypher_json(id)?))
+    }
 
-        let row = result.next().await?.context("no row returned")?;
+    pub async fn submit_one_id(&self, id: u32, pool: &Graph) -> Result<u64> {
+        let batch = self.to_cypher_map(id)?;
+        let cypher_string = generate_cypher_query();
+        let row = execute_batch(pool, &cypher_string, batch).await?;
 
         let merged: u64 = row
             .get("merged_relations")
@@ -215,10 +215,10 @@ impl BalanceTracker {
         Ok(merged)
     }
     /// submit to db
-    pub async fn submit_ledger(&self, pool: &Graph) -> Result<u64> {
+    pub async fn submit_ledger(&self, sink: &impl GraphSink) -> Result<u64> {
         let mut merged_relations = 0u64;
         for id in self.0.keys() {
-            match self.submit_one_id(*id, pool).await {
+            match sink.upsert_user_ledger(self, *id).await {
                 Ok(m) => merged_relations += m,
                 Err(e) => error!("could not submit user ledger: {}", e),
             }
@@ -227,14 +227,12 @@ impl BalanceTracker {
     }
 }
 
-/// Generate a Cypher query string to insert data into Neo4j
-pub fn generate_cypher_query(map: String) -> String {
-    // r#"{{ swap_id: {}, date: "{}", current_balance: {}, total_funded: {}, total_inflows: {}, total_outflows: {}, daily_funding: {}, daily_inflows: {}, daily_outflows: {} }}"#,
-    format!(
-        r#"
-            UNWIND {map} AS account
-            MERGE (sa:SwapAccount {{swap_id: account.swap_id}})
-            MERGE (ul:UserLedger {{swap_id: account.swap_id, date: datetime(account.date)}})
+/// Generate a Cypher query string to insert the `$batch` of ledger entries into Neo4j
+pub fn generate_cypher_query() -> String {
+    r#"
+            UNWIND $batch AS account
+            MERGE (sa:SwapAccount {swap_id: account.swap_id})
+            MERGE (ul:UserLedger {swap_id: account.swap_id, date: datetime(account.date)})
             SET ul.current_balance = account.current_balance,
                 ul.total_funded = account.total_funded,
                 ul.total_inflows = account.total_inflows,
@@ -245,8 +243,8 @@ pub fn generate_cypher_query(map: String) -> String {
             MERGE (sa)-[r:DailyLedger]->(ul)
             SET r.date = datetime(account.date)
             RETURN COUNT(r) as merged_relations
-            "#,
-    )
+            "#
+    .to_string()
 }
 
 #[test]
@@ -85,7 +85,7 @@ CALL {
         r#"
 MATCH (from_user:SwapAccount)-[t:Swap]->(to_accepter:SwapAccount)
 ORDER BY t.filled_at
-SKIP {skip_to} LIMIT {limit}
+SKIP $skip_to LIMIT $limit
 WITH DISTINCT t as txs, from_user, to_accepter, t.filled_at AS current_time
 
 MATCH (from_user2:SwapAccount)-[other:Swap]->(to_accepter2:SwapAccount)
@@ -100,7 +100,9 @@ WITH txs, COUNT(other) as matching_trades, sqrt(avg(other.price * other.price))
 RETURN DISTINCT(elementId(txs)) AS id, txs.filled_at AS time, matching_trades, rms
       "#
     );
-    let cypher_query = neo4rs::query(&cypher_string);
+    let cypher_query = neo4rs::query(&cypher_string)
+        .param("skip_to", skip_to as i64)
+        .param("limit", limit as i64);
 
     let mut res = pool
         .execute(cypher_query)
@@ -12,11 +12,27 @@ use log::{info, trace, warn};
 use neo4rs::Graph;
 use serde::{Deserialize, Serialize};
 
+use crate::coin_normalize::{coins_to_units, units_to_coins};
+
 /// A record of a deposit made to the exchange's on-chain address.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Deposit {
     pub account: AccountAddress,
+    /// the deposits in coins, for display
     pub deposited: f64,
+    /// the exact deposits, in current base units
+    #[serde(default)]
+    pub deposited_normalized: u64,
+}
+
+impl Deposit {
+    pub fn new(account: AccountAddress, deposited_normalized: u64) -> Self {
+        Self {
+            account,
+            deposited: units_to_coins(deposited_normalized),
+            deposited_normalized,
+        }
+    }
 }
 
 /// Statistics about the funding requirements of an exchange user.
@@ -40,30 +56,32 @@ pub async fn get_date_range_deposits_alt(
       WITH "0xf57d3968d0bfd5b3120fda88f34310c70bd72033f77422f4407fbbef7c24557a" AS olswap_deposit
 
       // Step 1: Get the list of all depositors
-      MATCH (acc:Account)-[tx:Tx]->(onboard:Account {{address: olswap_deposit}})
+      MATCH (acc:Account)-[tx:Transfer]->(onboard:Account {{address: olswap_deposit}})
       WITH DISTINCT(acc) AS all, olswap_deposit
 
       // Step 2: Match depositors and amounts within the date range
 
 
 
-      MATCH (all)-[tx2:Tx]->(onboard:Account {{address: olswap_deposit}})
+      MATCH (all)-[tx2:Transfer]->(onboard:Account {{address: olswap_deposit}})
       WHERE
         tx2.block_datetime > datetime("{}")
         AND tx2.block_datetime < datetime("{}")
+        AND COALESCE(tx2.status, "Success") = "Success"
 
 
+      // transfers loaded before `coins_normalized` have the amount argument only
       WITH
         DISTINCT (all.address) AS account,
-        COALESCE(SUM(tx2.V7_OlAccountTransfer_amount), 0)/1000000 AS deposit_amount
-      RETURN account, toFloat(deposit_amount) as deposited
-      ORDER BY deposit_amount DESC
+        COALESCE(SUM(COALESCE(tx2.coins_normalized, tx2.V7_OlAccountTransfer_amount)), 0) AS deposit_units
+      RETURN account, deposit_units
+      ORDER BY deposit_units DESC
 
       "#,
         // r#"
         // WITH "0xf57d3968d0bfd5b3120fda88f34310c70bd72033f77422f4407fbbef7c24557a" as exchange_deposit
         // MATCH
-        //   (u:Account)-[tx:Tx]->(onboard:Account {{address: exchange_deposit}})
+        //   (u:Account)-[tx:Transfer]->(onboard:Account {{address: exchange_deposit}})
         // WHERE
         //   tx.`block_datetime` > datetime("{}")
         //   AND tx.`block_datetime` < datetime("{}")
@@ -86,11 +104,11 @@ pub async fn get_date_range_deposits_alt(
     // Fetch the first row only
     while let Some(r) = result.next().await? {
         let account_str = r.get::<String>("account").unwrap_or("unknown".to_string());
-        let deposited = r.get::<f64>("deposited").unwrap_or(0.0);
-        let d = Deposit {
-            account: account_str.parse().unwrap_or(AccountAddress::ZERO),
-            deposited,
-        };
+        let deposited = r.get::<i64>("deposit_units").unwrap_or(0);
+        let d = Deposit::new(
+            account_str.parse().unwrap_or(AccountAddress::ZERO),
+            deposited as u64,
+        );
         top_deposits.push(d);
     }
     Ok(top_deposits)
@@ -352,12 +370,15 @@ impl Matching {
     ) {
         user_list.iter().for_each(|user| {
             let pending = self.pending.entry(user.user_id).or_default();
+            // compare in base units, so equal amounts are equal
+            let funded_units = coins_to_units(user.funded);
+            let max_units = coins_to_units(user.funded * tolerance);
 
             let candidates: Vec<AccountAddress> = deposits
                 .iter()
                 .filter_map(|el| {
-                    if el.deposited > user.funded && // must always be slightly more
-                el.deposited < user.funded * tolerance &&
+                    if el.deposited_normalized > funded_units && // must always be slightly more
+                el.deposited_normalized < max_units &&
                 !pending.impossible.contains(&el.account) &&
                 // is also not already discovered
                 !self.definite.values().any(|found| found == &el.account)
@@ -388,9 +409,10 @@ impl Matching {
         // let mut filtered_depositors = deposits.clone();
         let pending = self.pending.entry(user.user_id).or_default();
 
+        let funded_units = coins_to_units(user.funded);
         let mut eval: Vec<AccountAddress> = vec![];
         deposits.iter().for_each(|el| {
-            if el.deposited >= user.funded &&
+            if el.deposited_normalized >= funded_units &&
             // must not already have been tagged impossible
             !pending.impossible.contains(&el.account) &&
             // is also not already discovered
@@ -1,10 +1,12 @@
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
+
 /// Response metadata for a batch transaction insertion.
 ///
 /// Tracks the number of unique, created, modified, and unchanged accounts,
 /// as well as the total number of transactions created in the batch.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchTxReturn {
     pub unique_accounts: u64,
     pub created_accounts: u64,
@@ -0,0 +1,529 @@
+//! CSV files for `neo4j-admin database import`.
+//!
+//! Loading the whole chain through the batched `MERGE` queries takes days.
+//! The offline importer of Neo4j builds a fresh database from node and
+//! relationship CSVs in minutes, so the initial load of an `ArchiveMap` can
+//! be written to CSVs instead:
+//!
+//! - `accounts.csv`: `Account` nodes, deduplicated across all the archives
+//! - `tx/<archive_id>/<relation>.csv`: a relationship type per relation label
+//! - `lifetime.csv`: `Lifetime` totals of the coins sent between two accounts
+//! - `snapshots/<archive_id>.csv` and `state/<archive_id>.csv`: `Snapshot`
+//!   nodes and the `State` relationships of their accounts
+//! - `queue.csv`: a completed `Queue` node for each exported archive, so a
+//!   later `ingest-all` skips them and only loads new archives with `MERGE`
+//!
+//! The arguments to import them all are written to `import.args`:
+//!
+//! ```text
+//! neo4j-admin database import full neo4j @<out_dir>/import.args
+//! ```
+//!
+//! The importer does not create indexes or constraints. Those are added by
+//! `maybe_create_indexes` on the first ingest after the import.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use diem_crypto::HashValue;
+use libra_types::exports::AccountAddress;
+use log::{error, info, warn};
+use serde_json::{json, Value};
+
+use crate::{
+    extract_snapshot::{stream_current_snapshot, stream_v5_snapshot},
+    extract_transactions::extract_current_transactions,
+    lifetime::{is_success, LifetimeTotal},
+    provenance::Provenance,
+    scan::{ArchiveMap, BundleContent, FrameworkVersion, ManifestInfo},
+    schema_account_state::{WarehouseAccState, WarehouseTime},
+    schema_transaction::WarehouseTxMaster,
+    unzip_temp,
+};
+
+/// Header of the transaction properties every relationship has, in the
+/// order of `TX_COLUMNS`.
+const TX_HEADER: &[&str] = &[
+    ":START_ID(Account)",
+    ":END_ID(Account)",
+    "tx_hash",
+    "version:long",
+    "block_datetime:datetime",
+    "block_timestamp:long",
+    "relation",
+    "function",
+    "framework_version",
+    "sequence_number:long",
+    "gas_used:long",
+    "gas_unit_price:long",
+    "max_gas_amount:long",
+    "status",
+    "coins:double",
+    "coins_raw:long",
+    "coins_normalized:long",
+    "abort_code:long",
+    "abort_location",
+    "payee",
+    "payment_coins_raw:long",
+    "archive_id",
+    "manifest_path",
+    "chunk_sha256",
+];
+
+/// Properties of `WarehouseTxMaster::to_cypher_json` stored on the relationship.
+const TX_COLUMNS: &[&str] = &[
+    "tx_hash",
+    "version",
+    "block_datetime",
+    "block_timestamp",
+    "relation",
+    "function",
+    "framework_version",
+    "sequence_number",
+    "gas_used",
+    "gas_unit_price",
+    "max_gas_amount",
+    "status",
+    "coins",
+    "coins_raw",
+    "coins_normalized",
+    "abort_code",
+    "abort_location",
+    "payee",
+    "payment_coins_raw",
+    "archive_id",
+    "manifest_path",
+    "chunk_sha256",
+];
+
+const SNAPSHOT_HEADER: &[&str] = &[
+    ":ID(Snapshot)",
+    "address",
+    "epoch:long",
+    "version:long",
+    "balance:double",
+    "balance_raw:long",
+    "balance_normalized:long",
+    "framework_version",
+    "sequence_num:long",
+    "slow_wallet:boolean",
+    "donor_voice:boolean",
+    "miner_height:long",
+    "slow_unlocked:double",
+    "slow_transfer:double",
+    "archive_id",
+    "manifest_path",
+    "chunk_sha256",
+];
+
+/// Properties of `WarehouseAccState::acc_state_to_cypher_json`, in the order
+/// of `SNAPSHOT_HEADER` after the id.
+const SNAPSHOT_COLUMNS: &[&str] = &[
+    "address",
+    "epoch",
+    "version",
+    "balance",
+    "balance_raw",
+    "balance_normalized",
+    "framework_version",
+    "sequence_num",
+    "slow_wallet",
+    "donor_voice",
+    "miner_height",
+    "slow_unlocked",
+    "slow_transfer",
+    "archive_id",
+    "manifest_path",
+    "chunk_sha256",
+];
+
+/// Counts of the records written.
+#[derive(Debug, Default, Clone)]
+pub struct BulkImportSummary {
+    pub archives: u64,
+    pub accounts: u64,
+    pub transactions: u64,
+    pub lifetime: u64,
+    pub snapshots: u64,
+}
+
+/// Collects the CSVs of an import, deduplicating records across archives.
+pub struct BulkImportWriter {
+    out_dir: PathBuf,
+    accounts: BTreeSet<String>,
+    /// Totals of the coins sent between two accounts, by `(from, to)`.
+    lifetime: BTreeMap<(String, String), LifetimeTotal>,
+    tx_hashes: HashSet<HashValue>,
+    snapshot_ids: HashSet<(AccountAddress, u64)>,
+    /// `--nodes` and `--relationships` arguments, as `(flag, label, file)`.
+    imports: Vec<(&'static str, String, PathBuf)>,
+    archive_ids: Vec<String>,
+    summary: BulkImportSummary,
+}
+
+impl BulkImportWriter {
+    pub fn new(out_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(out_dir)
+            .context(format!("could not create directory {}", out_dir.display()))?;
+        Ok(Self {
+            out_dir: out_dir.to_owned(),
+            accounts: BTreeSet::new(),
+            lifetime: BTreeMap::new(),
+            tx_hashes: HashSet::new(),
+            snapshot_ids: HashSet::new(),
+            imports: vec![],
+            archive_ids: vec![],
+            summary: BulkImportSummary::default(),
+        })
+    }
+
+    /// Writes the transactions of an archive, one file per relation label.
+    pub fn add_transactions(&mut self, archive_id: &str, txs: &[WarehouseTxMaster]) -> Result<()> {
+        let mut by_relation: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for t in txs {
+            // a tx_hash is a single relationship, like in the MERGE
+            if !self.tx_hashes.insert(t.tx_hash) {
+                continue;
+            }
+            by_relation
+                .entry(t.relation_label.to_cypher_label())
+                .or_default()
+                .push(t.to_cypher_json());
+        }
+
+        for (relation, records) in by_relation {
+            let path = self
+                .out_dir
+                .join("tx")
+                .join(archive_id)
+                .join(format!("{relation}.csv"));
+
+            // the entry function arguments are flattened into properties, so
+            // each file has its own columns for them
+            let arg_columns = infer_arg_columns(&records);
+            let mut header: Vec<String> = TX_HEADER.iter().map(|h| h.to_string()).collect();
+            header.extend(arg_columns.iter().map(|(key, t)| format!("{key}:{t}")));
+
+            let mut rows = vec![];
+            for r in &records {
+                let from = r["sender"].as_str().context("no sender field")?.to_string();
+                let recipients = r["recipients"]
+                    .as_array()
+                    .context("no recipients field")?
+                    .iter()
+                    .map(|a| a.as_str().map(|s| s.to_string()))
+                    .collect::<Option<Vec<_>>>()
+                    .context("recipient is not a string")?;
+
+                // an edge to each recipient, like the Cypher template
+                for to in recipients {
+                    // failed transactions moved no coins
+                    let coins = r["coins"]
+                        .as_f64()
+                        .filter(|_| is_success(r["status"].as_str()));
+                    if let Some(coins) = coins {
+                        let seen = r["block_datetime"]
+                            .as_str()
+                            .context("no block_datetime field")?;
+                        self.lifetime
+                            .entry((from.clone(), to.clone()))
+                            .or_default()
+                            .add(
+                                coins,
+                                r["coins_normalized"].as_u64(),
+                                DateTime::parse_from_rfc3339(seen)?.with_timezone(&Utc),
+                            );
+                    }
+
+                    let mut row = vec![csv_field(&Value::String(from.clone()))];
+                    row.push(csv_field(&Value::String(to.clone())));
+                    row.extend(TX_COLUMNS.iter().map(|c| csv_field(&r[*c])));
+                    row.extend(
+                        arg_columns
+                            .iter()
+                            .map(|(key, _)| csv_field(&r["args"][key])),
+                    );
+                    rows.push(row);
+
+                    self.accounts.insert(to);
+                }
+                self.accounts.insert(from);
+            }
+
+            write_csv(&path, &header, &rows)?;
+            self.summary.transactions += rows.len() as u64;
+            self.imports.push(("--relationships", relation, path));
+        }
+
+        self.archive_ids.push(archive_id.to_string());
+        self.summary.archives += 1;
+        Ok(())
+    }
+
+    /// Writes the snapshots of an archive, and the `State` relationships of
+    /// their accounts.
+    pub fn add_snapshots(
+        &mut self,
+        archive_id: &str,
+        time: &WarehouseTime,
+        provenance: Option<&Provenance>,
+        snaps: &[WarehouseAccState],
+    ) -> Result<()> {
+        let mut snapshot_rows = vec![];
+        let mut state_rows = vec![];
+        for s in snaps {
+            if !self.snapshot_ids.insert((s.address, time.version)) {
+                continue;
+            }
+            let r = s.to_record_json(time, provenance);
+            let address = s.address.to_hex_literal();
+            let id = Value::String(format!("{}-{}", address, time.version));
+
+            let mut row = vec![csv_field(&id)];
+            row.extend(SNAPSHOT_COLUMNS.iter().map(|c| csv_field(&r[*c])));
+            snapshot_rows.push(row);
+
+            state_rows.push(vec![
+                csv_field(&Value::String(address.clone())),
+                csv_field(&id),
+                time.version.to_string(),
+                csv_field(&r["archive_id"]),
+                csv_field(&r["manifest_path"]),
+                csv_field(&r["chunk_sha256"]),
+            ]);
+            self.accounts.insert(address);
+        }
+
+        let header: Vec<String> = SNAPSHOT_HEADER.iter().map(|h| h.to_string()).collect();
+        let snapshot_path = self
+            .out_dir
+            .join("snapshots")
+            .join(format!("{archive_id}.csv"));
+        write_csv(&snapshot_path, &header, &snapshot_rows)?;
+        self.imports
+            .push(("--nodes", "Snapshot".to_string(), snapshot_path));
+
+        let header: Vec<String> = [
+            ":START_ID(Account)",
+            ":END_ID(Snapshot)",
+            "version:long",
+            "archive_id",
+            "manifest_path",
+            "chunk_sha256",
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+        let state_path = self.out_dir.join("state").join(format!("{archive_id}.csv"));
+        write_csv(&state_path, &header, &state_rows)?;
+        self.imports
+            .push(("--relationships", "State".to_string(), state_path));
+
+        self.summary.snapshots += snapshot_rows.len() as u64;
+        self.archive_ids.push(archive_id.to_string());
+        self.summary.archives += 1;
+        Ok(())
+    }
+
+    /// Writes the accounts, lifetime totals, queue and `import.args`, which
+    /// depend on all the archives.
+    pub fn finish(mut self) -> Result<BulkImportSummary> {
+        let accounts_path = self.out_dir.join("accounts.csv");
+        let rows: Vec<Vec<String>> = self
+            .accounts
+            .iter()
+            .map(|a| vec![csv_field(&Value::String(a.clone()))])
+            .collect();
+        write_csv(&accounts_path, &["address:ID(Account)".to_string()], &rows)?;
+        self.summary.accounts = rows.len() as u64;
+
+        let lifetime_path = self.out_dir.join("lifetime.csv");
+        let header: Vec<String> = [
+            ":START_ID(Account)",
+            ":END_ID(Account)",
+            "coins:double",
+            "coins_normalized:long",
+            "tx_count:long",
+            "first_seen:datetime",
+            "last_seen:datetime",
+            "min_coins:double",
+            "max_coins:double",
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+        let rows: Vec<Vec<String>> = self
+            .lifetime
+            .iter()
+            .map(|((from, to), total)| {
+                vec![
+                    csv_field(&Value::String(from.clone())),
+                    csv_field(&Value::String(to.clone())),
+                    total.coins.to_string(),
+                    csv_field(&json!(total.coins_normalized)),
+                    total.tx_count.to_string(),
+                    csv_field(&json!(total.first_seen.map(|t| t.to_rfc3339()))),
+                    csv_field(&json!(total.last_seen.map(|t| t.to_rfc3339()))),
+                    csv_field(&json!(total.min_coins)),
+                    csv_field(&json!(total.max_coins)),
+                ]
+            })
+            .collect();
+        write_csv(&lifetime_path, &header, &rows)?;
+        self.summary.lifetime = rows.len() as u64;
+
+        let queue_path = self.out_dir.join("queue.csv");
+        let header: Vec<String> = ["archive_id", "batch:long", "completed:boolean"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+        let rows: Vec<Vec<String>> = self
+            .archive_ids
+            .iter()
+            .map(|id| {
+                vec![
+                    csv_field(&Value::String(id.clone())),
+                    "0".to_string(),
+                    "true".to_string(),
+                ]
+            })
+            .collect();
+        write_csv(&queue_path, &header, &rows)?;
+
+        let mut args = vec![
+            format!("--nodes=Account={}", accounts_path.display()),
+            format!("--nodes=Queue={}", queue_path.display()),
+            format!("--relationships=Lifetime={}", lifetime_path.display()),
+        ];
+        for (flag, label, path) in &self.imports {
+            args.push(format!("{flag}={label}={}", path.display()));
+        }
+        args.push("--multiline-fields=true".to_string());
+        args.push("--skip-duplicate-nodes=true".to_string());
+        fs::write(self.out_dir.join("import.args"), args.join("\n") + "\n")?;
+
+        Ok(self.summary)
+    }
+}
+
+/// Exports the transaction and snapshot archives of a map to CSVs.
+/// Failed transactions are skipped unless `include_failed` is set.
+pub async fn export_archive_map(
+    archive_map: &ArchiveMap,
+    out_dir: &Path,
+    include_failed: bool,
+) -> Result<BulkImportSummary> {
+    let mut writer = BulkImportWriter::new(out_dir)?;
+
+    for (_p, m) in archive_map.0.iter() {
+        info!("exporting archive: {}", m.archive_dir.display());
+        let (new_unzip_path, temp) = unzip_temp::maybe_handle_gz(&m.archive_dir)?;
+        let mut man = ManifestInfo::new(&new_unzip_path);
+        man.set_info()?;
+
+        match man.contents {
+            BundleContent::Transaction => {
+                let (txs, _, _) =
+                    extract_current_transactions(&man.archive_dir, &man.version, include_failed)
+                        .await?;
+                writer.add_transactions(&man.archive_id, &txs)?;
+            }
+            BundleContent::StateSnapshot => {
+                let stream = match man.version {
+                    FrameworkVersion::Unknown => {
+                        error!("no framework version detected");
+                        bail!("could not export archive from manifest");
+                    }
+                    FrameworkVersion::V5 => stream_v5_snapshot(&man.archive_dir).await?,
+                    FrameworkVersion::V6 | FrameworkVersion::V7 => {
+                        stream_current_snapshot(&man.archive_dir).await?
+                    }
+                };
+                let time = stream.time.clone();
+                for batch in stream.collect_all().await? {
+                    writer.add_snapshots(
+                        &man.archive_id,
+                        &time,
+                        batch.provenance.as_deref(),
+                        &batch.accounts,
+                    )?;
+                }
+            }
+            _ => {
+                warn!(
+                    "no bulk import for {:?} archives, load {} with ingest-one",
+                    man.contents,
+                    man.archive_dir.display()
+                );
+            }
+        }
+        drop(temp);
+    }
+
+    writer.finish()
+}
+
+/// The `neo4j-admin` column type of each entry function argument in a list
+/// of transaction records. Mixed or nested values are written as strings.
+fn infer_arg_columns(records: &[Value]) -> Vec<(String, &'static str)> {
+    let mut types: BTreeMap<String, &'static str> = BTreeMap::new();
+    for r in records {
+        let Some(args) = r["args"].as_object() else {
+            continue;
+        };
+        for (key, value) in args {
+            // the transaction properties take precedence
+            if TX_COLUMNS.contains(&key.as_str()) || value.is_null() {
+                continue;
+            }
+            let this_type = match value {
+                Value::Bool(_) => "boolean",
+                Value::Number(n) if n.is_i64() => "long",
+                Value::Number(_) => "double",
+                _ => "string",
+            };
+            let entry = types.entry(key.clone()).or_insert(this_type);
+            *entry = match (*entry, this_type) {
+                (a, b) if a == b => a,
+                ("long", "double") | ("double", "long") => "double",
+                _ => "string",
+            };
+        }
+    }
+    types.into_iter().collect()
+}
+
+/// Formats a value as a CSV field. Strings are always quoted, and nulls are
+/// left empty, so the property is not set.
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => quote(s),
+        other => quote(&other.to_string()),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+fn write_csv(path: &Path, header: &[String], rows: &[Vec<String>]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context(format!("could not create directory {}", dir.display()))?;
+    }
+    let file = File::create(path).context(format!("could not create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "{}", header.join(","))?;
+    for row in rows {
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
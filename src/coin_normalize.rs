@@ -0,0 +1,123 @@
+//! Coin amounts across framework versions.
+//!
+//! Amounts are read from the chain in the base units of their framework
+//! version. The V6 upgrade rebased the coin, so one V5 base unit is worth
+//! `LEGACY_REBASE_MULTIPLIER` V6 base units; V6 and V7 share their units.
+//!
+//! Records carry the amount as read (`coins_raw`, `balance_raw`), and in
+//! current base units (`coins_normalized`, `balance_normalized`). The float
+//! in coins, e.g. `coins` or `balance`, is of the normalized amount and only
+//! for display. So the amounts of any version can be compared and summed.
+use anyhow::{Context, Result};
+use log::info;
+use neo4rs::{query, Graph};
+use serde::{Deserialize, Serialize};
+
+use crate::scan::FrameworkVersion;
+
+/// Base units per coin.
+pub const COIN_DECIMAL_PRECISION: u64 = 1_000_000;
+
+/// Current base units per V5 base unit.
+pub const LEGACY_REBASE_MULTIPLIER: u64 = 35;
+
+/// Current base units per base unit of a framework version. Records of an
+/// unknown version are assumed to be current.
+pub fn rebase_multiplier(version: &FrameworkVersion) -> u64 {
+    match version {
+        FrameworkVersion::V5 => LEGACY_REBASE_MULTIPLIER,
+        FrameworkVersion::V6 | FrameworkVersion::V7 | FrameworkVersion::Unknown => 1,
+    }
+}
+
+/// An amount as read from the chain, and in current base units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoinAmount {
+    pub raw: u64,
+    pub normalized: u64,
+}
+
+impl CoinAmount {
+    /// Normalizes an amount in the base units of `version`.
+    pub fn new(raw: u64, version: &FrameworkVersion) -> Self {
+        Self {
+            raw,
+            normalized: raw.saturating_mul(rebase_multiplier(version)),
+        }
+    }
+
+    /// The normalized amount in coins, for display.
+    pub fn coins(&self) -> f64 {
+        units_to_coins(self.normalized)
+    }
+}
+
+/// Converts whole coins, e.g. the `unscaled_value` of a V5 script, to base
+/// units of the same version.
+pub fn scale_whole_coins(coins: u64) -> u64 {
+    coins.saturating_mul(COIN_DECIMAL_PRECISION)
+}
+
+/// Converts base units to coins.
+pub fn units_to_coins(units: u64) -> f64 {
+    units as f64 / COIN_DECIMAL_PRECISION as f64
+}
+
+/// Converts coins to base units, rounded to the nearest unit.
+pub fn coins_to_units(coins: f64) -> u64 {
+    (coins * COIN_DECIMAL_PRECISION as f64).round() as u64
+}
+
+/// Normalizes the balances of snapshots without a `balance_normalized`.
+/// Snapshots loaded before this module stored V5 balances in V5 units, and
+/// may have no `balance_raw`, which is recovered from the six decimals of
+/// the balance.
+pub fn cypher_normalize_snapshots_str() -> String {
+    r#"
+MATCH (s:Snapshot)
+WHERE s.balance_normalized IS NULL AND s.balance IS NOT NULL
+WITH s, CASE WHEN s.framework_version = "V5" THEN $multiplier ELSE 1 END AS multiplier
+LIMIT $limit
+SET
+  s.balance_raw = COALESCE(s.balance_raw, toInteger(round(s.balance * $precision))),
+  s.balance = s.balance * multiplier,
+  s.slow_unlocked = s.slow_unlocked * multiplier,
+  s.slow_transfer = s.slow_transfer * multiplier
+SET s.balance_normalized = s.balance_raw * multiplier
+RETURN count(s) AS updated
+"#
+    .to_string()
+}
+
+/// Runs a normalization query until no records are left. Returns the
+/// number of records updated.
+async fn normalize_in_batches(pool: &Graph, cypher: &str, batch_size: usize) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        let mut res = pool
+            .execute(
+                query(cypher)
+                    .param("limit", batch_size as i64)
+                    .param("multiplier", LEGACY_REBASE_MULTIPLIER as i64)
+                    .param("precision", COIN_DECIMAL_PRECISION as f64),
+            )
+            .await
+            .context("execute query error")?;
+        let row = res.next().await?.context("no row returned")?;
+        let updated = row.get::<i64>("updated").context("no updated field")? as u64;
+        if updated == 0 {
+            break;
+        }
+        total += updated;
+        info!("records normalized: {}", total);
+    }
+    Ok(total)
+}
+
+/// Normalizes the snapshot balances of a graph loaded before snapshots
+/// carried both amounts. Transactions need no migration: their `coins` were
+/// always normalized, and edges loaded before `coins_raw` have no integers
+/// to normalize. Returns the number of snapshots updated.
+pub async fn normalize_graph_coins(pool: &Graph, batch_size: usize) -> Result<u64> {
+    normalize_in_batches(pool, &cypher_normalize_snapshots_str(), batch_size).await
+}
@@ -1,40 +1,101 @@
 //! organic free trade template literals for cypher queries
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use neo4rs::{
+    query, BoltBoolean, BoltFloat, BoltInteger, BoltList, BoltMap, BoltNull, BoltString, BoltType,
+    Graph, Row,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::provenance::cypher_add_archive_id;
 
 /// Generates a Cypher query string for batch transaction submission.
 ///
-/// Takes a string representation of a list of transaction objects and unwinds it
-/// to perform multiple `MERGE` operations for accounts and relationships.
-pub fn write_batch_tx_string(list_str: &str) -> String {
+/// Unwinds the list of transaction objects passed as `$batch` to perform
+/// multiple `MERGE` operations for accounts and relationships.
+/// Relationship types cannot be set dynamically in Cypher, so all the
+/// transactions in the list must share the `relation_type`, which is one of
+/// `RelationLabel::to_cypher_label`. A transaction gets an edge to each of
+/// its `recipients`.
+pub fn write_batch_tx_string(relation_type: &str) -> String {
+    let archive_ids = cypher_add_archive_id("rel", "tx.archive_id");
     format!(
         r#"
-WITH {list_str} AS tx_data
-UNWIND tx_data AS tx
+UNWIND $batch AS tx
+UNWIND tx.recipients AS recipient
 
 // NOTE: users should have already been merged in a previous call
 MERGE (from:Account {{address: tx.sender}})
-MERGE (to:Account {{address: tx.recipient}})
-MERGE (from)-[rel:Tx {{tx_hash: tx.tx_hash}}]->(to)
+MERGE (to:Account {{address: recipient}})
+MERGE (from)-[rel:{relation_type} {{tx_hash: tx.tx_hash}}]->(to)
 
 ON CREATE SET rel.cypher_created_at = timestamp(), rel.cypher_modified_at = null
 ON MATCH SET rel.cypher_modified_at = timestamp()
 SET
-    rel.block_datetime = tx.block_datetime,
+    {archive_ids},
+    rel.version = tx.version,
+    rel.block_datetime = datetime(tx.block_datetime),
     rel.block_timestamp = tx.block_timestamp,
     rel.relation = tx.relation,
     rel.function = tx.function,
-    rel.framework_version = tx.framework_version
+    rel.framework_version = tx.framework_version,
+    rel.sequence_number = tx.sequence_number,
+    rel.gas_used = tx.gas_used,
+    rel.gas_unit_price = tx.gas_unit_price,
+    rel.max_gas_amount = tx.max_gas_amount,
+    rel.status = tx.status,
+    rel.archive_id = tx.archive_id,
+    rel.manifest_path = tx.manifest_path,
+    rel.chunk_sha256 = tx.chunk_sha256
 
 // Conditionally add `tx.args` if it exists
 FOREACH (_ IN CASE WHEN tx.args IS NOT NULL THEN [1] ELSE [] END |
     SET rel += tx.args
 )
 
-// Conditionally increment the lifetime coins sent
+// Conditionally add the abort details of failed transactions
+FOREACH (_ IN CASE WHEN tx.abort_code IS NOT NULL THEN [1] ELSE [] END |
+    SET rel.abort_code = tx.abort_code, rel.abort_location = tx.abort_location
+)
+
+// Conditionally add the payee and amount of proposed donor voice payments
+FOREACH (_ IN CASE WHEN tx.payee IS NOT NULL THEN [1] ELSE [] END |
+    SET rel.payee = tx.payee, rel.payment_coins_raw = tx.payment_coins_raw
+)
+
 FOREACH (_ IN CASE WHEN tx.coins > 0 THEN [1] ELSE [] END |
-    SET rel.coins = tx.coins
+    SET
+      rel.coins = tx.coins,
+      rel.coins_raw = tx.coins_raw,
+      rel.coins_normalized = tx.coins_normalized
+)
+
+// Add new successful transactions to the lifetime totals. An edge which was
+// only MATCHed, e.g. when an archive is loaded again, was counted already.
+// The exact `coins_normalized` is only started on a new total, which has no
+// `coins` yet. It stays null on totals which include transactions loaded
+// without it, including totals written before `tx_count` was counted.
+FOREACH (_ IN CASE WHEN tx.coins > 0 AND tx.status = "Success" AND rel.cypher_created_at = timestamp() THEN [1] ELSE [] END |
     MERGE (from)-[relTotal:Lifetime]->(to)
-    SET relTotal.coins = COALESCE(relTotal.coins, 0) + tx.coins
+    SET
+      relTotal.coins_normalized = CASE
+        WHEN relTotal.coins IS NULL AND relTotal.tx_count IS NULL THEN tx.coins_normalized
+        WHEN relTotal.coins_normalized IS NULL THEN null
+        ELSE relTotal.coins_normalized + tx.coins_normalized END,
+      relTotal.coins = COALESCE(relTotal.coins, 0) + tx.coins,
+      relTotal.tx_count = COALESCE(relTotal.tx_count, 0) + 1,
+      relTotal.first_seen = CASE
+        WHEN relTotal.first_seen IS NULL OR rel.block_datetime < relTotal.first_seen
+        THEN rel.block_datetime ELSE relTotal.first_seen END,
+      relTotal.last_seen = CASE
+        WHEN relTotal.last_seen IS NULL OR rel.block_datetime > relTotal.last_seen
+        THEN rel.block_datetime ELSE relTotal.last_seen END,
+      relTotal.min_coins = CASE
+        WHEN relTotal.min_coins IS NULL OR tx.coins < relTotal.min_coins
+        THEN tx.coins ELSE relTotal.min_coins END,
+      relTotal.max_coins = CASE
+        WHEN relTotal.max_coins IS NULL OR tx.coins > relTotal.max_coins
+        THEN tx.coins ELSE relTotal.max_coins END
 )
 
 RETURN
@@ -44,166 +105,22 @@ RETURN
     )
 }
 
-// // TODO move this to a .CQL file so we can lint and debug
-// pub fn write_batch_tx_string(list_str: &str) -> String {
-//     format!(
-//         r#"
-// WITH {list_str} AS tx_data
-// UNWIND tx_data AS tx
-
-// // Ensure accounts exist
-// MERGE (from:Account {{address: tx.sender}})
-// MERGE (to:Account {{address: tx.recipient}})
-
-// // Dynamically set the relationship label using a subquery
-// WITH from, to, tx
-// CALL {{
-//     // Conditionally create the appropriate relationship
-//     FOREACH (_ IN CASE WHEN tx.relation = "Transfer" THEN [1] ELSE [] END |
-//         MERGE (from)-[rel:Transfer {tx_hash: tx.tx_hash}]->(to)
-//         ON CREATE SET
-//         rel.cypher_created_at = timestamp(),
-//         rel.cypher_modified_at = null
-//     ON MATCH SET
-//         rel.cypher_modified_at = timestamp()
-//     SET
-//         rel.block_datetime = tx.block_datetime,
-//         rel.block_timestamp = tx.block_timestamp,
-//         rel.function = tx.function
-//     )
-//     FOREACH (_ IN CASE WHEN tx.relation = "Onboarding" THEN [1] ELSE [] END |
-//         MERGE (from)-[rel:Onboarding {tx_hash: tx.tx_hash}]->(to)
-//         ON CREATE SET
-//         rel.cypher_created_at = timestamp(),
-//         rel.cypher_modified_at = null
-//     ON MATCH SET
-//         rel.cypher_modified_at = timestamp()
-//     SET
-//         rel.block_datetime = tx.block_datetime,
-//         rel.block_timestamp = tx.block_timestamp,
-//         rel.function = tx.function
-//     )
-//     FOREACH (_ IN CASE WHEN tx.relation = "Vouch" THEN [1] ELSE [] END |
-//         MERGE (from)-[rel:Vouch {tx_hash: tx.tx_hash}]->(to)
-//         ON CREATE SET
-//         rel.cypher_created_at = timestamp(),
-//         rel.cypher_modified_at = null
-//     ON MATCH SET
-//         rel.cypher_modified_at = timestamp()
-//     SET
-//         rel.block_datetime = tx.block_datetime,
-//         rel.block_timestamp = tx.block_timestamp,
-//         rel.function = tx.function
-//     )
-//     FOREACH (_ IN CASE WHEN tx.relation IS NULL OR NOT tx.relation IN ["Transfer", "Onboarding", "Vouch"] THEN [1] ELSE [] END |
-//         MERGE (from)-[rel:Misc {tx_hash: tx.tx_hash}]->(to)
-//         ON CREATE SET
-//         rel.cypher_created_at = timestamp(),
-//         rel.cypher_modified_at = null
-//     ON MATCH SET
-//         rel.cypher_modified_at = timestamp()
-//     SET
-//         rel.block_datetime = tx.block_datetime,
-//         rel.block_timestamp = tx.block_timestamp,
-//         rel.function = tx.function
-//         CASE
-//           WHEN tx.args IS NOT NULL THEN
-//             SET rel += tx.args
-//         END
-
-//     )
-// }}
-
-// // // Conditionally add `tx.args` if it exists
-// // FOREACH (_ IN CASE WHEN tx.args IS NOT NULL THEN [1] ELSE [] END |
-// //     SET rel += tx.args
-// // )
-
-// // Increment the cumulative Lifetime edge if `tx.amount > 0`
-// FOREACH (_ IN CASE WHEN tx.amount > 0 THEN [1] ELSE [] END |
-//     MERGE (from)-[rl:Lifetime]->(to)
-//     SET rl.coins_tx = COALESCE(rl.amount, 0) + tx.amount
-// )
-
-// // Final return with counts
-// RETURN
-//   COUNT(CASE WHEN rel.cypher_created_at = timestamp() THEN 1 END) AS created_tx,
-//   COUNT(CASE WHEN rel.cypher_modified_at = timestamp() AND rel.created_at < timestamp() THEN 1 END) AS modified_tx
-// "#
-//     )
-// }
-
-// // TODO move this to a .CQL file so we can lint and debug
-// pub fn write_batch_tx_string(list_str: &str) -> String {
-//     format!(
-//         r#"
-// WITH {list_str} AS tx_data
-// UNWIND tx_data AS tx
-
-// // Ensure accounts exist
-// MERGE (from:Account {{address: tx.sender}})
-// MERGE (to:Account {{address: tx.recipient}})
-
-// // Dynamically set the relationship label using a subquery
-// WITH from, to, tx
-// CALL {{
-//     WITH tx
-//     RETURN CASE
-//         WHEN tx.relation = "Tx" THEN "Tx"
-//         WHEN tx.relation = "Onboarding" THEN "Vouch"
-//         WHEN tx.relation = "Vouch" THEN "Vouch"
-//         ELSE "Unknown" // Default for unexpected or missing values
-//     END AS dynamicLabel
-// }}
-// WITH from, to, tx, dynamicLabel
-// // Use dynamicLabel to create the relationship
-// MERGE (from)-[rel:`${{dynamicLabel}}` {{tx_hash: tx.tx_hash}}]->(to)
-// ON CREATE SET
-//     rel.cypher_created_at = timestamp(),
-//     rel.cypher_modified_at = null
-// ON MATCH SET
-//     rel.cypher_modified_at = timestamp()
-// SET
-//     rel.block_datetime = tx.block_datetime,
-//     rel.block_timestamp = tx.block_timestamp,
-//     rel.function = tx.function
-
-// // Conditionally add `tx.args` if it exists
-// FOREACH (_ IN CASE WHEN tx.args IS NOT NULL THEN [1] ELSE [] END |
-//     SET rel += tx.args
-// )
-
-// // Increment the cumulative Lifetime edge if `tx.amount > 0`
-// FOREACH (_ IN CASE WHEN tx.amount > 0 THEN [1] ELSE [] END |
-//     MERGE (from)-[rl:Lifetime]->(to)
-//     SET rl.coins_tx = COALESCE(rl.amount, 0) + tx.amount
-// )
-
-// // Final return with counts
-// RETURN
-//   COUNT(CASE WHEN rel.cypher_created_at = timestamp() THEN 1 END) AS created_tx,
-//   COUNT(CASE WHEN rel.cypher_modified_at = timestamp() AND rel.created_at < timestamp() THEN 1 END) AS modified_tx
-// "#
-//     )
-// }
-
 /// Generates a Cypher query string for batch account creation.
 ///
-/// Deduplicates addresses from a transaction list and performs `MERGE` operations
-/// to ensure all involved accounts exist in the database.
-pub fn write_batch_user_create(list_str: &str) -> String {
-    format!(
-        r#"
-WITH {list_str} AS tx_data
-UNWIND tx_data AS tx
-WITH COLLECT(DISTINCT tx.sender) + COLLECT(DISTINCT tx.recipient) AS unique_addresses
+/// Deduplicates addresses from the transaction list passed as `$batch` and
+/// performs `MERGE` operations to ensure all involved accounts exist in the database.
+pub fn write_batch_user_create() -> String {
+    r#"
+UNWIND $batch AS tx
+UNWIND tx.recipients AS recipient
+WITH COLLECT(DISTINCT tx.sender) + COLLECT(DISTINCT recipient) AS unique_addresses
 // Deduplicate the combined list to ensure only unique addresses
 UNWIND unique_addresses AS each_addr
 WITH COLLECT(DISTINCT each_addr) as unique_array
 
 UNWIND unique_array AS addr
 // Merge unique Accounts
-MERGE (node:Account {{address: addr}})
+MERGE (node:Account {address: addr})
 ON CREATE SET
     node.cypher_created_at = timestamp(),
     node.cypher_modified_at = null
@@ -216,71 +133,96 @@ RETURN
   COUNT(CASE WHEN node.cypher_modified_at = timestamp() AND node.cypher_created_at < timestamp() THEN 1 END) AS modified_accounts,
   COUNT(CASE WHEN node.cypher_modified_at < timestamp() THEN 1 END) AS unchanged_accounts
 "#
-    )
+    .to_string()
 }
 
-use log::warn;
-use serde::Serialize;
-use serde_json::Value;
+/// Runs a batch write query, with the records bound to the `$batch` parameter.
+///
+/// The query is expected to `UNWIND $batch` and return a single row of
+/// counts. Passing the records as a parameter rather than formatting them
+/// into the query keeps quotes in the data from breaking the query, and lets
+/// the server cache the query plan.
+pub async fn execute_batch(pool: &Graph, cypher_string: &str, batch: BoltType) -> Result<Row> {
+    execute_batch_with(pool, cypher_string, batch, vec![]).await
+}
+
+/// Runs a batch write query like `execute_batch`, with more parameters which
+/// are shared by all the records of the batch.
+pub async fn execute_batch_with(
+    pool: &Graph,
+    cypher_string: &str,
+    batch: BoltType,
+    params: Vec<(&str, BoltType)>,
+) -> Result<Row> {
+    let mut cypher_query = query(cypher_string).param("batch", batch);
+    for (key, value) in params {
+        cypher_query = cypher_query.param(key, value);
+    }
+    let mut res = pool
+        .execute(cypher_query)
+        .await
+        .context("execute query error")?;
 
-/// Converts a serializable struct to a Cypher-compatible object string,
-/// handling nested objects, arrays, and basic types.
+    res.next().await?.context("no row returned")
+}
+
+/// Converts a JSON value into a Bolt value, for use as a query parameter.
 ///
-/// # Arguments
-/// - `object`: The serializable struct.
+/// Bolt integers are signed, so a `u64` beyond the `i64` range is passed as
+/// a string instead.
+pub fn json_to_bolt(value: &Value) -> BoltType {
+    match value {
+        Value::Null => BoltType::Null(BoltNull),
+        Value::Bool(b) => BoltType::Boolean(BoltBoolean::new(*b)),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                BoltType::Integer(BoltInteger::new(i))
+            } else if n.is_f64() {
+                BoltType::Float(BoltFloat::new(n.as_f64().unwrap_or_default()))
+            } else {
+                BoltType::String(BoltString::new(&n.to_string()))
+            }
+        }
+        Value::String(s) => BoltType::String(BoltString::new(s)),
+        Value::Array(arr) => BoltType::List(BoltList::from(
+            arr.iter().map(json_to_bolt).collect::<Vec<BoltType>>(),
+        )),
+        Value::Object(map) => {
+            let mut bolt_map = BoltMap::new();
+            for (k, v) in map {
+                bolt_map.put(BoltString::new(k), json_to_bolt(v));
+            }
+            BoltType::Map(bolt_map)
+        }
+    }
+}
+
+/// Converts a list of JSON objects into a Bolt list for the `$batch` parameter.
+pub fn json_list_to_bolt(list: &[Value]) -> BoltType {
+    BoltType::List(BoltList::from(
+        list.iter().map(json_to_bolt).collect::<Vec<BoltType>>(),
+    ))
+}
+
+/// Converts a serializable struct to a flat JSON object, suitable as a map of
+/// relationship or node properties.
 ///
-/// # Returns
-/// A string in the format `{key: value, nested: {key2: value2}, array: [value3, value4]}` that can be used in Cypher queries.
-///  Thanks Copilot ;)
-pub fn to_cypher_object<T: Serialize>(object: &T) -> Result<String> {
+/// Nested objects are flattened, joining the keys with `_`, since property
+/// values can only be primitives or lists of primitives.
+pub fn to_cypher_object<T: Serialize>(object: &T) -> Result<Value> {
     // Serialize the struct to a JSON value
-    let serialized_value = serde_json::to_value(object).expect("Failed to serialize");
+    let serialized_value = serde_json::to_value(object).context("failed to serialize")?;
 
     let flattener = smooth_json::Flattener {
         separator: "_",
         ..Default::default()
     };
 
-    // Convert the JSON value into a map for easy processing
     let flat = flattener.flatten(&serialized_value);
-    let map = flat.as_object().context("cannot map on json object")?;
-    // Build properties part of the Cypher object
-    let properties: Vec<String> = map
-        .into_iter()
-        .map(|(key, value)| {
-            let formatted_value = match value {
-                Value::String(s) => format!("'{}'", s), // Wrap strings in single quotes
-                Value::Number(n) => n.to_string(),      // Use numbers as-is
-                Value::Bool(b) => b.to_string(),        // Use booleans as-is
-                Value::Null => "null".to_string(),      // Represent null values
-                Value::Array(arr) => {
-                    // Handle arrays by formatting each element
-                    let elements: Vec<String> = arr
-                        .iter()
-                        .map(|elem| match elem {
-                            Value::String(s) => format!("'{}'", s),
-                            Value::Number(n) => n.to_string(),
-                            Value::Bool(b) => b.to_string(),
-                            Value::Null => "null".to_string(),
-                            Value::Object(_) => {
-                                to_cypher_object(elem).unwrap_or("error".to_owned())
-                            } // Recurse for nested objects in arrays
-                            _ => "Unsupported type in array for Cypher serialization".to_string(),
-                        })
-                        .collect();
-                    format!("[{}]", elements.join(", "))
-                }
-                Value::Object(_) => {
-                    warn!("the json should have been flattened before this");
-                    "recursive object error".to_string()
-                }
-            };
-            format!("{}: {}", key, formatted_value)
-        })
-        .collect();
-
-    // Join properties with commas and wrap in curly braces to form a Cypher-compatible object
-    Ok(format!("{{{}}}", properties.join(", ")))
+    if !flat.is_object() {
+        bail!("cannot map on json object");
+    }
+    Ok(flat)
 }
 
 #[test]
@@ -321,7 +263,17 @@ fn test_serialize_to_cypher_object() {
         },
     };
 
-    // Serialize to a Cypher object
+    // Serialize to a flat Cypher object
     let cypher_object = to_cypher_object(&person).unwrap();
     println!("{}", cypher_object);
+    assert!(cypher_object["name"] == "Alice");
+    assert!(cypher_object["address_city"] == "Wonderland");
+    assert!(cypher_object["hobbies"].as_array().unwrap().len() == 3);
+
+    // the Bolt map keeps the flat keys
+    if let BoltType::Map(m) = json_to_bolt(&cypher_object) {
+        assert!(m.value.contains_key(&BoltString::new("address_zip")));
+    } else {
+        panic!("not a map");
+    }
 }
@@ -0,0 +1,433 @@
+//! Integrity checks of a Neo4j graph, e.g. after a crashed load.
+//!
+//! `db doctor` runs each check and prints the problems it finds, with a few
+//! examples. With `--repair`, the problems which can be fixed without a
+//! reload are fixed:
+//!
+//! - missing indexes and constraints are created, and failed ones rebuilt
+//! - transaction edges without a `tx_hash` are deleted, since they can't be
+//!   matched to a transaction; a reload of their archive writes them again
+//! - `Snapshot` nodes without a `State` edge are linked to their account
+//! - `Lifetime` totals which disagree with their transactions are rebuilt
+//! - `SwapAccount` nodes with the same `swap_id` are merged into one
+//! - stuck `Queue` batches are marked as dead letters, so `queue list-failed`
+//!   shows them
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use neo4rs::{query, Graph, Query, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph_migrations::{all_schema_statements, run_schema_statements, schema_statement_name},
+    lifetime::{lifetime_relation_types_cypher, rebuild_lifetime_of, tx_relation_types_cypher},
+};
+
+/// Examples of the problems listed per check.
+const MAX_DETAILS: usize = 10;
+
+/// The outcome of one check.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub problems: u64,
+    pub repaired: u64,
+    /// a few of the problems found
+    pub details: Vec<String>,
+}
+
+impl DoctorCheck {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The checks of a `db doctor` run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    /// Problems which were found and not repaired.
+    pub fn unresolved(&self) -> u64 {
+        self.checks
+            .iter()
+            .map(|c| c.problems.saturating_sub(c.repaired))
+            .sum()
+    }
+}
+
+/// Runs all the checks, repairing what can be repaired with `repair`.
+/// Batches incomplete for more than `stuck_hours` are stuck.
+pub async fn run_doctor(pool: &Graph, repair: bool, stuck_hours: u64) -> Result<DoctorReport> {
+    // edges are deleted before the lifetime totals are checked, so the
+    // rebuilt totals don't count them
+    let checks = vec![
+        check_schema(pool, repair).await?,
+        check_tx_hashes(pool, repair).await?,
+        check_orphan_snapshots(pool, repair).await?,
+        check_lifetime(pool, repair).await?,
+        check_swap_accounts(pool, repair).await?,
+        check_stuck_queue(pool, repair, stuck_hours).await?,
+    ];
+    Ok(DoctorReport { checks })
+}
+
+async fn fetch_rows(pool: &Graph, q: Query) -> Result<Vec<Row>> {
+    let mut res = pool.execute(q).await.context("execute query error")?;
+    let mut rows = vec![];
+    while let Some(row) = res.next().await? {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+async fn fetch_count(pool: &Graph, q: Query, field: &str) -> Result<u64> {
+    let rows = fetch_rows(pool, q).await?;
+    let row = rows.first().context("no row returned")?;
+    Ok(row.get::<i64>(field)? as u64)
+}
+
+/// Checks that the indexes and constraints of all the migrations exist,
+/// and are online.
+pub async fn check_schema(pool: &Graph, repair: bool) -> Result<DoctorCheck> {
+    let mut check = DoctorCheck::new("indexes and constraints");
+
+    let problems = find_schema_problems(pool).await?;
+    check.problems = problems.len() as u64;
+    check.details = problems.iter().map(|p| p.detail.clone()).collect();
+
+    if repair && check.problems > 0 {
+        for problem in problems {
+            // an index which backs a constraint is dropped with it
+            let drop = match (problem.failed, problem.owner) {
+                (false, _) => continue,
+                (true, Some(constraint)) => format!("DROP CONSTRAINT {} IF EXISTS", constraint),
+                (true, None) => format!("DROP INDEX {} IF EXISTS", problem.name),
+            };
+            pool.run(query(&drop)).await?;
+        }
+        run_schema_statements(pool, all_schema_statements()).await?;
+        let left = find_schema_problems(pool).await?.len() as u64;
+        check.repaired = check.problems.saturating_sub(left);
+    }
+    Ok(check)
+}
+
+/// A missing, or not online, index or constraint.
+struct SchemaProblem {
+    name: String,
+    detail: String,
+    /// it exists, but is not online
+    failed: bool,
+    /// the constraint an index belongs to
+    owner: Option<String>,
+}
+
+async fn find_schema_problems(pool: &Graph) -> Result<Vec<SchemaProblem>> {
+    let mut existing = BTreeMap::new();
+    let rows = fetch_rows(
+        pool,
+        query("SHOW INDEXES YIELD name, state, owningConstraint"),
+    )
+    .await?;
+    for row in rows {
+        let name: String = row.get("name")?;
+        existing.insert(
+            name,
+            (
+                row.get::<String>("state")?,
+                row.get::<String>("owningConstraint").ok(),
+            ),
+        );
+    }
+    let constraints = fetch_rows(pool, query("SHOW CONSTRAINTS YIELD name")).await?;
+    let mut constraint_names = BTreeSet::new();
+    for row in constraints {
+        constraint_names.insert(row.get::<String>("name")?);
+    }
+
+    let mut problems = vec![];
+    for statement in all_schema_statements() {
+        let Some(name) = schema_statement_name(&statement) else {
+            continue;
+        };
+        match existing.get(name) {
+            Some((state, owner)) if state != "ONLINE" => problems.push(SchemaProblem {
+                name: name.to_string(),
+                detail: format!("{} is {}", name, state),
+                failed: true,
+                owner: owner.clone(),
+            }),
+            Some(_) => {}
+            None if constraint_names.contains(name) => {}
+            None => problems.push(SchemaProblem {
+                name: name.to_string(),
+                detail: format!("{} is missing", name),
+                failed: false,
+                owner: None,
+            }),
+        }
+    }
+    Ok(problems)
+}
+
+/// Finds transaction edges without a `tx_hash`, which a `MERGE` can't
+/// match, so a reload would write them again.
+pub async fn check_tx_hashes(pool: &Graph, repair: bool) -> Result<DoctorCheck> {
+    let mut check = DoctorCheck::new("transactions without tx_hash");
+    let relation_types = format!("{}|Tx", tx_relation_types_cypher());
+
+    let count_query = format!(
+        "MATCH ()-[r:{relation_types}]->() WHERE r.tx_hash IS NULL RETURN type(r) AS rel_type, count(r) AS edges"
+    );
+    for row in fetch_rows(pool, query(&count_query)).await? {
+        let edges = row.get::<i64>("edges")? as u64;
+        check.problems += edges;
+        check.details.push(format!(
+            "{} {} edges",
+            edges,
+            row.get::<String>("rel_type")?
+        ));
+    }
+
+    if repair && check.problems > 0 {
+        let delete_query = format!(
+            "MATCH ()-[r:{relation_types}]->() WHERE r.tx_hash IS NULL DELETE r RETURN count(*) AS deleted"
+        );
+        check.repaired = fetch_count(pool, query(&delete_query), "deleted").await?;
+    }
+    Ok(check)
+}
+
+/// Finds `Snapshot` nodes which no account has a `State` edge to.
+pub async fn check_orphan_snapshots(pool: &Graph, repair: bool) -> Result<DoctorCheck> {
+    let mut check = DoctorCheck::new("snapshots without State edge");
+
+    let rows = fetch_rows(
+        pool,
+        query(
+            r#"
+MATCH (s:Snapshot)
+WHERE NOT ()-[:State]->(s)
+RETURN count(s) AS orphans, collect(s.address + " at version " + toString(s.version))[..$max] AS examples
+"#,
+        )
+        .param("max", MAX_DETAILS as i64),
+    )
+    .await?;
+    if let Some(row) = rows.first() {
+        check.problems = row.get::<i64>("orphans")? as u64;
+        check.details = row.get("examples").unwrap_or_default();
+    }
+
+    if repair && check.problems > 0 {
+        let relink_query = r#"
+MATCH (s:Snapshot)
+WHERE NOT ()-[:State]->(s) AND s.address IS NOT NULL AND s.version IS NOT NULL
+MERGE (addr:Account {address: s.address})
+MERGE (addr)-[rel:State {version: s.version}]->(s)
+SET
+  rel.archive_id = s.archive_id,
+  rel.manifest_path = s.manifest_path,
+  rel.chunk_sha256 = s.chunk_sha256
+RETURN count(rel) AS linked
+"#;
+        check.repaired = fetch_count(pool, query(relink_query), "linked").await?;
+    }
+    Ok(check)
+}
+
+/// Finds `Lifetime` totals whose coins differ from the sum of the
+/// transactions between their accounts, and accounts which sent coins
+/// without a total. Legacy `Tx` edges are summed too, like the rebuild does.
+pub async fn check_lifetime(pool: &Graph, repair: bool) -> Result<DoctorCheck> {
+    let mut check = DoctorCheck::new("Lifetime totals which disagree with their transactions");
+
+    let rows = find_lifetime_mismatches(pool).await?;
+    let mut senders = BTreeSet::new();
+    for row in &rows {
+        let from: String = row.get("from")?;
+        if check.details.len() < MAX_DETAILS {
+            let total = row
+                .get::<f64>("total")
+                .map_or("none".to_string(), |t| t.to_string());
+            check.details.push(format!(
+                "{} -> {}: total {}, transactions {}",
+                from,
+                row.get::<String>("to")?,
+                total,
+                row.get::<f64>("coins")?
+            ));
+        }
+        senders.insert(from);
+    }
+    check.problems = rows.len() as u64;
+
+    if repair && check.problems > 0 {
+        let senders: Vec<String> = senders.into_iter().collect();
+        for page in senders.chunks(1_000) {
+            rebuild_lifetime_of(pool, page.to_vec()).await?;
+        }
+        let left = find_lifetime_mismatches(pool).await?.len() as u64;
+        check.repaired = check.problems.saturating_sub(left);
+    }
+    Ok(check)
+}
+
+async fn find_lifetime_mismatches(pool: &Graph) -> Result<Vec<Row>> {
+    let relation_types = lifetime_relation_types_cypher();
+    let mismatch_query = format!(
+        r#"
+MATCH (from:Account)-[total:Lifetime]->(to:Account)
+OPTIONAL MATCH (from)-[rel:{relation_types}]->(to)
+WHERE rel.coins > 0 AND COALESCE(rel.status, "Success") = "Success"
+WITH from, to, total, sum(COALESCE(rel.coins, 0.0)) AS coins
+WHERE abs(COALESCE(total.coins, 0.0) - coins) > 0.01
+RETURN from.address AS from, to.address AS to, total.coins AS total, coins
+UNION
+MATCH (from:Account)-[rel:{relation_types}]->(to:Account)
+WHERE rel.coins > 0 AND COALESCE(rel.status, "Success") = "Success"
+  AND NOT (from)-[:Lifetime]->(to)
+RETURN from.address AS from, to.address AS to, null AS total, sum(rel.coins) AS coins
+"#
+    );
+    fetch_rows(pool, query(&mismatch_query)).await
+}
+
+/// Finds `SwapAccount` nodes which share a `swap_id`, which the exchange
+/// loaders assume is unique.
+pub async fn check_swap_accounts(pool: &Graph, repair: bool) -> Result<DoctorCheck> {
+    let mut check = DoctorCheck::new("duplicate SwapAccount ids");
+
+    let rows = fetch_rows(
+        pool,
+        query(
+            r#"
+MATCH (s:SwapAccount)
+WITH s.swap_id AS swap_id, count(s) AS nodes
+WHERE nodes > 1
+RETURN swap_id, nodes
+ORDER BY swap_id
+"#,
+        ),
+    )
+    .await?;
+    for row in &rows {
+        let nodes = row.get::<i64>("nodes")?;
+        check.problems += nodes as u64 - 1;
+        if check.details.len() < MAX_DETAILS {
+            check.details.push(format!(
+                "swap_id {}: {} nodes",
+                row.get::<i64>("swap_id")?,
+                nodes
+            ));
+        }
+    }
+
+    if repair && check.problems > 0 {
+        check.repaired =
+            fetch_count(pool, query(&cypher_merge_swap_accounts_str()), "merged").await?;
+    }
+    Ok(check)
+}
+
+/// Moves the relationships of duplicate `SwapAccount` nodes to the first
+/// node of their `swap_id`, and deletes the duplicates.
+pub fn cypher_merge_swap_accounts_str() -> String {
+    r#"
+MATCH (s:SwapAccount)
+WITH s.swap_id AS swap_id, collect(s) AS nodes
+WHERE size(nodes) > 1
+WITH head(nodes) AS keep, tail(nodes) AS duplicates
+UNWIND duplicates AS dup
+CALL {
+  WITH keep, dup
+  MATCH (dup)-[r:Swap]->(taker:SwapAccount)
+  WITH keep, dup, r, CASE WHEN taker = dup THEN keep ELSE taker END AS target
+  CREATE (keep)-[moved:Swap]->(target)
+  SET moved = properties(r)
+  RETURN count(moved) AS made
+}
+CALL {
+  WITH keep, dup
+  MATCH (maker:SwapAccount)-[r:Swap]->(dup)
+  WHERE maker <> dup
+  CREATE (maker)-[moved:Swap]->(keep)
+  SET moved = properties(r)
+  RETURN count(moved) AS taken
+}
+CALL {
+  WITH keep, dup
+  MATCH (addr:Account)-[:OnRamp]->(dup)
+  MERGE (addr)-[:OnRamp]->(keep)
+  RETURN count(addr) AS onramps
+}
+CALL {
+  WITH keep, dup
+  MATCH (dup)-[r:DailyLedger]->(ul:UserLedger)
+  MERGE (keep)-[moved:DailyLedger]->(ul)
+  SET moved.date = r.date
+  RETURN count(ul) AS ledgers
+}
+DETACH DELETE dup
+RETURN count(*) AS merged
+"#
+    .to_string()
+}
+
+/// Finds queue batches whose write started `stuck_hours` ago, but which are
+/// incomplete and without an error, e.g. since their load crashed. Batches
+/// which were queued and never reached have no `started_at`, and are left to
+/// the next load.
+pub async fn check_stuck_queue(
+    pool: &Graph,
+    repair: bool,
+    stuck_hours: u64,
+) -> Result<DoctorCheck> {
+    let mut check = DoctorCheck::new("queue batches stuck incomplete");
+    let before = Utc::now().timestamp_millis() - (stuck_hours * 3_600_000) as i64;
+
+    let rows = fetch_rows(
+        pool,
+        query(
+            r#"
+MATCH (a:Queue)
+WHERE a.completed = false AND a.error IS NULL AND a.started_at < $before
+RETURN a.archive_id AS archive_id, a.batch AS batch
+ORDER BY archive_id, batch
+"#,
+        )
+        .param("before", before),
+    )
+    .await?;
+    for row in rows.iter().take(MAX_DETAILS) {
+        check.details.push(format!(
+            "{} batch #{}",
+            row.get::<String>("archive_id")?,
+            row.get::<i64>("batch")?
+        ));
+    }
+    check.problems = rows.len() as u64;
+
+    if repair && check.problems > 0 {
+        let error = format!("stuck: started over {} hours ago", stuck_hours);
+        let mark_query = query(
+            r#"
+MATCH (a:Queue)
+WHERE a.completed = false AND a.error IS NULL AND a.started_at < $before
+SET a.error = $error, a.attempts = 0, a.updated_at = timestamp()
+RETURN count(a) AS marked
+"#,
+        )
+        .param("before", before)
+        .param("error", error);
+        check.repaired = fetch_count(pool, mark_query, "marked").await?;
+    }
+    Ok(check)
+}
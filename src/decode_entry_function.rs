@@ -56,9 +56,45 @@ fn maybe_get_v7_relation(
                 RelationLabel::Transfer(to, amount)
             }
         }
+        Some(V7EntryFunctionCall::DonorVoiceTxsProposePaymentTx {
+            multisig_address,
+            payee,
+            value,
+            ..
+        }) => RelationLabel::DonorVoicePayment(multisig_address, payee, value),
+        Some(V7EntryFunctionCall::DonorVoiceTxsProposeVetoTx {
+            multisig_address, ..
+        })
+        | Some(V7EntryFunctionCall::DonorVoiceTxsVoteVetoTx {
+            multisig_address, ..
+        })
+        | Some(V7EntryFunctionCall::DonorVoiceTxsProposeLiquidateTx { multisig_address })
+        | Some(V7EntryFunctionCall::DonorVoiceTxsVoteLiquidationTx { multisig_address }) => {
+            RelationLabel::DonorVoice(multisig_address)
+        }
+        Some(V7EntryFunctionCall::CommunityWalletInitInitCommunity {
+            ref initial_authorities,
+            ..
+        }) => RelationLabel::CommunityWallet(initial_authorities.clone()),
+        Some(V7EntryFunctionCall::CommunityWalletInitProposeOffer {
+            ref new_signers, ..
+        }) => RelationLabel::CommunityWallet(new_signers.clone()),
+        Some(V7EntryFunctionCall::MultiActionClaimOffer { multisig_address }) => {
+            RelationLabel::Multisig(multisig_address)
+        }
+        Some(V7EntryFunctionCall::SlowWalletUserSetSlow {}) => RelationLabel::SlowWallet,
+        Some(V7EntryFunctionCall::ProofOfFeePofUpdateBid { .. })
+        | Some(V7EntryFunctionCall::ProofOfFeePofUpdateBidNetReward { .. })
+        | Some(V7EntryFunctionCall::ProofOfFeePofRetractBid {}) => RelationLabel::ProofOfFeeBid,
+        Some(V7EntryFunctionCall::ValidatorUniverseRegisterValidator { .. }) => {
+            RelationLabel::ValidatorRegistration
+        }
+        Some(V7EntryFunctionCall::VouchRevoke { friend_account }) => {
+            RelationLabel::VouchRevoke(friend_account)
+        }
+        Some(V7EntryFunctionCall::JailUnjailByVoucher { addr }) => RelationLabel::Unjail(addr),
 
-        // TODO: get other entry functions with known counter parties
-        // if nothing is found try to decipher from events
+        // TODO: if nothing is found try to decipher from events
         _ => return None,
     };
 
@@ -97,9 +133,44 @@ fn maybe_get_v6_relation(
                 RelationLabel::Transfer(to, amount)
             }
         }
+        Some(V6EntryFunctionCall::DonorVoiceTxsProposePaymentTx {
+            multisig_address,
+            payee,
+            value,
+            ..
+        }) => RelationLabel::DonorVoicePayment(multisig_address, payee, value),
+        Some(V6EntryFunctionCall::DonorVoiceTxsProposeVetoTx {
+            multisig_address, ..
+        })
+        | Some(V6EntryFunctionCall::DonorVoiceTxsVoteVetoTx {
+            multisig_address, ..
+        })
+        | Some(V6EntryFunctionCall::DonorVoiceTxsProposeLiquidateTx { multisig_address })
+        | Some(V6EntryFunctionCall::DonorVoiceTxsVoteLiquidationTx { multisig_address }) => {
+            RelationLabel::DonorVoice(multisig_address)
+        }
+        Some(V6EntryFunctionCall::CommunityWalletInitInitCommunity {
+            ref initial_authorities,
+            ..
+        }) => RelationLabel::CommunityWallet(initial_authorities.clone()),
+        Some(V6EntryFunctionCall::CommunityWalletInitProposeOffer {
+            ref new_signers, ..
+        }) => RelationLabel::CommunityWallet(new_signers.clone()),
+        Some(V6EntryFunctionCall::MultiActionClaimOffer { multisig_address }) => {
+            RelationLabel::Multisig(multisig_address)
+        }
+        Some(V6EntryFunctionCall::SlowWalletUserSetSlow {}) => RelationLabel::SlowWallet,
+        Some(V6EntryFunctionCall::ProofOfFeePofUpdateBid { .. })
+        | Some(V6EntryFunctionCall::ProofOfFeePofRetractBid {}) => RelationLabel::ProofOfFeeBid,
+        Some(V6EntryFunctionCall::ValidatorUniverseRegisterValidator { .. }) => {
+            RelationLabel::ValidatorRegistration
+        }
+        Some(V6EntryFunctionCall::VouchRevoke { its_not_me_its_you }) => {
+            RelationLabel::VouchRevoke(its_not_me_its_you)
+        }
+        Some(V6EntryFunctionCall::JailUnjailByVoucher { addr }) => RelationLabel::Unjail(addr),
 
-        // TODO: get other entry functions with known counter parties
-        // if nothing is found try to decipher from events
+        // TODO: if nothing is found try to decipher from events
         _ => return None,
     };
     let args = EntryFunctionArgs::V6(ef.unwrap());
@@ -1,9 +1,13 @@
-use crate::util::de_address_from_any_string;
+use crate::{
+    cypher_templates::{execute_batch, json_list_to_bolt},
+    util::de_address_from_any_string,
+};
 use anyhow::{Context, Result};
 use diem_types::account_address::AccountAddress;
 use log::info;
-use neo4rs::Graph;
+use neo4rs::{BoltType, Graph};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::path::Path;
 
 // Exchange onboard json files are formatted like so:
@@ -35,47 +39,38 @@ impl ExchangeOnRamp {
         Ok(serde_json::from_str(&s)?)
     }
 
-    /// Converts the struct into a Cypher-compatible object string.
-    pub fn to_cypher_object_template(&self) -> String {
-        format!(
-            r#"{{user_id: {}, address: "{}" }}"#,
-            self.user_id,
-            self.onramp_address.as_ref().unwrap().to_hex_literal(),
-        )
+    /// Converts the struct into a JSON object of its Cypher properties.
+    ///
+    /// Returns `None` for records without an address.
+    pub fn to_cypher_json(&self) -> Option<Value> {
+        let addr = self.onramp_address.as_ref()?;
+        Some(json!({
+            "user_id": self.user_id,
+            "address": addr.to_hex_literal(),
+        }))
     }
 
-    /// Creates a Cypher map string from a list of `ExchangeOnRamp` objects.
-    pub fn to_cypher_map(list: &[Self]) -> String {
-        let mut list_literal = "".to_owned();
-        for el in list {
-            // skip empty records
-            if el.onramp_address.is_none() {
-                continue;
-            };
-            let s = el.to_cypher_object_template();
-            list_literal.push_str(&s);
-            list_literal.push(',');
-        }
-        list_literal.pop(); // need to drop last comma ","
-        format!("[{}]", list_literal)
+    /// Converts a list of `ExchangeOnRamp` into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        // skip empty records
+        let list: Vec<Value> = list.iter().filter_map(|el| el.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
     }
 
     /// Generates a Cypher query to batch link addresses to swap accounts.
-    pub fn cypher_batch_link_owner(list_str: &str) -> String {
-        format!(
-            r#"
-  WITH {list_str} AS owner_data
-  UNWIND owner_data AS each_owner
-
-  MATCH (id:SwapAccount {{swap_id: each_owner.user_id}})
-  MATCH (addr:Account {{address: each_owner.address}})
+    pub fn cypher_batch_link_owner() -> String {
+        r#"
+  UNWIND $batch AS each_owner
+
+  MATCH (id:SwapAccount {swap_id: each_owner.user_id})
+  MATCH (addr:Account {address: each_owner.address})
   MERGE (addr)-[rel:OnRamp]->(id)
 
   WITH rel
   RETURN
       COUNT(rel) AS owners_merged
 "#
-        )
+        .to_string()
     }
 }
 
@@ -90,21 +85,15 @@ pub async fn impl_batch_tx_insert(pool: &Graph, batch_txs: &[ExchangeOnRamp]) ->
 
     info!("unique owner links in batch: {}", unique_owners.len());
 
-    let list_str = ExchangeOnRamp::to_cypher_map(batch_txs);
+    let batch = ExchangeOnRamp::to_cypher_map(batch_txs);
 
     // first insert the users
     // cypher queries makes it annoying to do a single insert of users and
     // txs
-    let cypher_string = ExchangeOnRamp::cypher_batch_link_owner(&list_str);
+    let cypher_string = ExchangeOnRamp::cypher_batch_link_owner();
 
     // Execute the query
-    let cypher_query = neo4rs::query(&cypher_string);
-    let mut res = pool
-        .execute(cypher_query)
-        .await
-        .context("execute query error")?;
-
-    let row = res.next().await?.context("no row returned")?;
+    let row = execute_batch(pool, &cypher_string, batch).await?;
 
     let owners_merged: u64 = row.get("owners_merged").context("no owners_merged field")?;
 
@@ -1,9 +1,13 @@
-use crate::util::de_address_from_any_string;
+use crate::{
+    cypher_templates::{execute_batch, json_list_to_bolt},
+    util::de_address_from_any_string,
+};
 use anyhow::{Context, Result};
 use diem_types::account_address::AccountAddress;
 use log::{error, info};
-use neo4rs::Graph;
+use neo4rs::{BoltType, Graph};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::path::Path;
 
 /// Metadata for account ownership mapping (whitepages).
@@ -22,54 +26,43 @@ impl Whitepages {
         Ok(serde_json::from_str(&s)?)
     }
 
-    /// Converts the struct into a Cypher-compatible object string.
-    pub fn to_cypher_object_template(&self) -> String {
-        if let Some(addr) = &self.address {
-            format!(
-                r#"{{owner: "{}", address: "{}"}}"#,
-                self.owner.as_ref().unwrap(),
-                addr.to_hex_literal(),
-            )
-        } else {
+    /// Converts the struct into a JSON object of its Cypher properties.
+    ///
+    /// Returns `None` for records without an owner or an address.
+    pub fn to_cypher_json(&self) -> Option<Value> {
+        let owner = self.owner.as_ref()?;
+        let Some(addr) = &self.address else {
             error!("missing address at {:#?}", &self);
-            "".to_string()
-        }
+            return None;
+        };
+        Some(json!({
+            "owner": owner,
+            "address": addr.to_hex_literal(),
+        }))
     }
 
-    /// Creates a Cypher map string from a list of `Whitepages` objects.
-    pub fn to_cypher_map(list: &[Self]) -> String {
-        let mut list_literal = "".to_owned();
-        for el in list {
-            // skip empty records
-            if el.owner.is_none() {
-                continue;
-            };
-
-            let s = el.to_cypher_object_template();
-            list_literal.push_str(&s);
-            list_literal.push(',');
-        }
-        list_literal.pop(); // need to drop last comma ","
-        format!("[{}]", list_literal)
+    /// Converts a list of `Whitepages` into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        // skip empty records
+        let list: Vec<Value> = list.iter().filter_map(|el| el.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
     }
 
     /// Generates a Cypher query to batch link addresses to owner aliases.
-    pub fn cypher_batch_link_owner(list_str: &str) -> String {
-        format!(
-            r#"
-  WITH {list_str} AS owner_data
-  UNWIND owner_data AS each_owner
+    pub fn cypher_batch_link_owner() -> String {
+        r#"
+  UNWIND $batch AS each_owner
 
-  MATCH (addr:Account {{address: each_owner.address}})
+  MATCH (addr:Account {address: each_owner.address})
 
-  MERGE (own:Owner {{alias: each_owner.owner}})
+  MERGE (own:Owner {alias: each_owner.owner})
   MERGE (own)-[rel:Owns]->(addr)
 
   WITH rel
   RETURN
       COUNT(rel) AS owners_merged
 "#
-        )
+        .to_string()
     }
 }
 
@@ -86,21 +79,15 @@ pub async fn impl_batch_tx_insert(pool: &Graph, batch_txs: &[Whitepages]) -> Res
 
     info!("unique owner links in batch: {}", unique_owners.len());
 
-    let list_str = Whitepages::to_cypher_map(batch_txs);
+    let batch = Whitepages::to_cypher_map(batch_txs);
 
     // first insert the users
     // cypher queries makes it annoying to do a single insert of users and
     // txs
-    let cypher_string = Whitepages::cypher_batch_link_owner(&list_str);
+    let cypher_string = Whitepages::cypher_batch_link_owner();
 
     // Execute the query
-    let cypher_query = neo4rs::query(&cypher_string);
-    let mut res = pool
-        .execute(cypher_query)
-        .await
-        .context("execute query error")?;
-
-    let row = res.next().await?.context("no row returned")?;
+    let row = execute_batch(pool, &cypher_string, batch).await?;
 
     let owners_merged: u64 = row.get("owners_merged").context("no owners_merged field")?;
 
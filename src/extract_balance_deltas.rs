@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use diem_crypto::HashValue;
+use diem_types::{state_store::state_key::StateKeyInner, write_set::WriteSet};
+use libra_types::{
+    exports::AccountAddress,
+    move_resource::{libra_coin::LibraCoinStoreResource, wallet::SlowWalletResource},
+};
+use log::warn;
+use move_core_types::{language_storage::StructTag, move_resource::MoveStructType};
+
+use crate::schema_balance_delta::{WarehouseBalance, WarehouseBalanceDelta};
+
+/// The resources of the write set which carry account balances.
+enum BalanceResource {
+    CoinStore,
+    SlowWallet,
+}
+
+/// A balance resource decoded from a write set, `None` when it was deleted.
+enum BalanceValue {
+    CoinStore(Option<LibraCoinStoreResource>),
+    SlowWallet(Option<SlowWalletResource>),
+}
+
+/// Keeps the latest balances written for each account while reading an
+/// archive sequentially, so that each change can be reported with the value
+/// it replaced.
+///
+/// Archives are extracted independently, so the tracker starts empty: the
+/// first change of an account in each archive has no `before`, and no
+/// `balance_change`.
+#[derive(Debug, Default)]
+pub struct BalanceTracker(pub HashMap<AccountAddress, WarehouseBalance>);
+
+impl BalanceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the coin store and slow wallet changes of one transaction's
+    /// write set, returning a delta for every account touched. A resource
+    /// which cannot be decoded is logged and skipped.
+    pub fn decode_write_set(
+        &mut self,
+        tx_hash: HashValue,
+        version: u64,
+        write_set: &WriteSet,
+    ) -> Result<Vec<WarehouseBalanceDelta>> {
+        // several resources of the same account may change in one write set
+        let mut touched: Vec<AccountAddress> = vec![];
+        let mut after_map: HashMap<AccountAddress, WarehouseBalance> = HashMap::new();
+
+        for (state_key, write_op) in write_set.iter() {
+            let StateKeyInner::AccessPath(ap) = state_key.inner() else {
+                continue;
+            };
+            let Some(tag) = ap.get_struct_tag() else {
+                continue;
+            };
+            let Some(kind) = match_balance_resource(&tag) else {
+                continue;
+            };
+
+            let address = ap.address;
+            let value = match decode_balance_resource(&kind, write_op.bytes().map(|b| &b[..])) {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!(
+                        "could not decode {}::{} of {} at version {}, skipping: {:#}",
+                        tag.module,
+                        tag.name,
+                        address.to_hex_literal(),
+                        version,
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            if !touched.contains(&address) {
+                touched.push(address);
+                let prev = self.0.get(&address).cloned().unwrap_or_default();
+                after_map.insert(address, prev);
+            }
+            let after = after_map.entry(address).or_default();
+
+            // a deletion leaves the value empty
+            match value {
+                BalanceValue::CoinStore(r) => {
+                    after.balance = r.map(|r| r.coin());
+                }
+                BalanceValue::SlowWallet(sw) => {
+                    after.slow_wallet_unlocked = sw.as_ref().map(|s| s.unlocked);
+                    after.slow_wallet_transferred = sw.as_ref().map(|s| s.transferred);
+                }
+            }
+        }
+
+        let mut deltas = vec![];
+        for address in touched {
+            let after = after_map.remove(&address).unwrap_or_default();
+            let before = self.0.insert(address, after.clone());
+            deltas.push(WarehouseBalanceDelta {
+                tx_hash,
+                version,
+                address,
+                before,
+                after,
+                provenance: None,
+            });
+        }
+
+        Ok(deltas)
+    }
+}
+
+/// Checks if a struct tag is one of the balance resources of the framework.
+/// The whole tag is compared, type parameters included, so the coin store
+/// of another coin type is not taken as a balance.
+fn match_balance_resource(tag: &StructTag) -> Option<BalanceResource> {
+    if tag == &LibraCoinStoreResource::struct_tag() {
+        Some(BalanceResource::CoinStore)
+    } else if tag == &SlowWalletResource::struct_tag() {
+        Some(BalanceResource::SlowWallet)
+    } else {
+        None
+    }
+}
+
+/// Decodes the bytes of a balance resource, or `None` for a deletion.
+fn decode_balance_resource(kind: &BalanceResource, bytes: Option<&[u8]>) -> Result<BalanceValue> {
+    Ok(match kind {
+        BalanceResource::CoinStore => {
+            BalanceValue::CoinStore(bytes.map(bcs::from_bytes).transpose()?)
+        }
+        BalanceResource::SlowWallet => {
+            BalanceValue::SlowWallet(bytes.map(bcs::from_bytes).transpose()?)
+        }
+    })
+}
+
+/// Compares the amount of a decoded transfer, in current base units, with
+/// the recipient's balance change in the write set, and warns on a mismatch.
+pub fn check_transfer_amount(
+    recipient: &AccountAddress,
+    amount: u64,
+    deltas: &[WarehouseBalanceDelta],
+) -> bool {
+    let Some(change) = deltas
+        .iter()
+        .find(|d| &d.address == recipient)
+        .and_then(|d| d.balance_change())
+    else {
+        // nothing to check against
+        return true;
+    };
+
+    let matches = change as i128 == amount as i128;
+    if !matches {
+        warn!(
+            "transfer amount {} does not match balance change {} of recipient {}",
+            amount,
+            change,
+            recipient.to_hex_literal()
+        );
+    }
+    matches
+}
@@ -0,0 +1,113 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use diem_backup_cli::backup_types::epoch_ending::manifest::{EpochEndingBackup, EpochEndingChunk};
+use diem_backup_cli::utils::read_record_bytes::ReadRecordBytes;
+use diem_types::ledger_info::LedgerInfoWithSignatures;
+use libra_backwards_compatibility::version_five::state_snapshot_v5::open_for_read;
+use log::{info, warn};
+
+use crate::{
+    provenance::Provenance,
+    schema_epoch::{WarehouseEpoch, WarehouseValidator},
+};
+
+/// Reads an epoch ending backup manifest file into an `EpochEndingBackup` object.
+pub fn load_epoch_ending_manifest(path: &Path) -> Result<EpochEndingBackup> {
+    let s =
+        std::fs::read_to_string(path).context(format!("Error: cannot read file at {:?}", path))?;
+
+    let map: EpochEndingBackup = serde_json::from_str(&s)?;
+
+    Ok(map)
+}
+
+/// Loads the ledger infos of one epoch ending chunk.
+pub async fn load_ledger_infos(
+    archive_path: &Path,
+    chunk: &EpochEndingChunk,
+) -> Result<Vec<LedgerInfoWithSignatures>> {
+    // like the transaction chunks, the file handle includes the archive dir name
+    let full_handle = archive_path
+        .parent()
+        .expect("could not read archive path")
+        .join(&chunk.ledger_infos);
+    let handle_str = full_handle.to_str().unwrap();
+    assert!(full_handle.exists(), "file does not exist");
+
+    let mut file = open_for_read(handle_str)
+        .await
+        .map_err(|e| anyhow!("epoch ending chunk {:?}, {:?}", &handle_str, e))?;
+
+    let mut ledger_infos = Vec::new();
+    while let Some(record_bytes) = file.read_record_bytes().await? {
+        let li: LedgerInfoWithSignatures = bcs::from_bytes(&record_bytes)?;
+        ledger_infos.push(li);
+    }
+
+    Ok(ledger_infos)
+}
+
+/// Extracts epoch boundaries and validator sets from an epoch ending archive.
+///
+/// Each ledger info in the archive closes an epoch, and announces the
+/// validator set of the next one. So the record produced is for the epoch
+/// which starts at the version following the ledger info. Every record is
+/// tagged with the `Provenance` of the chunk it was read from.
+pub async fn extract_epoch_ending(archive_path: &Path) -> Result<Vec<WarehouseEpoch>> {
+    let manifest_file = archive_path.join("epoch_ending.manifest");
+    assert!(
+        manifest_file.exists(),
+        "{}",
+        &format!("epoch_ending.manifest file not found at {:?}", archive_path)
+    );
+    let manifest = load_epoch_ending_manifest(&manifest_file)?;
+
+    let mut epochs = vec![];
+
+    for chunk in manifest.chunks.iter() {
+        let ledger_infos = load_ledger_infos(archive_path, chunk).await?;
+        let chunk_file = archive_path
+            .parent()
+            .expect("could not read archive path")
+            .join(&chunk.ledger_infos);
+        let provenance = Arc::new(Provenance::from_chunk(
+            archive_path,
+            &manifest_file,
+            &chunk_file,
+        )?);
+
+        for li_sigs in ledger_infos {
+            let li = li_sigs.ledger_info();
+
+            let Some(next) = li.next_epoch_state() else {
+                warn!(
+                    "ledger info at version {} is not an epoch change, skipping",
+                    li.version()
+                );
+                continue;
+            };
+
+            let validators = next
+                .verifier
+                .get_ordered_account_addresses_iter()
+                .map(|address| WarehouseValidator {
+                    address,
+                    voting_power: next.verifier.get_voting_power(&address).unwrap_or(0),
+                })
+                .collect();
+
+            epochs.push(WarehouseEpoch {
+                epoch: next.epoch,
+                first_version: li.version() + 1,
+                timestamp: li.timestamp_usecs(),
+                validators,
+                provenance: Some(provenance.clone()),
+            });
+        }
+    }
+
+    info!("epochs extracted: {}", epochs.len());
+
+    Ok(epochs)
+}
@@ -1,14 +1,28 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::Result;
-use diem_types::account_view::AccountView;
+use anyhow::{anyhow, Context, Result};
+use diem_backup_cli::{
+    backup_types::state_snapshot::manifest::StateSnapshotChunk,
+    utils::read_record_bytes::ReadRecordBytes,
+};
+use diem_types::{
+    account_config::AccountResource,
+    state_store::{
+        state_key::{StateKey, StateKeyInner},
+        state_value::StateValue,
+    },
+};
 use libra_backwards_compatibility::version_five::{
     balance_v5::BalanceResourceV5,
     ol_tower_state::TowerStateResource,
     ol_wallet::SlowWalletResourceV5,
-    state_snapshot_v5::{v5_accounts_from_manifest_path, v5_read_from_snapshot_manifest},
+    state_snapshot_v5::{open_for_read, read_account_state_chunk, v5_read_from_snapshot_manifest},
 };
-use libra_storage::read_snapshot::{accounts_from_snapshot_backup, load_snapshot_manifest};
+use libra_storage::read_snapshot::load_snapshot_manifest;
 use libra_types::{
     exports::AccountAddress,
     move_resource::{
@@ -17,23 +31,250 @@ use libra_types::{
     },
 };
 use log::{error, info, warn};
+use move_core_types::{language_storage::StructTag, move_resource::MoveStructType};
+use tokio::sync::mpsc;
 
 use crate::{
+    provenance::Provenance,
     scan::FrameworkVersion,
     schema_account_state::{WarehouseAccState, WarehouseTime},
-    util::COIN_DECIMAL_PRECISION,
 };
 
-/// Extracts account states from a V5 framework snapshot archive.
+/// The account states of a snapshot archive, read one chunk file at a time.
+///
+/// All the accounts of a snapshot share the same `WarehouseTime`, so it is
+/// kept once for the stream rather than on each record. The accounts are
+/// taken in batches, each tagged with the chunk file it was read from.
+///
+/// A V5 chunk file holds whole account blobs, so only the chunk being taken,
+/// and those a decoder keeps ahead, are in memory. A V6+ chunk file holds single resources, keyed by a hash,
+/// so the resources of an account can be in any chunk: all the chunk files
+/// are read one by one, and only the decoded account states of the whole
+/// archive are kept, not the raw records.
+pub struct SnapshotStream {
+    pub time: WarehouseTime,
+    source: ChunkSource,
+    /// decoded accounts of the last chunk read, not yet taken
+    pending: VecDeque<WarehouseAccState>,
+    /// the chunk file of the pending accounts
+    pending_provenance: Option<Arc<Provenance>>,
+    /// raw account blobs read so far
+    blobs: u64,
+    /// accounts taken from the stream so far
+    decoded: u64,
+}
+
+/// Account states of a snapshot, all read from the same chunk file.
+#[derive(Debug, Clone)]
+pub struct SnapshotBatch {
+    /// the archive and chunk file the accounts were read from, if known
+    pub provenance: Option<Arc<Provenance>>,
+    pub accounts: Vec<WarehouseAccState>,
+}
+
+/// The account states decoded from one chunk file.
+struct DecodedChunk {
+    batch: SnapshotBatch,
+    /// accounts found in the chunk, decoded or not
+    blobs: u64,
+}
+
+/// The chunk files of a snapshot which are left to read.
+enum ChunkSource {
+    /// accounts which were already decoded
+    Decoded(VecDeque<DecodedChunk>),
+    /// V5 chunk files, each record is a whole account
+    V5 {
+        archive_path: PathBuf,
+        manifest_file: PathBuf,
+        chunks: VecDeque<String>,
+    },
+    /// V6+ chunk files, read all at once on the first batch
+    Current {
+        archive_path: PathBuf,
+        manifest_file: PathBuf,
+        chunks: Option<Vec<StateSnapshotChunk>>,
+        decoded: VecDeque<DecodedChunk>,
+    },
+    /// chunks decoded by a `SnapshotDecoder`
+    Decoder(mpsc::Receiver<Result<DecodedChunk>>),
+}
+
+impl SnapshotStream {
+    /// A stream over account states which were already decoded, with no
+    /// provenance.
+    pub fn from_vec(time: WarehouseTime, accounts: Vec<WarehouseAccState>) -> Self {
+        Self::from_batches(
+            time,
+            vec![SnapshotBatch {
+                provenance: None,
+                accounts,
+            }],
+        )
+    }
+
+    /// A stream over batches of account states which were already decoded.
+    pub fn from_batches(time: WarehouseTime, batches: Vec<SnapshotBatch>) -> Self {
+        let chunks = batches
+            .into_iter()
+            .map(|batch| DecodedChunk {
+                blobs: batch.accounts.len() as u64,
+                batch,
+            })
+            .collect();
+        Self::new(time, ChunkSource::Decoded(chunks))
+    }
+
+    fn new(time: WarehouseTime, source: ChunkSource) -> Self {
+        Self {
+            time,
+            source,
+            pending: VecDeque::new(),
+            pending_provenance: None,
+            blobs: 0,
+            decoded: 0,
+        }
+    }
+
+    /// Splits the reading and decoding of the chunk files from the stream,
+    /// so it can run in another task. The decoder keeps up to `ahead`
+    /// decoded chunks which were not taken yet, so the decoding overlaps with
+    /// the loading of the batches.
+    pub fn with_decoder(mut self, ahead: usize) -> (Self, SnapshotDecoder) {
+        let (tx, rx) = mpsc::channel(ahead.max(1));
+        let source = std::mem::replace(&mut self.source, ChunkSource::Decoder(rx));
+        (self, SnapshotDecoder { source, tx })
+    }
+
+    /// Takes the next batch of up to `batch_size` accounts, or `None` once
+    /// the stream is done. A batch is never taken across two chunk files.
+    pub async fn next_batch(&mut self, batch_size: usize) -> Result<Option<SnapshotBatch>> {
+        while self.pending.is_empty() {
+            match self.source.read_next().await? {
+                Some(chunk) => {
+                    self.blobs += chunk.blobs;
+                    self.pending.extend(chunk.batch.accounts);
+                    self.pending_provenance = chunk.batch.provenance;
+                }
+                None => {
+                    self.check_count();
+                    return Ok(None);
+                }
+            }
+        }
+
+        let n = batch_size.max(1).min(self.pending.len());
+        let accounts: Vec<WarehouseAccState> = self.pending.drain(..n).collect();
+        self.decoded += accounts.len() as u64;
+        Ok(Some(SnapshotBatch {
+            provenance: self.pending_provenance.clone(),
+            accounts,
+        }))
+    }
+
+    /// Warns if some blobs were not decoded into accounts, e.g. since they
+    /// had no address.
+    fn check_count(&self) {
+        if self.decoded != self.blobs {
+            warn!(
+                "account count does not match: {} blobs, {} accounts",
+                self.blobs, self.decoded
+            );
+        }
+    }
+
+    /// The number of accounts taken from the stream so far.
+    pub fn decoded(&self) -> u64 {
+        self.decoded
+    }
+
+    /// Decodes all the remaining accounts, one batch for each chunk file.
+    pub async fn collect_all(mut self) -> Result<Vec<SnapshotBatch>> {
+        let mut batches = vec![];
+        while let Some(batch) = self.next_batch(usize::MAX).await? {
+            batches.push(batch);
+        }
+        Ok(batches)
+    }
+}
+
+/// Reads and decodes the chunk files of a `SnapshotStream` split with
+/// `with_decoder`.
+pub struct SnapshotDecoder {
+    source: ChunkSource,
+    tx: mpsc::Sender<Result<DecodedChunk>>,
+}
+
+impl SnapshotDecoder {
+    /// Decodes the chunks one at a time, sending them to the stream. Stops
+    /// on the first error, which is sent to the stream, or once the stream
+    /// is dropped.
+    pub async fn run(mut self) {
+        loop {
+            let chunk = match self.source.read_next().await {
+                Ok(Some(chunk)) => Ok(chunk),
+                // all the chunks were read, dropping the sender ends the stream
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            // the receiver is gone if the stream was dropped
+            if self.tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    }
+}
+
+impl ChunkSource {
+    /// Reads and decodes the next chunk file, or `None` when all were read.
+    async fn read_next(&mut self) -> Result<Option<DecodedChunk>> {
+        match self {
+            ChunkSource::Decoded(chunks) => Ok(chunks.pop_front()),
+            ChunkSource::Decoder(rx) => rx.recv().await.transpose(),
+            ChunkSource::V5 {
+                archive_path,
+                manifest_file,
+                chunks,
+            } => match chunks.pop_front() {
+                Some(handle) => Ok(Some(
+                    read_v5_chunk(archive_path, manifest_file, handle).await?,
+                )),
+                None => Ok(None),
+            },
+            ChunkSource::Current {
+                archive_path,
+                manifest_file,
+                chunks,
+                decoded,
+            } => {
+                if let Some(chunks) = chunks.take() {
+                    *decoded = read_current_chunks(archive_path, manifest_file, chunks)
+                        .await?
+                        .into();
+                }
+                Ok(decoded.pop_front())
+            }
+        }
+    }
+}
+
+/// The path of a chunk file listed in a manifest. File handles are relative
+/// to the parent of the archive directory.
+fn chunk_file(archive_path: &Path, handle: &str) -> Result<PathBuf> {
+    Ok(archive_path
+        .parent()
+        .context("could not read archive path")?
+        .join(handle))
+}
+
+/// Streams account states from a V5 framework snapshot archive.
 ///
 /// Decodes V5 framework bytecode into current version data structures.
-pub async fn extract_v5_snapshot(archive_path: &Path) -> Result<Vec<WarehouseAccState>> {
+pub async fn stream_v5_snapshot(archive_path: &Path) -> Result<SnapshotStream> {
     let v5_manifest_path = archive_path.join("state.manifest");
-    // NOTE: this is duplicated with next step.
     let manifest_data = v5_read_from_snapshot_manifest(&v5_manifest_path)?;
-    let account_blobs = v5_accounts_from_manifest_path(&v5_manifest_path).await?;
 
-    // TODO: see below, massively inefficient
     let time = WarehouseTime {
         framework_version: FrameworkVersion::V5,
         timestamp: 0,
@@ -41,51 +282,77 @@ pub async fn extract_v5_snapshot(archive_path: &Path) -> Result<Vec<WarehouseAcc
         epoch: 0,
     };
 
-    info!("account records found: {}", &account_blobs.len());
-    let mut warehouse_state = vec![];
-    for el in account_blobs.iter() {
-        let acc = el.to_account_state()?;
-        // convert v5 address to v7
-        match acc.get_address() {
-            Ok(a) => {
-                let address_literal = a.to_hex_literal();
-                let cast_address = AccountAddress::from_hex_literal(&address_literal)?;
-                let mut s = WarehouseAccState::new(cast_address);
+    info!("chunk files found: {}", manifest_data.chunks.len());
+    let chunks = manifest_data.chunks.into_iter().map(|c| c.blobs).collect();
 
-                s.time = time.clone();
-
-                if let Ok(r) = acc.get_diem_account_resource() {
-                    s.sequence_num = r.sequence_number();
-                }
-
-                if let Ok(b) = acc.get_resource::<BalanceResourceV5>() {
-                    s.balance = b.coin() as f64 / COIN_DECIMAL_PRECISION as f64;
-                }
-                if let Ok(sw) = acc.get_resource::<SlowWalletResourceV5>() {
-                    s.slow_wallet_acc = true;
-                    s.slow_wallet_unlocked =
-                        Some(sw.unlocked as f64 / COIN_DECIMAL_PRECISION as f64);
-                    s.slow_wallet_transferred =
-                        Some(sw.transferred as f64 / COIN_DECIMAL_PRECISION as f64);
-                }
+    Ok(SnapshotStream::new(
+        time,
+        ChunkSource::V5 {
+            archive_path: archive_path.to_owned(),
+            manifest_file: v5_manifest_path,
+            chunks,
+        },
+    ))
+}
 
-                if let Ok(tower) = acc.get_resource::<TowerStateResource>() {
-                    s.miner_height = Some(tower.verified_tower_height);
-                }
+/// Reads the account blobs of one V5 chunk file, and decodes them.
+async fn read_v5_chunk(
+    archive_path: &Path,
+    manifest_file: &Path,
+    handle: String,
+) -> Result<DecodedChunk> {
+    let provenance = Provenance::from_chunk(
+        archive_path,
+        manifest_file,
+        &chunk_file(archive_path, &handle)?,
+    )?;
+    let account_blobs = read_account_state_chunk(handle, archive_path).await?;
 
-                warehouse_state.push(s);
-            }
+    let blobs = account_blobs.len() as u64;
+    let mut accounts = Vec::with_capacity(account_blobs.len());
+    for (_key, el) in account_blobs {
+        let acc = el.to_account_state()?;
+        // convert v5 address to v7
+        let a = match acc.get_address() {
+            Ok(a) => a,
             Err(e) => {
                 error!("could not parse blob to V5 Address: {}", &e);
+                continue;
             }
+        };
+        let address_literal = a.to_hex_literal();
+        let cast_address = AccountAddress::from_hex_literal(&address_literal)?;
+        let mut s = WarehouseAccState::new(cast_address);
+
+        if let Ok(r) = acc.get_diem_account_resource() {
+            s.sequence_num = r.sequence_number();
+        }
+
+        if let Ok(b) = acc.get_resource::<BalanceResourceV5>() {
+            s.set_balance(b.coin(), &FrameworkVersion::V5);
         }
+        if let Ok(sw) = acc.get_resource::<SlowWalletResourceV5>() {
+            s.set_slow_wallet(sw.unlocked, sw.transferred, &FrameworkVersion::V5);
+        }
+
+        if let Ok(tower) = acc.get_resource::<TowerStateResource>() {
+            s.miner_height = Some(tower.verified_tower_height);
+        }
+
+        accounts.push(s);
     }
 
-    Ok(warehouse_state)
+    Ok(DecodedChunk {
+        batch: SnapshotBatch {
+            provenance: Some(Arc::new(provenance)),
+            accounts,
+        },
+        blobs,
+    })
 }
 
-/// Extracts account states from a current (V6+) framework snapshot archive.
-pub async fn extract_current_snapshot(archive_path: &Path) -> Result<Vec<WarehouseAccState>> {
+/// Streams account states from a current (V6+) framework snapshot archive.
+pub async fn stream_current_snapshot(archive_path: &Path) -> Result<SnapshotStream> {
     let manifest_file = archive_path.join("state.manifest");
     assert!(
         manifest_file.exists(),
@@ -94,8 +361,6 @@ pub async fn extract_current_snapshot(archive_path: &Path) -> Result<Vec<Warehou
     );
     let manifest = load_snapshot_manifest(&manifest_file)?;
 
-    // TODO: this is not memory efficient, will be massively duplicating data
-    // when the insert query could just use the warehouse time, for entire state
     let time = WarehouseTime {
         version: manifest.version,
         epoch: manifest.epoch,
@@ -103,50 +368,146 @@ pub async fn extract_current_snapshot(archive_path: &Path) -> Result<Vec<Warehou
         timestamp: 0,
     };
 
-    let accs = accounts_from_snapshot_backup(manifest, archive_path).await?;
+    info!("chunk files found: {}", manifest.chunks.len());
 
-    info!("SUCCESS: backup loaded. # accounts: {}", &accs.len());
-
-    // TODO: stream this
-    let mut warehouse_state = vec![];
-    for el in accs.iter() {
-        if let Some(address) = el.get_account_address()? {
-            let mut s = WarehouseAccState::new(address);
+    Ok(SnapshotStream::new(
+        time,
+        ChunkSource::Current {
+            archive_path: archive_path.to_owned(),
+            manifest_file,
+            chunks: Some(manifest.chunks),
+            decoded: VecDeque::new(),
+        },
+    ))
+}
 
-            s.time = time.clone();
+/// Reads the V6+ chunk files one at a time, decoding the resources of each
+/// account into its state, so the raw records of a chunk are dropped before
+/// the next one is read.
+///
+/// The accounts are returned grouped by the chunk file which holds their
+/// account resource. Addresses without one, like resource-only records of
+/// the framework, are counted as blobs but not decoded into accounts.
+async fn read_current_chunks(
+    archive_path: &Path,
+    manifest_file: &Path,
+    chunks: Vec<StateSnapshotChunk>,
+) -> Result<Vec<DecodedChunk>> {
+    // address -> (chunk first found in, chunk of the account resource, state)
+    let mut states: BTreeMap<AccountAddress, (usize, Option<usize>, WarehouseAccState)> =
+        BTreeMap::new();
 
-            if let Some(r) = el.get_account_resource()? {
-                s.sequence_num = r.sequence_number();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let records = read_state_chunk(archive_path, &chunk.blobs).await?;
+        for (key, value) in records {
+            let StateKeyInner::AccessPath(ap) = key.inner() else {
+                continue;
+            };
+            let (_, account_chunk, s) = states
+                .entry(ap.address)
+                .or_insert_with(|| (i, None, WarehouseAccState::new(ap.address)));
+            // modules have no struct tag
+            let Some(tag) = ap.get_struct_tag() else {
+                continue;
+            };
+            if apply_current_resource(s, &tag, &value.bytes()[..])? {
+                *account_chunk = Some(i);
             }
+        }
+    }
 
-            if let Some(b) = el.get_resource::<LibraCoinStoreResource>()? {
-                s.balance = b.coin() as f64 / COIN_DECIMAL_PRECISION as f64;
-            }
+    info!("SUCCESS: backup loaded. # accounts: {}", states.len());
 
-            if let Some(sw) = el.get_resource::<SlowWalletResource>()? {
-                s.slow_wallet_acc = true;
-                s.slow_wallet_unlocked = Some(sw.unlocked as f64 / COIN_DECIMAL_PRECISION as f64);
-                s.slow_wallet_transferred =
-                    Some(sw.transferred as f64 / COIN_DECIMAL_PRECISION as f64);
-            }
+    let mut decoded = vec![];
+    for chunk in &chunks {
+        let file = chunk_file(archive_path, &chunk.blobs)?;
+        decoded.push(DecodedChunk {
+            batch: SnapshotBatch {
+                provenance: Some(Arc::new(Provenance::from_chunk(
+                    archive_path,
+                    manifest_file,
+                    &file,
+                )?)),
+                accounts: vec![],
+            },
+            blobs: 0,
+        });
+    }
+    for (_, (first_chunk, account_chunk, s)) in states {
+        decoded[first_chunk].blobs += 1;
+        if let Some(i) = account_chunk {
+            decoded[i].batch.accounts.push(s);
+        }
+    }
+    Ok(decoded)
+}
 
-            // Infer if it is a donor voice account
-            if let Some(_sw) = el.get_resource::<CumulativeDepositResource>()? {
-                s.donor_voice_acc = true;
-            }
+/// Reads the `(StateKey, StateValue)` records of one V6+ chunk file.
+async fn read_state_chunk(
+    archive_path: &Path,
+    handle: &str,
+) -> Result<Vec<(StateKey, StateValue)>> {
+    let full_handle = chunk_file(archive_path, handle)?;
+    let handle_str = full_handle
+        .to_str()
+        .context("could not read chunk file path")?;
 
-            warehouse_state.push(s);
-        }
+    let mut file = open_for_read(handle_str)
+        .await
+        .map_err(|e| anyhow!("snapshot chunk {:?}, {:?}", &handle_str, e))?;
+
+    let mut records = vec![];
+    while let Some(record_bytes) = file.read_record_bytes().await? {
+        records.push(bcs::from_bytes(&record_bytes)?);
+    }
+    Ok(records)
+}
+
+/// Sets the fields of a current (V6+) account state which come from one of
+/// its resources. Other resources are ignored. Returns whether it was the
+/// account resource.
+fn apply_current_resource(
+    s: &mut WarehouseAccState,
+    tag: &StructTag,
+    bytes: &[u8],
+) -> Result<bool> {
+    if tag == &AccountResource::struct_tag() {
+        let r: AccountResource = bcs::from_bytes(bytes)?;
+        s.sequence_num = r.sequence_number();
+        return Ok(true);
+    } else if tag == &LibraCoinStoreResource::struct_tag() {
+        let b: LibraCoinStoreResource = bcs::from_bytes(bytes)?;
+        s.set_balance(b.coin(), &FrameworkVersion::V7);
+    } else if tag == &SlowWalletResource::struct_tag() {
+        let sw: SlowWalletResource = bcs::from_bytes(bytes)?;
+        s.set_slow_wallet(sw.unlocked, sw.transferred, &FrameworkVersion::V7);
+    } else if tag == &CumulativeDepositResource::struct_tag() {
+        // Infer if it is a donor voice account
+        s.donor_voice_acc = true;
     }
+    Ok(false)
+}
+
+/// Extracts all the account states from a V5 framework snapshot archive.
+pub async fn extract_v5_snapshot(archive_path: &Path) -> Result<Vec<WarehouseAccState>> {
+    let batches = stream_v5_snapshot(archive_path)
+        .await?
+        .collect_all()
+        .await?;
+    Ok(batches.into_iter().flat_map(|b| b.accounts).collect())
+}
 
+/// Extracts all the account states from a current (V6+) framework snapshot archive.
+pub async fn extract_current_snapshot(archive_path: &Path) -> Result<Vec<WarehouseAccState>> {
+    let batches = stream_current_snapshot(archive_path)
+        .await?
+        .collect_all()
+        .await?;
+    let warehouse_state: Vec<WarehouseAccState> =
+        batches.into_iter().flat_map(|b| b.accounts).collect();
     info!(
         "SUCCESS: accounts parsed. # accounts: {}",
         &warehouse_state.len()
     );
-
-    if warehouse_state.len() != accs.len() {
-        warn!("account count does not match");
-    }
-
     Ok(warehouse_state)
 }
@@ -1,12 +1,17 @@
 use crate::decode_entry_function::decode_entry_function_all_versions;
+use crate::extract_balance_deltas::{check_transfer_amount, BalanceTracker};
+use crate::provenance::Provenance;
 use crate::read_tx_chunk::{load_chunk, load_tx_chunk_manifest};
 use crate::scan::FrameworkVersion;
+use crate::schema_balance_delta::WarehouseBalanceDelta;
 use crate::schema_transaction::{RelationLabel, UserEventTypes, WarehouseEvent, WarehouseTxMaster};
 use anyhow::Result;
 use chrono::DateTime;
 use diem_crypto::HashValue;
 use diem_types::account_config::{NewBlockEvent, WithdrawEvent};
 use diem_types::contract_event::ContractEvent;
+use diem_types::transaction::{ExecutionStatus, TransactionInfo};
+use diem_types::vm_status::AbortLocation;
 use diem_types::{account_config::DepositEvent, transaction::SignedTransaction};
 use libra_types::move_resource::coin_register_event::CoinRegisterEvent;
 use log::{error, info, warn};
@@ -18,10 +23,20 @@ use std::sync::Arc;
 ///
 /// This function reads the `transaction.manifest` and processes each chunk to extract
 /// successful user transactions and their associated events.
+/// With `include_failed` the transactions which did not execute successfully
+/// are also extracted, with their abort code and location.
+/// The balance changes of every transaction's write set are returned as well,
+/// including those of system transactions which are otherwise not extracted.
+/// Every record is tagged with the `Provenance` of the chunk it was read from.
 pub async fn extract_current_transactions(
     archive_path: &Path,
     framework_version: &FrameworkVersion,
-) -> Result<(Vec<WarehouseTxMaster>, Vec<WarehouseEvent>)> {
+    include_failed: bool,
+) -> Result<(
+    Vec<WarehouseTxMaster>,
+    Vec<WarehouseEvent>,
+    Vec<WarehouseBalanceDelta>,
+)> {
     let manifest_file = archive_path.join("transaction.manifest");
     assert!(
         manifest_file.exists(),
@@ -37,13 +52,23 @@ pub async fn extract_current_transactions(
 
     let mut user_txs: Vec<WarehouseTxMaster> = vec![];
     let mut events: Vec<WarehouseEvent> = vec![];
+    let mut balance_deltas: Vec<WarehouseBalanceDelta> = vec![];
+    let mut balances = BalanceTracker::new();
 
     let mut count_excluded = 0;
 
     for each_chunk_manifest in manifest.chunks {
         let chunk = load_chunk(archive_path, each_chunk_manifest).await?;
+        let provenance = Arc::new(Provenance::from_chunk(
+            archive_path,
+            &manifest_file,
+            &chunk.path,
+        )?);
 
         for (i, tx) in chunk.txns.iter().enumerate() {
+            // the chunk is sequential, so the ledger version is the offset from the chunk's first version
+            let version = chunk.manifest.first_version + i as u64;
+
             // first collect the block metadata. This assumes the vector is sequential.
             if let Some(block) = tx.try_as_block_metadata() {
                 epoch = block.epoch();
@@ -56,25 +81,42 @@ pub async fn extract_current_transactions(
                 .get(i)
                 .expect("could not index on tx_info chunk, vectors may not be same length");
 
-            // only process successful transactions
-            if !tx_info.status().is_success() {
+            let tx_hash_info = tx_info.transaction_hash();
+
+            // write sets are decoded for every transaction, so the tracked
+            // balances stay current
+            let write_set = chunk
+                .write_sets
+                .get(i)
+                .expect("could not index on write_set chunk, vectors may not be same length");
+            let mut tx_deltas = balances.decode_write_set(tx_hash_info, version, write_set)?;
+            for d in tx_deltas.iter_mut() {
+                d.provenance = Some(provenance.clone());
+            }
+            balance_deltas.extend(tx_deltas.iter().cloned());
+
+            // only process successful transactions, unless asked for failures
+            if !tx_info.status().is_success() && !include_failed {
                 count_excluded += 1;
                 continue;
             };
 
-            let tx_hash_info = tx_info.transaction_hash();
-
             let tx_events = chunk
                 .event_vecs
                 .get(i)
                 .expect("could not index on events chunk, vectors may not be same length");
 
             let mut decoded_events = decode_events(tx_hash_info, tx_events)?;
+            for e in decoded_events.iter_mut() {
+                e.provenance = Some(provenance.clone());
+            }
             events.append(&mut decoded_events);
 
             if let Some(signed_transaction) = tx.try_as_signed_user_txn() {
-                let tx = make_master_tx(
+                let mut tx = make_master_tx(
                     signed_transaction,
+                    tx_info,
+                    version,
                     epoch,
                     round,
                     timestamp,
@@ -87,6 +129,14 @@ pub async fn extract_current_transactions(
                     error!("transaction hashes do not match in transaction vector and transaction_info vector");
                 }
 
+                if let (RelationLabel::Transfer(recipient, _), Some(amount)) =
+                    (&tx.relation_label, tx.coin_amount())
+                {
+                    check_transfer_amount(recipient, amount.normalized, &tx_deltas);
+                }
+                tx.balance_deltas = tx_deltas;
+                tx.provenance = Some(provenance.clone());
+
                 if tx.relation_label.get_recipient().is_some() {
                     user_txs.push(tx);
                     user_txs_in_chunk += 1;
@@ -102,12 +152,16 @@ pub async fn extract_current_transactions(
 
     info!("Excluding {} unsuccessful transactions", count_excluded);
 
-    Ok((user_txs, events))
+    info!("balance changes extracted: {}", balance_deltas.len());
+
+    Ok((user_txs, events, balance_deltas))
 }
 
 /// Constructs a `WarehouseTxMaster` from a signed user transaction and its context.
 pub fn make_master_tx(
     user_tx: &SignedTransaction,
+    tx_info: &TransactionInfo,
+    version: u64,
     epoch: u64,
     round: u64,
     block_timestamp: u64,
@@ -132,10 +186,20 @@ pub fn make_master_tx(
         Err(_) => (None, RelationLabel::Configuration),
     };
 
+    let (status, abort_code, abort_location) = decode_execution_status(tx_info.status());
+
     let tx = WarehouseTxMaster {
         tx_hash,
         expiration_timestamp: user_tx.expiration_timestamp_secs(),
+        sequence_number: user_tx.sequence_number(),
+        gas_used: tx_info.gas_used(),
+        gas_unit_price: user_tx.gas_unit_price(),
+        max_gas_amount: user_tx.max_gas_amount(),
+        status,
+        abort_code,
+        abort_location,
         sender: user_tx.sender(),
+        version,
         epoch,
         round,
         block_timestamp,
@@ -144,12 +208,43 @@ pub fn make_master_tx(
         relation_label,
         block_datetime: DateTime::from_timestamp_micros(block_timestamp as i64).unwrap(),
         events,
+        balance_deltas: vec![],
         framework_version: framework_version.clone(),
+        provenance: None,
     };
 
     Ok(tx)
 }
 
+/// Flattens the VM `ExecutionStatus` into a status name, and the abort code
+/// and module location when the transaction failed.
+pub fn decode_execution_status(status: &ExecutionStatus) -> (String, Option<u64>, Option<String>) {
+    let location_str = |l: &AbortLocation| match l {
+        AbortLocation::Module(m) => m.short_str_lossless(),
+        AbortLocation::Script => "Script".to_owned(),
+    };
+
+    match status {
+        ExecutionStatus::Success => ("Success".to_owned(), None, None),
+        ExecutionStatus::OutOfGas => ("OutOfGas".to_owned(), None, None),
+        ExecutionStatus::MoveAbort { location, code, .. } => (
+            "MoveAbort".to_owned(),
+            Some(*code),
+            Some(location_str(location)),
+        ),
+        ExecutionStatus::ExecutionFailure { location, .. } => (
+            "ExecutionFailure".to_owned(),
+            None,
+            Some(location_str(location)),
+        ),
+        ExecutionStatus::MiscellaneousError(code) => (
+            "MiscellaneousError".to_owned(),
+            code.map(|c| c as u64),
+            None,
+        ),
+    }
+}
+
 /// Decodes raw contract events into `WarehouseEvent` structures.
 ///
 /// Filters out noisy events like `NewBlockEvent` and attempts to parse
@@ -160,7 +255,8 @@ pub fn decode_events(
 ) -> Result<Vec<WarehouseEvent>> {
     let list: Vec<WarehouseEvent> = tx_events
         .iter()
-        .filter_map(|el| {
+        .enumerate()
+        .filter_map(|(index, el)| {
             // exclude block announcements, too much noise
             if NewBlockEvent::try_from_bytes(el.event_data()).is_ok() {
                 return None;
@@ -188,9 +284,12 @@ pub fn decode_events(
 
             Some(WarehouseEvent {
                 tx_hash,
+                index: index as u64,
+                account: el.key().get_creator_address(),
                 event,
                 event_name,
                 data,
+                provenance: None,
             })
         })
         .collect();
@@ -0,0 +1,274 @@
+//! Exports decoded records to files instead of a database.
+//!
+//! Transactions, events and account state snapshots are written as NDJSON or
+//! Parquet, one file per archive and epoch, in Hive style directories:
+//!
+//! ```text
+//! <out_dir>/transactions/archive_id=<id>/epoch=<epoch>/part-0.parquet
+//! ```
+//!
+//! so tools like DuckDB can read them as one partitioned table, e.g.
+//! `read_parquet('<out_dir>/transactions/*/*/*.parquet', hive_partitioning = true)`.
+//! An export overwrites the files of its archive, so running it again gives
+//! the same files, and no queue is needed.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use arrow_json::reader::{infer_json_schema_from_iterator, ReaderBuilder};
+use log::{error, info, warn};
+use parquet::arrow::ArrowWriter;
+use serde_json::Value;
+
+use crate::{
+    extract_snapshot::{stream_current_snapshot, stream_v5_snapshot, SnapshotBatch},
+    extract_transactions::extract_current_transactions,
+    scan::{ArchiveMap, BundleContent, FrameworkVersion, ManifestInfo},
+    schema_account_state::WarehouseTime,
+    schema_transaction::{WarehouseEvent, WarehouseTxMaster},
+    unzip_temp,
+};
+
+/// File formats of an export.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum FileFormat {
+    /// one JSON object per line
+    Ndjson,
+    /// columnar Parquet, with a schema inferred from the records
+    Parquet,
+}
+
+impl FileFormat {
+    /// The extension of the exported files.
+    pub fn extension(&self) -> &str {
+        match self {
+            FileFormat::Ndjson => "ndjson",
+            FileFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Writes records to partitioned files under an output directory.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    pub out_dir: PathBuf,
+    pub format: FileFormat,
+}
+
+impl FileSink {
+    pub fn new(out_dir: &Path, format: FileFormat) -> Self {
+        Self {
+            out_dir: out_dir.to_owned(),
+            format,
+        }
+    }
+
+    /// The file of one table for an archive and epoch.
+    pub fn partition_path(&self, table: &str, archive_id: &str, epoch: u64) -> PathBuf {
+        self.out_dir
+            .join(table)
+            .join(format!("archive_id={}", archive_id))
+            .join(format!("epoch={}", epoch))
+            .join(format!("part-0.{}", self.format.extension()))
+    }
+
+    /// Writes records of one table, grouped by their epoch, returning the
+    /// files written.
+    pub fn write_partitioned(
+        &self,
+        table: &str,
+        archive_id: &str,
+        records: Vec<(u64, Value)>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut by_epoch: BTreeMap<u64, Vec<Value>> = BTreeMap::new();
+        for (epoch, record) in records {
+            by_epoch.entry(epoch).or_default().push(record);
+        }
+
+        let mut paths = vec![];
+        for (epoch, list) in by_epoch {
+            let path = self.partition_path(table, archive_id, epoch);
+            let dir = path.parent().context("no partition directory")?;
+            fs::create_dir_all(dir)
+                .context(format!("could not create directory {}", dir.display()))?;
+
+            match self.format {
+                FileFormat::Ndjson => write_ndjson(&path, &list)?,
+                FileFormat::Parquet => write_parquet(&path, &list)?,
+            }
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Writes transactions, returning the number written.
+    pub fn write_transactions(&self, archive_id: &str, txs: &[WarehouseTxMaster]) -> Result<u64> {
+        let records = txs
+            .iter()
+            .map(|t| (t.epoch, tx_file_record(t)))
+            .collect::<Vec<_>>();
+        let count = records.len() as u64;
+        self.write_partitioned("transactions", archive_id, records)?;
+        Ok(count)
+    }
+
+    /// Writes events, partitioned by the epoch of the transaction which
+    /// emitted them. Returns the number written.
+    pub fn write_events(
+        &self,
+        archive_id: &str,
+        events: &[WarehouseEvent],
+        txs: &[WarehouseTxMaster],
+    ) -> Result<u64> {
+        let epochs: HashMap<_, _> = txs.iter().map(|t| (t.tx_hash, t.epoch)).collect();
+
+        let mut records = vec![];
+        for ev in events {
+            // events of skipped transactions, e.g. failed ones, are not written
+            let Some(epoch) = epochs.get(&ev.tx_hash) else {
+                continue;
+            };
+            records.push((*epoch, ev.to_cypher_json()));
+        }
+        let count = records.len() as u64;
+        self.write_partitioned("events", archive_id, records)?;
+        Ok(count)
+    }
+
+    /// Writes account state snapshots taken at `time`, each with the chunk
+    /// file of its batch, returning the number written.
+    pub fn write_snapshots(
+        &self,
+        archive_id: &str,
+        time: &WarehouseTime,
+        batches: &[SnapshotBatch],
+    ) -> Result<u64> {
+        let records = batches
+            .iter()
+            .flat_map(|b| {
+                b.accounts
+                    .iter()
+                    .map(|s| (time.epoch, s.to_record_json(time, b.provenance.as_deref())))
+            })
+            .collect::<Vec<_>>();
+        let count = records.len() as u64;
+        self.write_partitioned("snapshots", archive_id, records)?;
+        Ok(count)
+    }
+}
+
+/// The Cypher properties of a transaction, with its epoch, and the entry
+/// function arguments as a JSON string, since their shape differs for each
+/// function.
+fn tx_file_record(tx: &WarehouseTxMaster) -> Value {
+    let mut record = tx.to_cypher_json();
+    if let Some(obj) = record.as_object_mut() {
+        if let Some(args) = obj.get("args").filter(|a| !a.is_null()) {
+            let args = Value::String(args.to_string());
+            obj.insert("args".to_string(), args);
+        }
+        obj.insert("epoch".to_string(), tx.epoch.into());
+    }
+    record
+}
+
+fn write_ndjson(path: &Path, records: &[Value]) -> Result<()> {
+    let file = File::create(path).context(format!("could not create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for r in records {
+        serde_json::to_writer(&mut writer, r)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_parquet(path: &Path, records: &[Value]) -> Result<()> {
+    let schema = Arc::new(infer_json_schema_from_iterator(records.iter().map(Ok))?);
+
+    let mut decoder = ReaderBuilder::new(schema.clone())
+        .with_batch_size(records.len().max(1))
+        .build_decoder()?;
+    decoder.serialize(records)?;
+    let batch = decoder.flush()?.context("no records to write")?;
+
+    let file = File::create(path).context(format!("could not create {}", path.display()))?;
+    let mut writer = ArrowWriter::try_new(file, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Exports all the archives of a map. Archives are decompressed first if needed.
+pub async fn export_all(
+    archive_map: &ArchiveMap,
+    sink: &FileSink,
+    include_failed: bool,
+) -> Result<u64> {
+    let mut exported = 0;
+    for (_p, m) in archive_map.0.iter() {
+        info!(
+            "exporting: {:?} with archive: {}",
+            m.contents,
+            m.archive_dir.display()
+        );
+
+        let (new_unzip_path, temp) = unzip_temp::maybe_handle_gz(&m.archive_dir)?;
+        let mut better_man = ManifestInfo::new(&new_unzip_path);
+        better_man.set_info()?;
+
+        let count = export_one_archive(&better_man, sink, include_failed).await?;
+        info!("records exported: {}", count);
+        exported += count;
+        drop(temp);
+    }
+    Ok(exported)
+}
+
+/// Exports the records of a single archive, returning the number written.
+/// Failed transactions are skipped unless `include_failed` is set.
+pub async fn export_one_archive(
+    man: &ManifestInfo,
+    sink: &FileSink,
+    include_failed: bool,
+) -> Result<u64> {
+    match man.contents {
+        BundleContent::StateSnapshot => {
+            let stream = match man.version {
+                FrameworkVersion::Unknown => {
+                    error!("no framework version detected");
+                    bail!("could not export archive from manifest");
+                }
+                FrameworkVersion::V5 => stream_v5_snapshot(&man.archive_dir).await?,
+                FrameworkVersion::V6 | FrameworkVersion::V7 => {
+                    stream_current_snapshot(&man.archive_dir).await?
+                }
+            };
+            // one file is written per archive and epoch, so all the records
+            // are needed at once
+            let time = stream.time.clone();
+            sink.write_snapshots(&man.archive_id, &time, &stream.collect_all().await?)
+        }
+        BundleContent::Transaction => {
+            let (txs, events, _) =
+                extract_current_transactions(&man.archive_dir, &man.version, include_failed)
+                    .await?;
+            let event_count = sink.write_events(&man.archive_id, &events, &txs)?;
+            info!("events exported: {}", event_count);
+            Ok(sink.write_transactions(&man.archive_id, &txs)? + event_count)
+        }
+        _ => {
+            warn!(
+                "no file export for {:?} archives, skipping {}",
+                man.contents,
+                man.archive_dir.display()
+            );
+            Ok(0)
+        }
+    }
+}
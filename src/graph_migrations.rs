@@ -0,0 +1,299 @@
+//! Versioned schema and data migrations of the Neo4j graph.
+//!
+//! Each `Migration` has a version, and the versions applied to a graph are
+//! recorded as `(:SchemaVersion {version})` nodes, so graphs loaded by
+//! different releases can be brought to the same schema with `db migrate`.
+//! Migrations run in order, once each, and are idempotent, so a migration
+//! interrupted before its version is recorded can run again.
+//!
+//! Schema migrations are lists of `CREATE ... IF NOT EXISTS` statements.
+//! These are cheap, so `maybe_create_indexes` runs all of them before every
+//! load, without recording a version. Data migrations rewrite records, and
+//! only run from `db migrate`.
+//!
+//! Migrations are only added, never edited: a fix to an index is a new
+//! migration, since graphs which applied the old one will not run it again.
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use neo4rs::{query, Graph};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coin_normalize, lifetime, migrate_tx_relations,
+    neo4j_init::{
+        tx_relation_indexes, ACCOUNT_UNIQUE, EPOCH_UNIQUE, INDEX_BALANCE_DELTA,
+        INDEX_BALANCE_DELTA_ARCHIVE, INDEX_BALANCE_DELTA_VERSION, INDEX_EPOCH_VERSION, INDEX_EVENT,
+        INDEX_EVENT_ARCHIVE, INDEX_EVENT_TYPE, INDEX_EXCHANGE_LEDGER, INDEX_EXCHANGE_LINK_LEDGER,
+        INDEX_HEX_ADDR, INDEX_LIFETIME, INDEX_SNAPSHOT, INDEX_SNAPSHOT_ARCHIVE, INDEX_SWAP_ID,
+        INDEX_SWAP_TIME, TX_CONSTRAINT,
+    },
+};
+
+/// What a migration does.
+#[derive(Debug, Clone, Copy)]
+pub enum MigrationKind {
+    /// idempotent schema statements, e.g. `CREATE INDEX ... IF NOT EXISTS`
+    Schema(fn() -> Vec<String>),
+    /// a rewrite of the records
+    Data(DataMigration),
+}
+
+/// The data migrations, run by `apply_migration`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataMigration {
+    /// rewrite legacy `Tx` edges to a relationship type per relation label
+    TxRelations,
+    /// aggregate the `Lifetime` totals again, which older releases double
+    /// counted on reloads
+    RebuildLifetime,
+    /// store coin amounts as read and in current base units
+    NormalizeCoins,
+}
+
+/// One step of the graph schema.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub kind: MigrationKind,
+}
+
+/// All the migrations, in the order of their versions.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "constraints and indexes of accounts, transactions, snapshots, epochs, events and balance deltas",
+        kind: MigrationKind::Schema(initial_schema),
+    },
+    Migration {
+        version: 2,
+        description: "index Lifetime coins and Swap times, which reused names or were never applied",
+        kind: MigrationKind::Schema(fixed_indexes_schema),
+    },
+    Migration {
+        version: 3,
+        description: "rewrite legacy Tx edges to a relationship type per relation",
+        kind: MigrationKind::Data(DataMigration::TxRelations),
+    },
+    Migration {
+        version: 4,
+        description: "rebuild Lifetime totals with tx counts, first and last seen, min and max coins",
+        kind: MigrationKind::Data(DataMigration::RebuildLifetime),
+    },
+    Migration {
+        version: 5,
+        description: "normalize V5 snapshot balances to current base units, keeping the amounts as read",
+        kind: MigrationKind::Data(DataMigration::NormalizeCoins),
+    },
+    Migration {
+        version: 6,
+        description: "rebuild Lifetime totals with coins_normalized",
+        kind: MigrationKind::Data(DataMigration::RebuildLifetime),
+    },
+];
+
+fn initial_schema() -> Vec<String> {
+    let mut statements: Vec<String> = [
+        ACCOUNT_UNIQUE,
+        TX_CONSTRAINT,
+        INDEX_HEX_ADDR,
+        INDEX_SWAP_ID,
+        INDEX_EXCHANGE_LEDGER,
+        INDEX_EXCHANGE_LINK_LEDGER,
+        INDEX_SNAPSHOT,
+        EPOCH_UNIQUE,
+        INDEX_EPOCH_VERSION,
+        INDEX_EVENT,
+        INDEX_EVENT_TYPE,
+        INDEX_BALANCE_DELTA,
+        INDEX_BALANCE_DELTA_VERSION,
+        INDEX_SNAPSHOT_ARCHIVE,
+        INDEX_EVENT_ARCHIVE,
+        INDEX_BALANCE_DELTA_ARCHIVE,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    statements.extend(tx_relation_indexes());
+    statements
+}
+
+fn fixed_indexes_schema() -> Vec<String> {
+    vec![INDEX_LIFETIME.to_string(), INDEX_SWAP_TIME.to_string()]
+}
+
+/// A migration applied by `migrate`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub description: String,
+    /// indexes and constraints created by a schema migration
+    pub created: Vec<String>,
+    /// records rewritten by a data migration
+    pub records: u64,
+}
+
+/// The changes of a `migrate` run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub applied: Vec<AppliedMigration>,
+}
+
+/// The latest schema version.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// The migrations after a version, in order.
+pub fn pending_migrations(version: u32) -> Vec<&'static Migration> {
+    MIGRATIONS.iter().filter(|m| m.version > version).collect()
+}
+
+/// All the schema statements, of every migration. Each one is idempotent.
+pub fn all_schema_statements() -> Vec<String> {
+    MIGRATIONS
+        .iter()
+        .filter_map(|m| match m.kind {
+            MigrationKind::Schema(statements) => Some(statements()),
+            MigrationKind::Data(_) => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// The name of the index or constraint a schema statement creates.
+pub fn schema_statement_name(statement: &str) -> Option<&str> {
+    let mut words = statement.split_whitespace();
+    words.find(|w| *w == "INDEX" || *w == "CONSTRAINT")?;
+    words.next()
+}
+
+/// The highest version recorded in a graph, or 0 for a graph which was
+/// never migrated.
+pub async fn get_schema_version(pool: &Graph) -> Result<u32> {
+    let mut res = pool
+        .execute(query(
+            "MATCH (v:SchemaVersion) RETURN COALESCE(max(v.version), 0) AS version",
+        ))
+        .await
+        .context("execute query error")?;
+    let row = res.next().await?.context("no row returned")?;
+    Ok(row.get::<i64>("version")? as u32)
+}
+
+/// Records that a migration was applied.
+pub async fn set_schema_version(pool: &Graph, migration: &Migration) -> Result<()> {
+    let cypher_query = query(
+        r#"
+MERGE (v:SchemaVersion {version: $version})
+SET v.description = $description,
+  v.applied_at = timestamp(),
+  v.tool_version = $tool_version
+"#,
+    )
+    .param("version", migration.version as i64)
+    .param("description", migration.description)
+    .param("tool_version", env!("CARGO_PKG_VERSION"));
+    pool.run(cypher_query).await?;
+    Ok(())
+}
+
+/// The names of the indexes and constraints of a graph.
+pub async fn get_schema_names(pool: &Graph) -> Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+    for cypher in ["SHOW INDEXES YIELD name", "SHOW CONSTRAINTS YIELD name"] {
+        let mut res = pool
+            .execute(query(cypher))
+            .await
+            .context("execute query error")?;
+        while let Some(row) = res.next().await? {
+            names.insert(row.get::<String>("name")?);
+        }
+    }
+    Ok(names)
+}
+
+/// Runs schema statements in a single transaction.
+pub async fn run_schema_statements(pool: &Graph, statements: Vec<String>) -> Result<()> {
+    let mut txn = pool.start_txn().await?;
+    txn.run_queries(statements).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+/// Applies one migration, without recording its version.
+pub async fn apply_migration(pool: &Graph, migration: &Migration) -> Result<AppliedMigration> {
+    let mut applied = AppliedMigration {
+        version: migration.version,
+        description: migration.description.to_string(),
+        ..Default::default()
+    };
+    match migration.kind {
+        MigrationKind::Schema(statements) => {
+            let before = get_schema_names(pool).await?;
+            run_schema_statements(pool, statements()).await?;
+            let after = get_schema_names(pool).await?;
+            applied.created = after.difference(&before).cloned().collect();
+        }
+        MigrationKind::Data(DataMigration::TxRelations) => {
+            applied.records = migrate_tx_relations::migrate_tx_relations(pool, 10_000).await?;
+        }
+        MigrationKind::Data(DataMigration::RebuildLifetime) => {
+            applied.records = lifetime::rebuild_lifetime(pool, 1_000).await?;
+        }
+        MigrationKind::Data(DataMigration::NormalizeCoins) => {
+            applied.records = coin_normalize::normalize_graph_coins(pool, 10_000).await?;
+        }
+    }
+    Ok(applied)
+}
+
+/// Applies the pending migrations of a graph in order, recording the
+/// version of each one once it is done. With `dry_run`, only lists them.
+pub async fn migrate(pool: &Graph, dry_run: bool) -> Result<MigrationReport> {
+    let from_version = get_schema_version(pool).await?;
+    let mut report = MigrationReport {
+        from_version,
+        to_version: from_version,
+        applied: vec![],
+    };
+
+    for migration in pending_migrations(from_version) {
+        if dry_run {
+            report.applied.push(AppliedMigration {
+                version: migration.version,
+                description: migration.description.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        info!(
+            "applying migration {}: {}",
+            migration.version, migration.description
+        );
+        let applied = apply_migration(pool, migration)
+            .await
+            .context(format!("migration {} failed", migration.version))?;
+        set_schema_version(pool, migration).await?;
+        report.to_version = migration.version;
+        report.applied.push(applied);
+    }
+    Ok(report)
+}
+
+/// Warns if a graph has migrations which `db migrate` has not applied.
+pub async fn warn_pending_migrations(pool: &Graph) -> Result<()> {
+    let version = get_schema_version(pool).await?;
+    if version < latest_version() {
+        warn!(
+            "graph schema is at version {}, latest is {}, run `db migrate`",
+            version,
+            latest_version()
+        );
+    }
+    Ok(())
+}
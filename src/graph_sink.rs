@@ -0,0 +1,267 @@
+//! Storage backends for the loaders.
+//!
+//! The loaders batch the extracted records and track their progress in the
+//! queue, but leave the writes to a `GraphSink`. Neo4j is the reference
+//! backend, implemented here for `neo4rs::Graph`.
+use std::future::Future;
+
+use anyhow::Result;
+use neo4rs::Graph;
+
+use crate::{
+    analytics::enrich_account_funding::BalanceTracker,
+    batch_tx_type::BatchTxReturn,
+    enrich_exchange_onboarding::{self, ExchangeOnRamp},
+    enrich_whitepages::{self, Whitepages},
+    ingest_report::{self, IngestRun},
+    load_account_state, load_balance_deltas, load_epoch, load_events, load_exchange_orders,
+    load_tx_cypher,
+    provenance::Provenance,
+    purge::{self, PurgeReport},
+    queue::{self, DeadLetter, QueueStatus},
+    schema_account_state::{WarehouseAccState, WarehouseTime},
+    schema_balance_delta::WarehouseBalanceDelta,
+    schema_epoch::WarehouseEpoch,
+    schema_exchange_orders::ExchangeOrder,
+    schema_transaction::{WarehouseEvent, WarehouseTxMaster},
+};
+
+/// A store the loaders can write records and queue state to.
+///
+/// Every write is an upsert, so a batch can be loaded again after a failure
+/// without duplicating records.
+pub trait GraphSink: Sync {
+    /// Merges the senders and recipients of a batch of transactions, and
+    /// reports the account counts.
+    fn upsert_accounts(
+        &self,
+        txs: &[WarehouseTxMaster],
+    ) -> impl Future<Output = Result<BatchTxReturn>> + Send;
+
+    /// Merges a batch of transactions as relations between their accounts,
+    /// returning the number created. The accounts must already exist.
+    fn upsert_transactions(
+        &self,
+        txs: &[WarehouseTxMaster],
+    ) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Merges a batch of contract events.
+    fn upsert_events(
+        &self,
+        events: &[WarehouseEvent],
+    ) -> impl Future<Output = Result<BatchTxReturn>> + Send;
+
+    /// Merges a batch of per-transaction balance changes.
+    fn upsert_balance_deltas(
+        &self,
+        deltas: &[WarehouseBalanceDelta],
+    ) -> impl Future<Output = Result<BatchTxReturn>> + Send;
+
+    /// Merges a batch of account state snapshots, all taken at `time` and
+    /// read from the archive of `provenance`.
+    fn upsert_snapshots(
+        &self,
+        time: &WarehouseTime,
+        provenance: Option<&Provenance>,
+        snapshots: &[WarehouseAccState],
+    ) -> impl Future<Output = Result<BatchTxReturn>> + Send;
+
+    /// Merges a batch of epoch boundaries and their validator sets.
+    fn upsert_epochs(
+        &self,
+        epochs: &[WarehouseEpoch],
+    ) -> impl Future<Output = Result<BatchTxReturn>> + Send;
+
+    /// Merges a batch of exchange orders, returning the merged and ignored counts.
+    fn upsert_exchange_orders(
+        &self,
+        orders: &[ExchangeOrder],
+    ) -> impl Future<Output = Result<(u64, u64)>> + Send;
+
+    /// Merges the daily ledger of one exchange user.
+    fn upsert_user_ledger(
+        &self,
+        ledger: &BalanceTracker,
+        id: u32,
+    ) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Links a batch of accounts to their owners.
+    fn link_owners(&self, owners: &[Whitepages]) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Links a batch of accounts to their exchange users.
+    fn link_onramps(&self, onramps: &[ExchangeOnRamp]) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Saves an ingest run and the reports of its archives.
+    fn record_ingest_run(&self, run: &IngestRun) -> impl Future<Output = Result<()>> + Send;
+
+    /// Creates or updates a batch of an archive in the queue. Completing a
+    /// batch clears its dead letter.
+    fn update_task(
+        &self,
+        archive_id: &str,
+        completed: bool,
+        batch: usize,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Records that the write of a batch of an archive started.
+    fn start_task(&self, archive_id: &str, batch: usize)
+        -> impl Future<Output = Result<()>> + Send;
+
+    /// Leaves a batch of an archive incomplete in the queue, with the error
+    /// of its last attempt.
+    fn mark_dead_letter(
+        &self,
+        archive_id: &str,
+        batch: usize,
+        error: &str,
+        attempts: u32,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Lists the batches which failed after their retries.
+    fn get_dead_letters(&self) -> impl Future<Output = Result<Vec<DeadLetter>>> + Send;
+
+    /// Sets all the batches of a queue id as complete or incomplete, clearing
+    /// their dead letters. Returns the number of batches.
+    fn set_all_batches(
+        &self,
+        archive_id: &str,
+        completed: bool,
+    ) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Counts the complete, pending and failed batches of each queue id.
+    fn queue_status(&self) -> impl Future<Output = Result<Vec<QueueStatus>>> + Send;
+
+    /// Checks if a batch of an archive is complete, and errors if the batch
+    /// is not in the queue.
+    fn is_batch_complete(
+        &self,
+        archive_id: &str,
+        batch: usize,
+    ) -> impl Future<Output = Result<Option<bool>>> + Send;
+
+    /// Checks if all the queued batches of an archive are complete.
+    fn are_all_completed(&self, archive_id: &str) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Lists the queue ids with incomplete batches. See
+    /// `queue::get_queued_archives` for the archives they belong to.
+    fn get_queued(&self) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Removes all the batches from the queue.
+    fn clear_queue(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Removes all the batches of a queue id, returning the number removed.
+    fn remove_queued(&self, archive_id: &str) -> impl Future<Output = Result<u64>> + Send;
+
+    /// Deletes the records loaded from an archive, by their provenance, and
+    /// aggregates the lifetime totals of its accounts again without its
+    /// transactions. Accounts and the queue are kept.
+    fn purge_records(&self, archive_id: &str) -> impl Future<Output = Result<PurgeReport>> + Send;
+}
+
+impl GraphSink for Graph {
+    async fn upsert_accounts(&self, txs: &[WarehouseTxMaster]) -> Result<BatchTxReturn> {
+        load_tx_cypher::impl_batch_user_insert(self, txs).await
+    }
+
+    async fn upsert_transactions(&self, txs: &[WarehouseTxMaster]) -> Result<u64> {
+        load_tx_cypher::impl_batch_relation_insert(self, txs).await
+    }
+
+    async fn upsert_events(&self, events: &[WarehouseEvent]) -> Result<BatchTxReturn> {
+        load_events::impl_batch_event_insert(self, events).await
+    }
+
+    async fn upsert_balance_deltas(
+        &self,
+        deltas: &[WarehouseBalanceDelta],
+    ) -> Result<BatchTxReturn> {
+        load_balance_deltas::impl_batch_balance_delta_insert(self, deltas).await
+    }
+
+    async fn upsert_snapshots(
+        &self,
+        time: &WarehouseTime,
+        provenance: Option<&Provenance>,
+        snapshots: &[WarehouseAccState],
+    ) -> Result<BatchTxReturn> {
+        load_account_state::impl_batch_snapshot_insert(self, time, provenance, snapshots).await
+    }
+
+    async fn upsert_epochs(&self, epochs: &[WarehouseEpoch]) -> Result<BatchTxReturn> {
+        load_epoch::impl_batch_epoch_insert(self, epochs).await
+    }
+
+    async fn upsert_exchange_orders(&self, orders: &[ExchangeOrder]) -> Result<(u64, u64)> {
+        load_exchange_orders::impl_batch_tx_insert(self, orders).await
+    }
+
+    async fn upsert_user_ledger(&self, ledger: &BalanceTracker, id: u32) -> Result<u64> {
+        ledger.submit_one_id(id, self).await
+    }
+
+    async fn link_owners(&self, owners: &[Whitepages]) -> Result<u64> {
+        enrich_whitepages::impl_batch_tx_insert(self, owners).await
+    }
+
+    async fn link_onramps(&self, onramps: &[ExchangeOnRamp]) -> Result<u64> {
+        enrich_exchange_onboarding::impl_batch_tx_insert(self, onramps).await
+    }
+
+    async fn record_ingest_run(&self, run: &IngestRun) -> Result<()> {
+        ingest_report::impl_ingest_run_insert(self, run).await
+    }
+
+    async fn update_task(&self, archive_id: &str, completed: bool, batch: usize) -> Result<String> {
+        queue::update_task(self, archive_id, completed, batch).await
+    }
+
+    async fn start_task(&self, archive_id: &str, batch: usize) -> Result<()> {
+        queue::start_task(self, archive_id, batch).await
+    }
+
+    async fn mark_dead_letter(
+        &self,
+        archive_id: &str,
+        batch: usize,
+        error: &str,
+        attempts: u32,
+    ) -> Result<()> {
+        queue::mark_dead_letter(self, archive_id, batch, error, attempts).await
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        queue::get_dead_letters(self).await
+    }
+
+    async fn set_all_batches(&self, archive_id: &str, completed: bool) -> Result<u64> {
+        queue::set_all_batches(self, archive_id, completed).await
+    }
+
+    async fn queue_status(&self) -> Result<Vec<QueueStatus>> {
+        queue::get_status(self).await
+    }
+
+    async fn is_batch_complete(&self, archive_id: &str, batch: usize) -> Result<Option<bool>> {
+        queue::is_batch_complete(self, archive_id, batch).await
+    }
+
+    async fn are_all_completed(&self, archive_id: &str) -> Result<bool> {
+        queue::are_all_completed(self, archive_id).await
+    }
+
+    async fn get_queued(&self) -> Result<Vec<String>> {
+        queue::get_queued(self).await
+    }
+
+    async fn clear_queue(&self) -> Result<()> {
+        queue::clear_queue(self).await
+    }
+
+    async fn remove_queued(&self, archive_id: &str) -> Result<u64> {
+        queue::remove_queued(self, archive_id).await
+    }
+
+    async fn purge_records(&self, archive_id: &str) -> Result<PurgeReport> {
+        purge::impl_purge_records(self, archive_id).await
+    }
+}
@@ -0,0 +1,292 @@
+//! Records of each ingest run, for an audit trail of what was loaded.
+//!
+//! An `ingest-all` or `ingest-one` run is saved as an `IngestRun`, with a
+//! `ArchiveReport` of the counts and duration of each archive it loaded. The
+//! run is written to the sink, e.g. as `(:IngestRun)-[:REPORTED]->(:ArchiveReport)`
+//! in Neo4j, and to a JSON report file.
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use neo4rs::Graph;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    batch_tx_type::BatchTxReturn,
+    cypher_templates::{execute_batch_with, json_list_to_bolt, json_to_bolt},
+    graph_sink::GraphSink,
+};
+
+/// CLI arguments which are not saved with a run, since they hold credentials.
+const SECRET_ARGS: [&str; 4] = ["--db-password", "--password", "-p", "--sql-url"];
+
+/// The load of one archive in a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveReport {
+    pub archive_id: String,
+    /// the kind of records, e.g. `transactions`
+    pub contents: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// records decoded from the archive
+    pub extracted: u64,
+    /// the counts of the writes, where `created_tx` is the records created
+    #[serde(flatten)]
+    pub results: BatchTxReturn,
+    /// extracted records which were not created, e.g. since they were
+    /// loaded before, or their batch failed
+    pub mismatch: i64,
+}
+
+impl ArchiveReport {
+    pub fn new(
+        archive_id: &str,
+        contents: &str,
+        started_at: DateTime<Utc>,
+        extracted: u64,
+        results: BatchTxReturn,
+    ) -> Self {
+        let duration_ms = (Utc::now() - started_at).num_milliseconds().max(0) as u64;
+        Self {
+            archive_id: archive_id.to_string(),
+            contents: contents.to_string(),
+            started_at,
+            duration_ms,
+            extracted,
+            mismatch: extracted as i64 - results.created_tx as i64,
+            results,
+        }
+    }
+
+    /// The properties of the `ArchiveReport` node.
+    pub fn to_cypher_json(&self) -> Value {
+        json!({
+            "archive_id": self.archive_id,
+            "contents": self.contents,
+            "started_at": self.started_at.to_rfc3339(),
+            "duration_ms": self.duration_ms,
+            "extracted": self.extracted,
+            "created": self.results.created_tx,
+            "mismatch": self.mismatch,
+            "unique_accounts": self.results.unique_accounts,
+            "created_accounts": self.results.created_accounts,
+            "modified_accounts": self.results.modified_accounts,
+            "unchanged_accounts": self.results.unchanged_accounts,
+        })
+    }
+}
+
+/// The reports of the archives a run loaded before it stopped on an error,
+/// attached to the error as context.
+#[derive(Debug)]
+pub struct PartialReports(pub Vec<ArchiveReport>);
+
+impl fmt::Display for PartialReports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stopped after loading {} archives", self.0.len())
+    }
+}
+
+/// One `ingest-all` or `ingest-one` invocation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestRun {
+    pub run_id: String,
+    /// the subcommand, e.g. `ingest-all`
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub tool_version: String,
+    /// the CLI arguments, without credentials
+    pub args: Vec<String>,
+    pub archive_count: u64,
+    pub archives: Vec<ArchiveReport>,
+    /// the error the run stopped on, if any
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl IngestRun {
+    /// Starts a run of a subcommand, with the arguments of this process.
+    pub fn start(command: &str) -> Self {
+        let started_at = Utc::now();
+        Self {
+            run_id: format!("{}-{}", command, started_at.timestamp_millis()),
+            command: command.to_string(),
+            started_at,
+            finished_at: None,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            args: redact_args(std::env::args()),
+            archive_count: 0,
+            archives: vec![],
+            error: None,
+        }
+    }
+
+    /// Ends the run with the reports of the archives it loaded.
+    pub fn finish(&mut self, archives: Vec<ArchiveReport>) {
+        self.finished_at = Some(Utc::now());
+        self.archive_count = archives.len() as u64;
+        self.archives = archives;
+    }
+
+    /// Ends a run which stopped on an error.
+    pub fn fail(&mut self, error: &anyhow::Error) {
+        self.finished_at = Some(Utc::now());
+        self.error = Some(format!("{:#}", error));
+    }
+
+    /// The properties of the `IngestRun` node.
+    pub fn to_cypher_json(&self) -> Value {
+        json!({
+            "run_id": self.run_id,
+            "command": self.command,
+            "started_at": self.started_at.to_rfc3339(),
+            "finished_at": self.finished_at.map(|t| t.to_rfc3339()),
+            "tool_version": self.tool_version,
+            "args": self.args,
+            "archive_count": self.archive_count,
+            "error": self.error,
+        })
+    }
+
+    /// Writes the run as `ingest_run_<run_id>.json` in a directory,
+    /// returning the file path.
+    pub fn write_json(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir).context(format!("could not create directory {}", dir.display()))?;
+        let path = dir.join(format!("ingest_run_{}.json", self.run_id));
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .context(format!("could not write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Generates a Cypher query which merges the run, with `$run` as its
+    /// properties, and its archive reports from `$batch`.
+    pub fn cypher_insert_str() -> String {
+        r#"
+MERGE (run:IngestRun {run_id: $run.run_id})
+SET
+  run.command = $run.command,
+  run.started_at = datetime($run.started_at),
+  run.finished_at = datetime($run.finished_at),
+  run.tool_version = $run.tool_version,
+  run.args = $run.args,
+  run.archive_count = $run.archive_count,
+  run.error = $run.error
+
+WITH run
+UNWIND $batch AS report
+MERGE (rep:ArchiveReport {run_id: run.run_id, archive_id: report.archive_id})
+SET rep += report
+SET rep.started_at = datetime(report.started_at)
+MERGE (run)-[:REPORTED]->(rep)
+
+RETURN COUNT(rep) AS reports
+"#
+        .to_string()
+    }
+}
+
+/// Replaces the values of credential arguments, in the `--flag value`,
+/// `--flag=value` and, for short flags, `-fvalue` forms.
+pub fn redact_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut redacted = vec![];
+    let mut hide_next = false;
+    for arg in args {
+        if hide_next {
+            redacted.push("***".to_string());
+            hide_next = false;
+            continue;
+        }
+        if let Some((flag, _)) = arg
+            .split_once('=')
+            .filter(|(flag, _)| SECRET_ARGS.contains(flag))
+        {
+            redacted.push(format!("{}=***", flag));
+            continue;
+        }
+        // a short flag with its value attached
+        if let Some(flag) = SECRET_ARGS
+            .iter()
+            .find(|f| !f.starts_with("--") && arg.starts_with(*f) && arg.len() > f.len())
+        {
+            redacted.push(format!("{}***", flag));
+            continue;
+        }
+        hide_next = SECRET_ARGS.contains(&arg.as_str());
+        redacted.push(arg);
+    }
+    redacted
+}
+
+/// Merges a run and its archive reports into Neo4j.
+pub async fn impl_ingest_run_insert(pool: &Graph, run: &IngestRun) -> Result<()> {
+    let reports: Vec<Value> = run.archives.iter().map(|a| a.to_cypher_json()).collect();
+    let row = execute_batch_with(
+        pool,
+        &IngestRun::cypher_insert_str(),
+        json_list_to_bolt(&reports),
+        vec![("run", json_to_bolt(&run.to_cypher_json()))],
+    )
+    .await?;
+
+    let merged: u64 = row.get("reports").context("no reports field")?;
+    info!("archive reports merged: {}", merged);
+    Ok(())
+}
+
+/// Saves a finished run to the sink and to a JSON report in `report_dir`.
+pub async fn save_run(run: &IngestRun, sink: &impl GraphSink, report_dir: &Path) -> Result<()> {
+    sink.record_ingest_run(run).await?;
+    let path = run.write_json(report_dir)?;
+    match &run.error {
+        None => println!(
+            "SUCCESS: run {} loaded {} archives, report: {}",
+            run.run_id,
+            run.archive_count,
+            path.display()
+        ),
+        Some(e) => println!(
+            "FAILED: run {} stopped on error: {}, report: {}",
+            run.run_id,
+            e,
+            path.display()
+        ),
+    }
+    Ok(())
+}
+
+/// Finishes a run with the result of its load and saves it, like
+/// `save_run`. A run which failed is saved with its error, and the reports
+/// of the archives it loaded before it, from the error's [`PartialReports`]
+/// context. The error is then returned; a failure to save the run is only
+/// logged, so the load's error is not hidden.
+pub async fn save_run_result(
+    mut run: IngestRun,
+    result: Result<Vec<ArchiveReport>>,
+    sink: &impl GraphSink,
+    report_dir: &Path,
+) -> Result<()> {
+    match result {
+        Ok(reports) => {
+            run.finish(reports);
+            save_run(&run, sink, report_dir).await
+        }
+        Err(e) => {
+            let partial = e
+                .downcast_ref::<PartialReports>()
+                .map(|p| p.0.clone())
+                .unwrap_or_default();
+            run.finish(partial);
+            run.fail(&e);
+            if let Err(save_err) = save_run(&run, sink, report_dir).await {
+                warn!("could not save failed run {}: {:#}", run.run_id, save_err);
+            }
+            Err(e)
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use crate::{
+    coin_normalize::scale_whole_coins,
     scan::FrameworkVersion,
     schema_transaction::{EntryFunctionArgs, RelationLabel, WarehouseEvent, WarehouseTxMaster},
     unzip_temp::decompress_tar_archive,
-    util::{COIN_DECIMAL_PRECISION, LEGACY_REBASE_MULTIPLIER},
 };
 use chrono::DateTime;
 use diem_crypto::HashValue;
@@ -61,8 +61,20 @@ pub fn decode_transaction_dataview_v5(
         };
 
         let timestamp = t.timestamp_usecs.unwrap_or(0);
-        if let TransactionDataView::UserTransaction { sender, script, .. } = &t.transaction {
+        if let TransactionDataView::UserTransaction {
+            sender,
+            script,
+            sequence_number,
+            max_gas_amount,
+            gas_unit_price,
+            ..
+        } = &t.transaction
+        {
             wtxs.sender = cast_legacy_account(sender)?;
+            wtxs.sequence_number = *sequence_number;
+            wtxs.max_gas_amount = *max_gas_amount;
+            wtxs.gas_unit_price = *gas_unit_price;
+            wtxs.gas_used = t.gas_used;
 
             // must cast from V5 HashValue buffer layout
             wtxs.tx_hash = HashValue::from_slice(t.hash.to_vec())?;
@@ -79,6 +91,7 @@ pub fn decode_transaction_dataview_v5(
             // each .json is not guaranteed to have an epoch change event.
             // tracking epoch change events and incrementing is error prone
             // as the async loader does not guarantee ordered reading of files.
+            wtxs.version = t.version;
             wtxs.block_timestamp = timestamp;
             wtxs.block_datetime =
                 DateTime::from_timestamp_micros(timestamp as i64).expect("get timestamp");
@@ -88,6 +101,16 @@ pub fn decode_transaction_dataview_v5(
                 RelationLabel::Transfer(..) => tx_vec.push(wtxs),
                 RelationLabel::Onboarding(..) => tx_vec.push(wtxs),
                 RelationLabel::Vouch(..) => tx_vec.push(wtxs),
+                // not decoded from v5 scripts
+                RelationLabel::DonorVoice(..)
+                | RelationLabel::DonorVoicePayment(..)
+                | RelationLabel::CommunityWallet(..)
+                | RelationLabel::Multisig(..)
+                | RelationLabel::SlowWallet
+                | RelationLabel::ProofOfFeeBid
+                | RelationLabel::ValidatorRegistration
+                | RelationLabel::VouchRevoke(..)
+                | RelationLabel::Unjail(..) => tx_vec.push(wtxs),
                 RelationLabel::Configuration => {}
                 RelationLabel::Miner => {}
             };
@@ -134,7 +157,7 @@ fn maybe_decode_v5_genesis_function(
             } => {
                 wtx.relation_label = RelationLabel::Transfer(
                     cast_legacy_account(destination)?,
-                    *unscaled_value * COIN_DECIMAL_PRECISION * LEGACY_REBASE_MULTIPLIER,
+                    scale_whole_coins(*unscaled_value),
                 );
 
                 wtx.entry_function = Some(EntryFunctionArgs::V5(sf.to_owned()));
@@ -159,7 +182,7 @@ fn maybe_decode_v5_genesis_function(
             } => {
                 wtx.relation_label = RelationLabel::Onboarding(
                     cast_legacy_account(account)?,
-                    *unscaled_value * COIN_DECIMAL_PRECISION * LEGACY_REBASE_MULTIPLIER,
+                    scale_whole_coins(*unscaled_value),
                 );
             }
             ScriptFunctionCallGenesis::CreateValidatorAccount {
@@ -210,7 +233,7 @@ fn maybe_decode_v520_function(
             } => {
                 wtx.relation_label = RelationLabel::Transfer(
                     cast_legacy_account(destination)?,
-                    *unscaled_value * COIN_DECIMAL_PRECISION * LEGACY_REBASE_MULTIPLIER,
+                    scale_whole_coins(*unscaled_value),
                 );
 
                 wtx.entry_function = Some(EntryFunctionArgs::V520(sf.to_owned()));
@@ -1,13 +1,13 @@
 use crate::{
+    graph_sink::GraphSink,
     json_rescue_v5_extract::{
         decompress_to_temppath, extract_v5_json_rescue, list_all_json_files, list_all_tgz_archives,
     },
     load_tx_cypher::tx_batch,
-    queue::{self},
+    provenance::{sha256_file, Provenance},
 };
 use anyhow::Result;
 use log::{error, info, trace, warn};
-use neo4rs::Graph;
 use std::sync::Arc;
 use std::{path::Path, thread::available_parallelism};
 use tokio::sync::Semaphore;
@@ -18,7 +18,10 @@ use tokio::sync::Semaphore;
 static QUERY_BATCH_SIZE: usize = 250;
 
 /// Decompresses a `.tgz` archive and extracts/loads all contained V5 JSON transactions.
-pub async fn single_thread_decompress_extract(tgz_file: &Path, pool: &Graph) -> Result<u64> {
+pub async fn single_thread_decompress_extract(
+    tgz_file: &Path,
+    sink: &impl GraphSink,
+) -> Result<u64> {
     let temppath = decompress_to_temppath(tgz_file)?;
     // for caching the archive
     let tgz_filename = tgz_file
@@ -28,6 +31,8 @@ pub async fn single_thread_decompress_extract(tgz_file: &Path, pool: &Graph) ->
         .unwrap();
 
     let json_vec = list_all_json_files(temppath.path())?;
+    // the listed files have canonical paths
+    let temp_root = temppath.path().canonicalize()?;
 
     let mut found_count = 0u64;
     let mut created_count = 0u64;
@@ -38,7 +43,7 @@ pub async fn single_thread_decompress_extract(tgz_file: &Path, pool: &Graph) ->
     for j in json_vec {
         let archive_id = j.file_name().unwrap().to_str().unwrap();
         // checks for .json cases remaining where we were interrupted mid .tgz archive.
-        let complete = queue::are_all_completed(pool, archive_id).await?;
+        let complete = sink.are_all_completed(archive_id).await?;
         if complete {
             trace!(
                 "skip parsing {}, this file was loaded successfully",
@@ -47,7 +52,22 @@ pub async fn single_thread_decompress_extract(tgz_file: &Path, pool: &Graph) ->
             continue;
         }
 
-        let (records, _, unique) = extract_v5_json_rescue(&j)?;
+        let (mut records, _, unique) = extract_v5_json_rescue(&j)?;
+
+        // the rescue archives have no manifest, so the path is of the .json
+        // file within its .tgz
+        let inner_path = j.strip_prefix(&temp_root).unwrap_or(&j);
+        let provenance = Arc::new(Provenance {
+            archive_id: archive_id.to_owned(),
+            manifest_path: Path::new(tgz_filename)
+                .join(inner_path)
+                .to_string_lossy()
+                .into_owned(),
+            chunk_sha256: sha256_file(&j)?,
+        });
+        for r in records.iter_mut() {
+            r.provenance = Some(provenance.clone());
+        }
 
         unique.iter().for_each(|f| {
             if !unique_functions.contains(f) {
@@ -55,10 +75,10 @@ pub async fn single_thread_decompress_extract(tgz_file: &Path, pool: &Graph) ->
             }
         });
 
-        let res = tx_batch(&records, pool, QUERY_BATCH_SIZE, archive_id).await?;
+        let res = tx_batch(&records, sink, QUERY_BATCH_SIZE, archive_id).await?;
         created_count += res.created_tx as u64;
         found_count += records.len() as u64;
-        queue::update_task(pool, tgz_filename, true, 0).await?;
+        sink.update_task(tgz_filename, true, 0).await?;
     }
     if found_count > 0 && created_count > 0 {
         info!("V5 transactions found: {}", found_count);
@@ -77,9 +97,9 @@ pub async fn single_thread_decompress_extract(tgz_file: &Path, pool: &Graph) ->
 }
 
 /// Concurrently processes multiple `.tgz` archives using a limited number of threads.
-pub async fn rip_concurrent_limited(
+pub async fn rip_concurrent_limited<S: GraphSink + Clone + Send + 'static>(
     start_dir: &Path,
-    pool: &Graph,
+    sink: &S,
     threads: Option<usize>,
 ) -> Result<u64> {
     let threads = threads.unwrap_or(available_parallelism().unwrap().get());
@@ -93,14 +113,14 @@ pub async fn rip_concurrent_limited(
     let mut tasks = vec![];
 
     for (n, tgz_path) in tgz_list.into_iter().enumerate() {
-        let pool = pool.clone(); // Clone pool for each task
+        let sink = sink.clone(); // Clone sink for each task
 
         let tgz_filename = tgz_path
             .file_name()
             .expect("could not find .tgz filename")
             .to_str()
             .unwrap();
-        if queue::are_all_completed(&pool, tgz_filename).await? {
+        if sink.are_all_completed(tgz_filename).await? {
             info!("skipping, archive already loaded: {}", tgz_filename);
             continue;
         }
@@ -111,7 +131,7 @@ pub async fn rip_concurrent_limited(
             let _permit = semaphore.acquire().await; // Acquire semaphore permit
             info!("PROGRESS: {n}/{archives_count}");
 
-            single_thread_decompress_extract(&tgz_path, &pool).await // Perform the task
+            single_thread_decompress_extract(&tgz_path, &sink).await // Perform the task
         });
 
         tasks.push(task);
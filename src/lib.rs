@@ -5,26 +5,47 @@
 
 pub mod analytics;
 pub mod batch_tx_type;
+pub mod bulk_import;
+pub mod coin_normalize;
 pub mod cypher_templates;
+pub mod db_doctor;
 pub mod decode_entry_function;
 pub mod enrich_exchange_onboarding;
 pub mod enrich_whitepages;
+pub mod extract_balance_deltas;
+pub mod extract_epoch_ending;
 pub mod extract_exchange_orders;
 pub mod extract_snapshot;
 pub mod extract_transactions;
+pub mod file_sink;
+pub mod graph_migrations;
+pub mod graph_sink;
+pub mod ingest_report;
 pub mod json_rescue_v5_extract;
 pub mod json_rescue_v5_load;
+pub mod lifetime;
 pub mod load;
 pub mod load_account_state;
+pub mod load_balance_deltas;
+pub mod load_epoch;
+pub mod load_events;
 pub mod load_exchange_orders;
 pub mod load_tx_cypher;
+pub mod memory_graph;
+pub mod migrate_tx_relations;
 pub mod neo4j_init;
+pub mod provenance;
+pub mod purge;
 pub mod queue;
 pub mod read_tx_chunk;
+pub mod retry;
 pub mod scan;
 pub mod schema_account_state;
+pub mod schema_balance_delta;
+pub mod schema_epoch;
 pub mod schema_exchange_orders;
 pub mod schema_transaction;
+pub mod sql_sink;
 pub mod unzip_temp;
 pub mod util;
 pub mod v5_rpc_to_raw;
@@ -0,0 +1,188 @@
+//! `Lifetime` totals of the coins sent between two accounts.
+//!
+//! A `(from)-[:Lifetime]->(to)` relationship sums the transactions with
+//! coins from one account to another: their `coins`, `tx_count`, the
+//! `first_seen` and `last_seen` block times, and the `min_coins` and
+//! `max_coins` of a single transaction. `coins_normalized` is the exact sum
+//! in current base units (see `coin_normalize`); it is null if any of the
+//! transactions was loaded without a `coins_normalized`. Only successful
+//! transactions are summed: failed ones, loaded with `--include-failed`,
+//! keep the coins they tried to send, but moved none.
+//!
+//! The batch writer only adds a transaction to the totals when its edge is
+//! created, so loading an archive again, or retrying a batch, does not count
+//! it twice. Totals written by older versions, which added the coins on each
+//! `MERGE`, are fixed by `rebuild-lifetime`, which aggregates them again from
+//! the transaction edges.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use neo4rs::{query, Graph};
+use serde::{Deserialize, Serialize};
+
+use crate::schema_transaction::TX_RELATION_TYPES;
+
+/// The totals of the transactions with coins between two accounts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LifetimeTotal {
+    pub coins: f64,
+    /// the exact sum in current base units, if all the transactions have one
+    pub coins_normalized: Option<u64>,
+    pub tx_count: u64,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub min_coins: Option<f64>,
+    pub max_coins: Option<f64>,
+}
+
+impl LifetimeTotal {
+    /// Adds a transaction of `coins`, or `coins_normalized` base units, sent
+    /// at `seen`.
+    pub fn add(&mut self, coins: f64, coins_normalized: Option<u64>, seen: DateTime<Utc>) {
+        self.coins_normalized = match self.tx_count {
+            0 => coins_normalized,
+            _ => self
+                .coins_normalized
+                .zip(coins_normalized)
+                .map(|(a, b)| a + b),
+        };
+        self.coins += coins;
+        self.tx_count += 1;
+        self.first_seen = Some(self.first_seen.map_or(seen, |t| t.min(seen)));
+        self.last_seen = Some(self.last_seen.map_or(seen, |t| t.max(seen)));
+        self.min_coins = Some(self.min_coins.map_or(coins, |c| c.min(coins)));
+        self.max_coins = Some(self.max_coins.map_or(coins, |c| c.max(coins)));
+    }
+}
+
+/// The transaction relationship types, as a Cypher type disjunction, e.g.
+/// `Transfer|Vouch`.
+pub fn tx_relation_types_cypher() -> String {
+    TX_RELATION_TYPES.join("|")
+}
+
+/// The relationship types summed into `Lifetime` totals: the transaction
+/// types, and the legacy `Tx` type of graphs which were not migrated yet.
+/// `migrate-tx-relations` moves each edge in a single query, so an edge is
+/// never counted under both types.
+pub fn lifetime_relation_types_cypher() -> String {
+    format!("{}|Tx", tx_relation_types_cypher())
+}
+
+/// Checks if a transaction of this VM status moved its coins. Edges loaded
+/// before the status was recorded have none, and were all successful.
+pub fn is_success(status: Option<&str>) -> bool {
+    match status {
+        Some(s) => s == "Success",
+        None => true,
+    }
+}
+
+/// A Cypher subquery which aggregates the `Lifetime` total of each `from`
+/// and `to` pair in scope from their transaction edges. The total is
+/// replaced, or deleted if no transactions with coins are left. Returns
+/// `totals`, 1 if the pair has a total.
+pub fn cypher_aggregate_pair_str() -> String {
+    let relation_types = lifetime_relation_types_cypher();
+    format!(
+        r#"
+CALL {{
+  WITH from, to
+  OPTIONAL MATCH (from)-[rel:{relation_types}]->(to)
+  WHERE rel.coins > 0 AND COALESCE(rel.status, "Success") = "Success"
+  WITH from, to,
+    count(rel) AS tx_count,
+    sum(rel.coins) AS coins,
+    CASE WHEN count(rel.coins_normalized) = count(rel)
+      THEN sum(rel.coins_normalized) END AS coins_normalized,
+    min(rel.block_datetime) AS first_seen,
+    max(rel.block_datetime) AS last_seen,
+    min(rel.coins) AS min_coins,
+    max(rel.coins) AS max_coins
+  OPTIONAL MATCH (from)-[old:Lifetime]->(to)
+  WITH from, to, tx_count, coins, coins_normalized, first_seen, last_seen, min_coins, max_coins,
+    collect(old) AS old_totals
+  FOREACH (old IN CASE WHEN tx_count = 0 THEN old_totals ELSE [] END | DELETE old)
+  FOREACH (_ IN CASE WHEN tx_count > 0 THEN [1] ELSE [] END |
+    MERGE (from)-[total:Lifetime]->(to)
+    SET
+      total.coins = coins,
+      total.coins_normalized = coins_normalized,
+      total.tx_count = tx_count,
+      total.first_seen = first_seen,
+      total.last_seen = last_seen,
+      total.min_coins = min_coins,
+      total.max_coins = max_coins
+  )
+  RETURN CASE WHEN tx_count > 0 THEN 1 ELSE 0 END AS totals
+}}
+"#
+    )
+}
+
+/// Aggregates the `Lifetime` totals of the accounts in `$addresses` again,
+/// for all the accounts they sent coins to, or had a total with.
+pub fn cypher_rebuild_lifetime_str() -> String {
+    let relation_types = lifetime_relation_types_cypher();
+    format!(
+        r#"
+UNWIND $addresses AS address
+MATCH (from:Account {{address: address}})-[:{relation_types}|Lifetime]->(to:Account)
+WITH DISTINCT from, to
+{}
+RETURN count(*) AS pairs, sum(totals) AS totals
+"#,
+        cypher_aggregate_pair_str()
+    )
+}
+
+/// Rebuilds the `Lifetime` totals sent by some accounts from their
+/// transaction edges. Returns the number of totals.
+pub async fn rebuild_lifetime_of(pool: &Graph, addresses: Vec<String>) -> Result<u64> {
+    let mut res = pool
+        .execute(query(&cypher_rebuild_lifetime_str()).param("addresses", addresses))
+        .await
+        .context("execute query error")?;
+    let row = res.next().await?.context("no row returned")?;
+    Ok(row.get("totals").context("no totals field")?)
+}
+
+/// Rebuilds all the `Lifetime` totals from the transaction edges, paging
+/// through the senders by address, `batch_size` at a time. The rebuild can
+/// be interrupted and run again. Returns the number of totals.
+pub async fn rebuild_lifetime(pool: &Graph, batch_size: usize) -> Result<u64> {
+    let page_query = r#"
+MATCH (a:Account)
+WHERE a.address > $after
+RETURN a.address AS address
+ORDER BY address
+LIMIT $limit
+"#;
+    let mut after = String::new();
+    let mut total = 0;
+    loop {
+        let mut res = pool
+            .execute(
+                query(page_query)
+                    .param("after", after.clone())
+                    .param("limit", batch_size as i64),
+            )
+            .await
+            .context("execute query error")?;
+        let mut addresses: Vec<String> = vec![];
+        while let Some(row) = res.next().await? {
+            addresses.push(row.get("address").context("no address field")?);
+        }
+        let Some(last) = addresses.last().cloned() else {
+            break;
+        };
+
+        let totals = rebuild_lifetime_of(pool, addresses).await?;
+        total += totals;
+        info!("lifetime totals rebuilt up to {}: {}", last, totals);
+
+        after = last;
+    }
+
+    Ok(total)
+}
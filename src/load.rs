@@ -1,35 +1,91 @@
 use crate::{
     batch_tx_type::BatchTxReturn,
-    extract_snapshot::{extract_current_snapshot, extract_v5_snapshot},
+    extract_epoch_ending::extract_epoch_ending,
+    extract_snapshot::{
+        stream_current_snapshot, stream_v5_snapshot, SnapshotDecoder, SnapshotStream,
+    },
     extract_transactions::extract_current_transactions,
+    graph_sink::GraphSink,
+    ingest_report::{ArchiveReport, PartialReports},
     load_account_state::snapshot_batch,
+    load_balance_deltas::balance_delta_batch,
+    load_epoch::epoch_batch,
+    load_events::event_batch,
     load_tx_cypher,
-    queue::{self, clear_queue, push_queue_from_archive_map},
-    scan::{ArchiveMap, ManifestInfo},
+    queue::{get_queued_archives, push_queue_from_archive_map},
+    scan::{ArchiveMap, BundleContent, FrameworkVersion, ManifestInfo},
+    schema_balance_delta::WarehouseBalanceDelta,
+    schema_epoch::WarehouseEpoch,
+    schema_transaction::{WarehouseEvent, WarehouseTxMaster},
     unzip_temp,
 };
 
 use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use diem_temppath::TempPath;
 use log::{error, info, warn};
-use neo4rs::Graph;
+use std::{sync::Arc, thread::available_parallelism};
+use tokio::{
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+
+/// The decoded snapshot chunks which the extract task keeps ahead of the
+/// batches the writer loads.
+const SNAPSHOT_CHUNKS_AHEAD: usize = 1;
+
+/// The records extracted from one archive, ready to load.
+pub enum ArchiveRecords {
+    /// read one chunk file at a time, as the batches are loaded
+    Snapshots(SnapshotStream),
+    Transactions {
+        txs: Vec<WarehouseTxMaster>,
+        events: Vec<WarehouseEvent>,
+        balance_deltas: Vec<WarehouseBalanceDelta>,
+    },
+    Epochs(Vec<WarehouseEpoch>),
+}
 
-/// takes all the archives from a map, and tries to load them sequentially
+/// An archive which was decompressed and extracted, waiting to be loaded.
+struct ExtractedArchive {
+    man: ManifestInfo,
+    records: ArchiveRecords,
+    /// the decompressed files are removed once this is dropped
+    _temp: Option<TempPath>,
+    /// released once the writer is done with the records
+    _permit: OwnedSemaphorePermit,
+}
+
+/// takes all the archives from a map, and loads them.
+///
+/// Up to `threads` archives are decompressed and extracted in parallel,
+/// defaulting to the available parallelism. The extracted records are sent
+/// over a channel to a single writer, so decoding overlaps with the database
+/// writes. The chunk files of a snapshot are decoded by its extract task
+/// while the writer loads the batches, a chunk ahead of it. An archive holds
+/// its extraction slot until it is written, so at most `threads` archives
+/// are in memory, extracting, waiting or being written. If a write fails,
+/// the extractions still running are aborted.
+/// Returns the reports of the archives loaded. On an error, the reports of
+/// the archives loaded before it are attached as [`PartialReports`] context.
 pub async fn ingest_all(
     archive_map: &ArchiveMap,
-    pool: &Graph,
+    sink: &impl GraphSink,
     force_queue: bool,
     batch_size: usize,
-) -> Result<()> {
+    include_failed: bool,
+    threads: Option<usize>,
+) -> Result<Vec<ArchiveReport>> {
     // clear the queue and enqueue all these jobs
     if force_queue {
         warn!(
             "clearing load queue, and enqueueing all archives, count: {}",
             archive_map.0.len()
         );
-        clear_queue(pool).await.context("could not clear queue")?;
+        sink.clear_queue().await.context("could not clear queue")?;
         // NOTE: this does not infer batches. That is done at the actual
         // load controller level.
-        push_queue_from_archive_map(archive_map, pool)
+        push_queue_from_archive_map(archive_map, sink)
             .await
             .context("could not push queue")?;
     }
@@ -38,72 +94,256 @@ pub async fn ingest_all(
     // don't bother extracting archives which we loaded successfully prior
     // Note that the inner tx_batch will also check if anything has already
     // been inserted perhaps concurrently to the start of this process.
-    // get queue of any batch which has any incomplete batches
-    let pending = queue::get_queued(pool).await?;
+    // get queue of any batch which has any incomplete batches, including
+    // the events and balance changes queued apart from their archive
+    let pending = get_queued_archives(sink).await?;
     info!("pending archives: {}", pending.len());
 
-    // This manifest may be for a .gz file, we should handle here as well
-    for (_p, m) in archive_map.0.iter() {
-        println!(
-            "\nProcessing: {:?} with archive: {}",
-            m.contents,
-            m.archive_dir.display()
-        );
+    let threads = threads.unwrap_or(available_parallelism()?.get());
+    info!("concurrent threads used: {}", threads);
 
-        let complete = queue::are_all_completed(pool, &m.archive_id).await?;
+    let semaphore = Arc::new(Semaphore::new(threads)); // Semaphore to limit concurrency
+    let (tx, mut rx) = mpsc::channel::<Result<ExtractedArchive>>(threads);
+    // dropping the set, e.g. when the writer returns an error, aborts the tasks
+    let mut tasks = JoinSet::new();
 
-        if !complete {
-            info!("checking if we need to decompress");
-            let (new_unzip_path, temp) = unzip_temp::maybe_handle_gz(&m.archive_dir)?;
-            let mut better_man = ManifestInfo::new(&new_unzip_path);
-            better_man.set_info()?;
-
-            let batch_tx_return = try_load_one_archive(&better_man, pool, batch_size).await?;
-            println!("SUCCESS: {}", batch_tx_return);
-            drop(temp);
-        } else {
+    // This manifest may be for a .gz file, which is handled in the extract task
+    for (_p, m) in archive_map.0.iter() {
+        let complete =
+            !pending.contains(&m.archive_id) && sink.are_all_completed(&m.archive_id).await?;
+        if complete {
             info!(
                 "archive complete (or not in queue): {}",
                 m.archive_dir.display()
             );
+            continue;
+        }
+
+        let semaphore = Arc::clone(&semaphore);
+        let tx = tx.clone();
+        let m = m.clone();
+        tasks.spawn(async move {
+            // the permit moves with the records, and is released once the
+            // writer drops them
+            let Ok(permit) = semaphore.acquire_owned().await else {
+                return;
+            };
+            let (res, decoder) = match decompress_extract(&m, include_failed, permit).await {
+                Ok(extracted) => {
+                    let (records, decoder) = split_snapshot_decoder(extracted.records);
+                    (
+                        Ok(ExtractedArchive {
+                            records,
+                            ..extracted
+                        }),
+                        decoder,
+                    )
+                }
+                Err(e) => (Err(e), None),
+            };
+            // the receiver is gone if the writer stopped on an error
+            if tx.send(res).await.is_err() {
+                return;
+            }
+            // snapshot chunks are decoded here, and sent to the stream the
+            // writer takes the batches from
+            if let Some(decoder) = decoder {
+                decoder.run().await;
+            }
+        });
+    }
+    // the channel closes once all the extract tasks are done
+    drop(tx);
+
+    let mut reports = vec![];
+    let written: Result<()> = async {
+        while let Some(res) = rx.recv().await {
+            let ExtractedArchive {
+                man,
+                records,
+                _temp,
+                _permit,
+            } = res?;
+            info!(
+                "processing: {:?} with archive: {}",
+                man.contents,
+                man.archive_dir.display()
+            );
+
+            let report = load_archive_records(records, &man.archive_id, sink, batch_size).await?;
+            info!("SUCCESS: {}", report.results);
+            reports.push(report);
+        }
+
+        // the channel closed, so the tasks are done, but one may have panicked
+        while let Some(joined) = tasks.join_next().await {
+            joined.context("archive extraction task failed")?;
+        }
+        Ok(())
+    }
+    .await;
+    // the archives loaded before the error are still in the database
+    if let Err(e) = written {
+        return Err(e.context(PartialReports(reports)));
+    }
+
+    // batches which still failed after their retries are picked up by the next run
+    let dead_letters = sink.get_dead_letters().await?;
+    if !dead_letters.is_empty() {
+        warn!(
+            "{} batches failed after their retries, run ingest-all again to retry them",
+            dead_letters.len()
+        );
+        for d in dead_letters {
+            warn!(
+                "dead letter: {} batch #{} after {} attempts: {}",
+                d.archive_id, d.batch, d.attempts, d.error
+            );
         }
     }
 
-    Ok(())
+    Ok(reports)
+}
+
+/// Decompresses an archive if needed, and extracts its records, which keep
+/// the extraction `permit`.
+async fn decompress_extract(
+    m: &ManifestInfo,
+    include_failed: bool,
+    permit: OwnedSemaphorePermit,
+) -> Result<ExtractedArchive> {
+    info!("checking if we need to decompress");
+    let (new_unzip_path, temp) = unzip_temp::maybe_handle_gz(&m.archive_dir)?;
+    let mut better_man = ManifestInfo::new(&new_unzip_path);
+    better_man.set_info()?;
+
+    let records = extract_archive(&better_man, include_failed).await?;
+    Ok(ExtractedArchive {
+        man: better_man,
+        records,
+        _temp: temp,
+        _permit: permit,
+    })
+}
+
+/// Splits the decoding from the records of a snapshot, so it runs in the
+/// extract task rather than in the writer.
+fn split_snapshot_decoder(records: ArchiveRecords) -> (ArchiveRecords, Option<SnapshotDecoder>) {
+    match records {
+        ArchiveRecords::Snapshots(snaps) => {
+            let (snaps, decoder) = snaps.with_decoder(SNAPSHOT_CHUNKS_AHEAD);
+            (ArchiveRecords::Snapshots(snaps), Some(decoder))
+        }
+        records => (records, None),
+    }
 }
 
 /// Attempts to load a single archive into the database based on its manifest type.
+/// Failed transactions are skipped unless `include_failed` is set.
 pub async fn try_load_one_archive(
     man: &ManifestInfo,
-    pool: &Graph,
+    sink: &impl GraphSink,
     batch_size: usize,
+    include_failed: bool,
 ) -> Result<BatchTxReturn> {
-    let mut all_results = BatchTxReturn::new();
-    match man.contents {
-        crate::scan::BundleContent::Unknown => todo!(),
-        crate::scan::BundleContent::StateSnapshot => {
+    Ok(
+        load_one_archive_report(man, sink, batch_size, include_failed)
+            .await?
+            .results,
+    )
+}
+
+/// Loads a single archive like `try_load_one_archive`, returning the report
+/// of the load.
+pub async fn load_one_archive_report(
+    man: &ManifestInfo,
+    sink: &impl GraphSink,
+    batch_size: usize,
+    include_failed: bool,
+) -> Result<ArchiveReport> {
+    let records = extract_archive(man, include_failed).await?;
+    load_archive_records(records, &man.archive_id, sink, batch_size).await
+}
+
+/// Extracts the records of a single archive based on its manifest type.
+/// Failed transactions are skipped unless `include_failed` is set.
+pub async fn extract_archive(man: &ManifestInfo, include_failed: bool) -> Result<ArchiveRecords> {
+    let records = match man.contents {
+        BundleContent::Unknown => bail!("unknown archive contents: {}", man.archive_dir.display()),
+        BundleContent::StateSnapshot => {
             let snaps = match man.version {
-                crate::scan::FrameworkVersion::Unknown => {
+                FrameworkVersion::Unknown => {
                     error!("no framework version detected");
                     bail!("could not load archive from manifest");
                 }
-                crate::scan::FrameworkVersion::V5 => extract_v5_snapshot(&man.archive_dir).await?,
-                crate::scan::FrameworkVersion::V6 => {
-                    extract_current_snapshot(&man.archive_dir).await?
-                }
-                crate::scan::FrameworkVersion::V7 => {
-                    extract_current_snapshot(&man.archive_dir).await?
-                }
+                FrameworkVersion::V5 => stream_v5_snapshot(&man.archive_dir).await?,
+                FrameworkVersion::V6 => stream_current_snapshot(&man.archive_dir).await?,
+                FrameworkVersion::V7 => stream_current_snapshot(&man.archive_dir).await?,
             };
-            snapshot_batch(&snaps, pool, batch_size, &man.archive_id).await?;
+            ArchiveRecords::Snapshots(snaps)
+        }
+        BundleContent::Transaction => {
+            let (txs, events, balance_deltas) =
+                extract_current_transactions(&man.archive_dir, &man.version, include_failed)
+                    .await?;
+            ArchiveRecords::Transactions {
+                txs,
+                events,
+                balance_deltas,
+            }
+        }
+        BundleContent::EpochEnding => {
+            ArchiveRecords::Epochs(extract_epoch_ending(&man.archive_dir).await?)
+        }
+    };
+    Ok(records)
+}
+
+/// Loads the extracted records of an archive in batches, tracking them in
+/// the queue under `archive_id`. The report has the duration of the load,
+/// without the extraction.
+pub async fn load_archive_records(
+    records: ArchiveRecords,
+    archive_id: &str,
+    sink: &impl GraphSink,
+    batch_size: usize,
+) -> Result<ArchiveReport> {
+    let started_at = Utc::now();
+    let mut all_results = BatchTxReturn::new();
+    let (contents, extracted) = match records {
+        ArchiveRecords::Snapshots(mut snaps) => {
+            let batch_res = snapshot_batch(&mut snaps, sink, batch_size, archive_id).await?;
+            all_results.increment(&batch_res);
+            ("snapshots", snaps.decoded())
         }
-        crate::scan::BundleContent::Transaction => {
-            let (txs, _) = extract_current_transactions(&man.archive_dir, &man.version).await?;
-            let batch_res =
-                load_tx_cypher::tx_batch(&txs, pool, batch_size, &man.archive_id).await?;
+        ArchiveRecords::Transactions {
+            txs,
+            events,
+            balance_deltas,
+        } => {
+            // NOTE: events and balance deltas are loaded before the transactions, since the
+            // archive is considered done once the transaction batches are.
+            let event_res = event_batch(&events, sink, batch_size, archive_id).await?;
+            info!("events merged: {}", event_res.created_tx);
+            let delta_res =
+                balance_delta_batch(&balance_deltas, sink, batch_size, archive_id).await?;
+            info!("balance deltas merged: {}", delta_res.created_tx);
+
+            let batch_res = load_tx_cypher::tx_batch(&txs, sink, batch_size, archive_id).await?;
             all_results.increment(&batch_res);
+            ("transactions", txs.len() as u64)
         }
-        crate::scan::BundleContent::EpochEnding => todo!(),
-    }
-    Ok(all_results)
+        ArchiveRecords::Epochs(epochs) => {
+            let batch_res = epoch_batch(&epochs, sink, batch_size, archive_id).await?;
+            all_results.increment(&batch_res);
+            ("epochs", epochs.len() as u64)
+        }
+    };
+    Ok(ArchiveReport::new(
+        archive_id,
+        contents,
+        started_at,
+        extracted,
+        all_results,
+    ))
 }
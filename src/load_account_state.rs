@@ -1,33 +1,45 @@
-use std::{thread, time::Duration};
-
-use crate::{batch_tx_type::BatchTxReturn, queue, schema_account_state::WarehouseAccState};
+use crate::{
+    batch_tx_type::BatchTxReturn,
+    cypher_templates::execute_batch_with,
+    extract_snapshot::SnapshotStream,
+    graph_sink::GraphSink,
+    provenance::{provenance_to_cypher_map, Provenance},
+    retry::{load_batch, RetryPolicy},
+    schema_account_state::{WarehouseAccState, WarehouseTime},
+};
 use anyhow::{Context, Result};
-use log::{error, info, warn};
+use log::info;
 use neo4rs::Graph;
 
 /// Batches and loads account states from a snapshot into the database.
 ///
-/// Uses the queue system to ensure resume capability.
+/// Uses the queue system to ensure resume capability. Accounts are taken
+/// from the stream one batch at a time, and a batch never spans two chunk
+/// files of the archive.
 pub async fn snapshot_batch(
-    txs: &[WarehouseAccState],
-    pool: &Graph,
+    snapshots: &mut SnapshotStream,
+    sink: &impl GraphSink,
     batch_size: usize,
     archive_id: &str,
 ) -> Result<BatchTxReturn> {
     let mut all_results = BatchTxReturn::new();
-
-    let chunks: Vec<&[WarehouseAccState]> = txs.chunks(batch_size).collect();
+    let policy = RetryPolicy::from_env();
 
     info!("archive: {}", archive_id);
 
-    for (i, c) in chunks.into_iter().enumerate() {
+    let mut next_index = 0;
+    let mut accounts = 0;
+    while let Some(c) = snapshots.next_batch(batch_size).await? {
+        let i = next_index;
+        next_index += 1;
+        accounts += c.accounts.len();
         info!("batch #{}", i);
         // double checking the status of the loading PER BATCH
         // it could have been updated in the interim
         // since the outer check in ingest_all, just checks
         // all things completed prior to this run
         // check if this is already completed, or should be inserted.
-        match queue::is_batch_complete(pool, archive_id, i).await {
+        match sink.is_batch_complete(archive_id, i).await {
             Ok(Some(true)) => {
                 info!("...skipping, all batches loaded.");
                 // skip this one
@@ -40,45 +52,49 @@ pub async fn snapshot_batch(
                 info!("...batch not found in queue, adding to queue.");
 
                 // no task found in db, add to queue
-                queue::update_task(pool, archive_id, false, i).await?;
+                sink.update_task(archive_id, false, i).await?;
             }
         }
         info!("...loading to db");
 
-        match impl_batch_snapshot_insert(pool, c).await {
-            Ok(batch) => {
-                all_results.increment(&batch);
-                queue::update_task(pool, archive_id, true, i).await?;
-                info!("...success");
-            }
-            Err(e) => {
-                let secs = 10;
-                error!("skipping batch, could not insert: {:?}", e);
-                warn!("waiting {} secs before retrying connection", secs);
-                thread::sleep(Duration::from_secs(secs));
-            }
-        };
+        if let Some(batch) = load_batch(sink, &policy, archive_id, i, || {
+            sink.upsert_snapshots(&snapshots.time, c.provenance.as_deref(), &c.accounts)
+        })
+        .await?
+        {
+            all_results.increment(&batch);
+        }
     }
 
+    info!("accounts in snapshot: {}", accounts);
+
     Ok(all_results)
 }
 
 /// Executes a batch insertion of account states into Neo4j.
+///
+/// The snapshot time and provenance are sent once as the `$time` and
+/// `$provenance` parameters.
 pub async fn impl_batch_snapshot_insert(
     pool: &Graph,
+    time: &WarehouseTime,
+    provenance: Option<&Provenance>,
     batch_snapshots: &[WarehouseAccState],
 ) -> Result<BatchTxReturn> {
-    let list_str = WarehouseAccState::to_cypher_map(batch_snapshots);
-    let cypher_string = WarehouseAccState::cypher_batch_insert_str(&list_str);
+    let batch = WarehouseAccState::to_cypher_map(batch_snapshots);
+    let cypher_string = WarehouseAccState::cypher_batch_insert_str();
 
     // Execute the query
-    let cypher_query = neo4rs::query(&cypher_string);
-    let mut res = pool
-        .execute(cypher_query)
-        .await
-        .context("execute query error")?;
-
-    let row = res.next().await?.context("no row returned")?;
+    let row = execute_batch_with(
+        pool,
+        &cypher_string,
+        batch,
+        vec![
+            ("time", time.to_cypher_map()),
+            ("provenance", provenance_to_cypher_map(provenance)),
+        ],
+    )
+    .await?;
 
     let merged_snapshots: u64 = row
         .get("merged_snapshots")
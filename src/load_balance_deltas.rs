@@ -0,0 +1,87 @@
+use crate::{
+    batch_tx_type::BatchTxReturn,
+    cypher_templates::execute_batch,
+    graph_sink::GraphSink,
+    retry::{load_batch, RetryPolicy},
+    schema_balance_delta::WarehouseBalanceDelta,
+};
+use anyhow::{Context, Result};
+use log::info;
+use neo4rs::Graph;
+
+/// Batches and loads the balance changes of an archive's write sets into the database.
+///
+/// Like events, these get their own queue entries, so they can be resumed
+/// independently of the transactions from the same archive.
+pub async fn balance_delta_batch(
+    deltas: &[WarehouseBalanceDelta],
+    sink: &impl GraphSink,
+    batch_size: usize,
+    archive_id: &str,
+) -> Result<BatchTxReturn> {
+    let mut all_results = BatchTxReturn::new();
+    let policy = RetryPolicy::from_env();
+
+    let queue_id = balance_delta_queue_id(archive_id);
+    info!("archive: {}", queue_id);
+
+    if deltas.is_empty() {
+        // mark as complete so we don't retry
+        sink.update_task(&queue_id, true, 0).await?;
+    }
+
+    for (i, c) in deltas.chunks(batch_size).enumerate() {
+        info!("batch #{}", i);
+
+        match sink.is_batch_complete(&queue_id, i).await {
+            Ok(Some(true)) => {
+                info!("...skipping, all batches loaded.");
+                continue;
+            }
+            Ok(Some(false)) => {
+                // keep going
+            }
+            _ => {
+                info!("...batch not found in queue, adding to queue.");
+                sink.update_task(&queue_id, false, i).await?;
+            }
+        }
+        info!("...loading to db");
+
+        if let Some(batch) = load_batch(sink, &policy, &queue_id, i, || {
+            sink.upsert_balance_deltas(c)
+        })
+        .await?
+        {
+            all_results.increment(&batch);
+        }
+    }
+
+    Ok(all_results)
+}
+
+/// The queue identifier for the balance changes of an archive.
+pub fn balance_delta_queue_id(archive_id: &str) -> String {
+    format!("{}_balance_deltas", archive_id)
+}
+
+/// Executes a batch insertion of balance deltas into Neo4j.
+pub async fn impl_batch_balance_delta_insert(
+    pool: &Graph,
+    batch_deltas: &[WarehouseBalanceDelta],
+) -> Result<BatchTxReturn> {
+    let batch = WarehouseBalanceDelta::to_cypher_map(batch_deltas);
+    let cypher_string = WarehouseBalanceDelta::cypher_batch_insert_str();
+
+    // Execute the query
+    let row = execute_batch(pool, &cypher_string, batch).await?;
+
+    let merged_deltas: u64 = row.get("merged_deltas").context("no merged_deltas field")?;
+
+    info!("merged balance deltas: {}", merged_deltas);
+
+    Ok(BatchTxReturn {
+        created_tx: merged_deltas,
+        ..Default::default()
+    })
+}
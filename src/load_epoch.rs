@@ -0,0 +1,78 @@
+use crate::{
+    batch_tx_type::BatchTxReturn,
+    cypher_templates::execute_batch,
+    graph_sink::GraphSink,
+    retry::{load_batch, RetryPolicy},
+    schema_epoch::WarehouseEpoch,
+};
+use anyhow::{Context, Result};
+use log::info;
+use neo4rs::Graph;
+
+/// Batches and loads epoch boundaries into the database.
+///
+/// Uses the queue system to ensure resume capability.
+pub async fn epoch_batch(
+    epochs: &[WarehouseEpoch],
+    sink: &impl GraphSink,
+    batch_size: usize,
+    archive_id: &str,
+) -> Result<BatchTxReturn> {
+    let mut all_results = BatchTxReturn::new();
+    let policy = RetryPolicy::from_env();
+
+    info!("archive: {}", archive_id);
+
+    if epochs.is_empty() {
+        // mark as complete so we don't retry
+        sink.update_task(archive_id, true, 0).await?;
+    }
+
+    for (i, c) in epochs.chunks(batch_size).enumerate() {
+        info!("batch #{}", i);
+
+        match sink.is_batch_complete(archive_id, i).await {
+            Ok(Some(true)) => {
+                info!("...skipping, all batches loaded.");
+                continue;
+            }
+            Ok(Some(false)) => {
+                // keep going
+            }
+            _ => {
+                info!("...batch not found in queue, adding to queue.");
+                sink.update_task(archive_id, false, i).await?;
+            }
+        }
+        info!("...loading to db");
+
+        if let Some(batch) =
+            load_batch(sink, &policy, archive_id, i, || sink.upsert_epochs(c)).await?
+        {
+            all_results.increment(&batch);
+        }
+    }
+
+    Ok(all_results)
+}
+
+/// Executes a batch insertion of epochs and validator sets into Neo4j.
+pub async fn impl_batch_epoch_insert(
+    pool: &Graph,
+    batch_epochs: &[WarehouseEpoch],
+) -> Result<BatchTxReturn> {
+    let batch = WarehouseEpoch::to_cypher_map(batch_epochs);
+    let cypher_string = WarehouseEpoch::cypher_batch_insert_str();
+
+    // Execute the query
+    let row = execute_batch(pool, &cypher_string, batch).await?;
+
+    let merged_epochs: u64 = row.get("merged_epochs").context("no merged_epochs field")?;
+
+    info!("merged epochs: {}", merged_epochs);
+
+    Ok(BatchTxReturn {
+        created_tx: merged_epochs,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,85 @@
+use crate::{
+    batch_tx_type::BatchTxReturn,
+    cypher_templates::execute_batch,
+    graph_sink::GraphSink,
+    retry::{load_batch, RetryPolicy},
+    schema_transaction::WarehouseEvent,
+};
+use anyhow::{Context, Result};
+use log::info;
+use neo4rs::Graph;
+
+/// Batches and loads decoded contract events into the database.
+///
+/// Events get their own queue entries, so they can be resumed independently
+/// of the transactions from the same archive.
+pub async fn event_batch(
+    events: &[WarehouseEvent],
+    sink: &impl GraphSink,
+    batch_size: usize,
+    archive_id: &str,
+) -> Result<BatchTxReturn> {
+    let mut all_results = BatchTxReturn::new();
+    let policy = RetryPolicy::from_env();
+
+    let queue_id = event_queue_id(archive_id);
+    info!("archive: {}", queue_id);
+
+    if events.is_empty() {
+        // mark as complete so we don't retry
+        sink.update_task(&queue_id, true, 0).await?;
+    }
+
+    for (i, c) in events.chunks(batch_size).enumerate() {
+        info!("batch #{}", i);
+
+        match sink.is_batch_complete(&queue_id, i).await {
+            Ok(Some(true)) => {
+                info!("...skipping, all batches loaded.");
+                continue;
+            }
+            Ok(Some(false)) => {
+                // keep going
+            }
+            _ => {
+                info!("...batch not found in queue, adding to queue.");
+                sink.update_task(&queue_id, false, i).await?;
+            }
+        }
+        info!("...loading to db");
+
+        if let Some(batch) =
+            load_batch(sink, &policy, &queue_id, i, || sink.upsert_events(c)).await?
+        {
+            all_results.increment(&batch);
+        }
+    }
+
+    Ok(all_results)
+}
+
+/// The queue identifier for the events of an archive.
+pub fn event_queue_id(archive_id: &str) -> String {
+    format!("{}_events", archive_id)
+}
+
+/// Executes a batch insertion of events into Neo4j.
+pub async fn impl_batch_event_insert(
+    pool: &Graph,
+    batch_events: &[WarehouseEvent],
+) -> Result<BatchTxReturn> {
+    let batch = WarehouseEvent::to_cypher_map(batch_events);
+    let cypher_string = WarehouseEvent::cypher_batch_insert_str();
+
+    // Execute the query
+    let row = execute_batch(pool, &cypher_string, batch).await?;
+
+    let merged_events: u64 = row.get("merged_events").context("no merged_events field")?;
+
+    info!("merged events: {}", merged_events);
+
+    Ok(BatchTxReturn {
+        created_tx: merged_events,
+        ..Default::default()
+    })
+}
@@ -2,18 +2,20 @@ use std::{path::Path, thread, time::Duration};
 
 use anyhow::{Context, Result};
 use log::{error, info, warn};
-use neo4rs::{query, Graph};
+use neo4rs::Graph;
 
 use crate::{
     analytics::{enrich_account_funding::BalanceTracker, enrich_rms},
-    extract_exchange_orders, queue,
+    cypher_templates::execute_batch,
+    extract_exchange_orders,
+    graph_sink::GraphSink,
     schema_exchange_orders::ExchangeOrder,
 };
 
 /// Batches and loads exchange orders into the database.
 pub async fn exchange_txs_batch(
     txs: &[ExchangeOrder],
-    pool: &Graph,
+    sink: &impl GraphSink,
     batch_size: usize,
 ) -> Result<(u64, u64)> {
     let chunks: Vec<&[ExchangeOrder]> = txs.chunks(batch_size).collect();
@@ -26,7 +28,7 @@ pub async fn exchange_txs_batch(
     for (i, c) in chunks.iter().enumerate() {
         info!("batch #{}", i);
 
-        match queue::is_batch_complete(pool, archive_id, i).await {
+        match sink.is_batch_complete(archive_id, i).await {
             Ok(Some(true)) => {
                 info!("...skipping, already loaded.");
                 // skip this one
@@ -39,14 +41,14 @@ pub async fn exchange_txs_batch(
                 info!("...not found in queue, adding to queue.");
 
                 // no task found in db, add to queue
-                queue::update_task(pool, archive_id, false, i).await?;
+                sink.update_task(archive_id, false, i).await?;
             }
         }
         info!("...loading to db");
 
-        match impl_batch_tx_insert(pool, c).await {
+        match sink.upsert_exchange_orders(c).await {
             Ok((m, ig)) => {
-                queue::update_task(pool, archive_id, true, i).await?;
+                sink.update_task(archive_id, true, i).await?;
 
                 info!("...success");
                 info!("merged {}", m);
@@ -69,17 +71,11 @@ pub async fn exchange_txs_batch(
 
 /// Executes a batch insertion of exchange orders into Neo4j.
 pub async fn impl_batch_tx_insert(pool: &Graph, batch_txs: &[ExchangeOrder]) -> Result<(u64, u64)> {
-    let list_str = ExchangeOrder::to_cypher_map(batch_txs);
-    let cypher_string = ExchangeOrder::cypher_batch_insert_str(list_str);
+    let batch = ExchangeOrder::to_cypher_map(batch_txs);
+    let cypher_string = ExchangeOrder::cypher_batch_insert_str();
 
     // Execute the query
-    let cypher_query = query(&cypher_string);
-    let mut res = pool
-        .execute(cypher_query)
-        .await
-        .context("execute query error")?;
-
-    let row = res.next().await?.context("no row returned")?;
+    let row = execute_batch(pool, &cypher_string, batch).await?;
     let merged: i64 = row.get("merged_tx_count").context("no merged_tx field")?;
     let ignored: i64 = row.get("ignored_tx_count").context("no ignored_tx_count")?;
 
@@ -87,7 +83,11 @@ pub async fn impl_batch_tx_insert(pool: &Graph, batch_txs: &[ExchangeOrder]) ->
 }
 
 /// Loads exchange orders from a JSON file, enriches them, and inserts them into the database.
-pub async fn load_from_json(path: &Path, pool: &Graph, batch_size: usize) -> Result<(u64, u64)> {
+pub async fn load_from_json(
+    path: &Path,
+    sink: &impl GraphSink,
+    batch_size: usize,
+) -> Result<(u64, u64)> {
     let mut orders = extract_exchange_orders::read_orders_from_file(path)?;
     info!("completed parsing orders");
 
@@ -101,8 +101,8 @@ pub async fn load_from_json(path: &Path, pool: &Graph, batch_size: usize) -> Res
 
     let mut balances = BalanceTracker::new();
     balances.replay_transactions(&mut orders)?;
-    let ledger_inserts = balances.submit_ledger(pool).await?;
+    let ledger_inserts = balances.submit_ledger(sink).await?;
     info!("exchange UserLedger state inserted: {}", ledger_inserts);
 
-    exchange_txs_batch(&orders, pool, batch_size).await
+    exchange_txs_batch(&orders, sink, batch_size).await
 }
@@ -1,11 +1,17 @@
+use std::collections::BTreeMap;
+
 use anyhow::{Context, Result};
 use log::{error, info};
-use neo4rs::{query, Graph};
+use neo4rs::Graph;
+use serde_json::Value;
 
 use crate::{
     batch_tx_type::BatchTxReturn,
-    cypher_templates::{write_batch_tx_string, write_batch_user_create},
-    queue,
+    cypher_templates::{
+        execute_batch, json_list_to_bolt, write_batch_tx_string, write_batch_user_create,
+    },
+    graph_sink::GraphSink,
+    retry::{load_batch, RetryPolicy},
     schema_transaction::WarehouseTxMaster,
 };
 
@@ -14,7 +20,7 @@ use crate::{
 /// Ensures accounts are created/merged before linking them with transaction relationships.
 pub async fn tx_batch(
     txs: &[WarehouseTxMaster],
-    pool: &Graph,
+    sink: &impl GraphSink,
     batch_size: usize,
     archive_id: &str,
 ) -> Result<BatchTxReturn> {
@@ -22,11 +28,12 @@ pub async fn tx_batch(
 
     if txs.is_empty() {
         // mark as complete so we don't retry
-        queue::update_task(pool, archive_id, true, 0).await?;
+        sink.update_task(archive_id, true, 0).await?;
     }
 
     let chunks: Vec<&[WarehouseTxMaster]> = txs.chunks(batch_size).collect();
     let mut all_results = BatchTxReturn::new();
+    let policy = RetryPolicy::from_env();
 
     for (i, c) in chunks.into_iter().enumerate() {
         info!("batch #{}", i);
@@ -35,7 +42,7 @@ pub async fn tx_batch(
         // since the outer check in ingest_all, just checks
         // all things completed prior to this run
         // check if this is already completed, or should be inserted.
-        match queue::is_batch_complete(pool, archive_id, i).await {
+        match sink.is_batch_complete(archive_id, i).await {
             Ok(Some(true)) => {
                 info!("...skipping, all batches loaded.");
                 // skip this one
@@ -48,37 +55,28 @@ pub async fn tx_batch(
                 info!("...batch not found in queue, adding to queue.");
 
                 // no task found in db, add to queue
-                queue::update_task(pool, archive_id, false, i).await?;
+                sink.update_task(archive_id, false, i).await?;
             }
         }
         info!("...loading to db");
 
-        match impl_batch_tx_insert(pool, c).await {
-            Ok(batch) => {
-                all_results.increment(&batch);
-                queue::update_task(pool, archive_id, true, i).await?;
-                info!("...success");
-            }
-            Err(e) => {
-                error!("could not insert batch: {:?}", e);
-                ////////
-                // TODO: do we need to handle connection errors?
-                // let secs = 10;
-                // warn!("waiting {} secs before retrying connection", secs);
-                // thread::sleep(Duration::from_secs(secs));
-                ////////
-            }
-        };
+        if let Some(batch) = load_batch(sink, &policy, archive_id, i, || {
+            impl_batch_tx_insert(sink, c)
+        })
+        .await?
+        {
+            all_results.increment(&batch);
+        }
     }
 
     Ok(all_results)
 }
 
-/// Executes a batch insertion of transactions into Neo4j.
+/// Executes a batch insertion of transactions.
 ///
 /// First ensures all involved accounts exist, then creates the transaction relationships.
 pub async fn impl_batch_tx_insert(
-    pool: &Graph,
+    sink: &impl GraphSink,
     batch_txs: &[WarehouseTxMaster],
 ) -> Result<BatchTxReturn> {
     let mut unique_addrs = vec![];
@@ -86,7 +84,7 @@ pub async fn impl_batch_tx_insert(
         if !unique_addrs.contains(&t.sender) {
             unique_addrs.push(t.sender);
         }
-        if let Some(r) = t.relation_label.get_recipient() {
+        for r in t.relation_label.get_recipients() {
             if !unique_addrs.contains(&r) {
                 unique_addrs.push(r);
             }
@@ -95,21 +93,34 @@ pub async fn impl_batch_tx_insert(
 
     info!("unique accounts in batch: {}", unique_addrs.len());
 
-    let list_str = WarehouseTxMaster::to_cypher_map(batch_txs);
-
     // first insert the users
     // cypher queries makes it annoying to do a single insert of users and
     // txs
-    let cypher_string = write_batch_user_create(&list_str);
+    let mut res = sink.upsert_accounts(batch_txs).await?;
 
-    // Execute the query
-    let cypher_query = query(&cypher_string);
-    let mut res = pool
-        .execute(cypher_query)
-        .await
-        .context("execute query error")?;
+    if res.unique_accounts != unique_addrs.len() as u64 {
+        error!(
+            "number of accounts in batch {} is not equal to unique accounts in query: {}",
+            unique_addrs.len(),
+            res.unique_accounts,
+        );
+    }
+
+    res.created_tx = sink.upsert_transactions(batch_txs).await?;
+
+    Ok(res)
+}
 
-    let row = res.next().await?.context("no row returned")?;
+/// Merges the accounts of a batch of transactions into Neo4j.
+pub async fn impl_batch_user_insert(
+    pool: &Graph,
+    batch_txs: &[WarehouseTxMaster],
+) -> Result<BatchTxReturn> {
+    let batch = WarehouseTxMaster::to_cypher_map(batch_txs);
+    let cypher_string = write_batch_user_create();
+
+    // Execute the query
+    let row = execute_batch(pool, &cypher_string, batch).await?;
 
     let unique_accounts: u64 = row
         .get("unique_accounts")
@@ -124,30 +135,41 @@ pub async fn impl_batch_tx_insert(
         .get("unchanged_accounts")
         .context("no unchanged_accounts field")?;
 
-    let cypher_string = write_batch_tx_string(&list_str);
-
-    // Execute the query
-    let cypher_query = query(&cypher_string);
-    let mut res = pool.execute(cypher_query).await.context(format!(
-        "execute query error. Query string: {:#}",
-        &cypher_string
-    ))?;
-    let row = res.next().await?.context("no row returned")?;
-    let created_tx: u64 = row.get("created_tx").context("no created_tx field")?;
-
-    if unique_accounts != unique_addrs.len() as u64 {
-        error!(
-            "number of accounts in batch {} is not equal to unique accounts in query: {}",
-            unique_addrs.len(),
-            unique_accounts,
-        );
-    }
-
     Ok(BatchTxReturn {
         unique_accounts,
         created_accounts,
         modified_accounts,
         unchanged_accounts,
-        created_tx,
+        created_tx: 0,
     })
 }
+
+/// Merges the relationships of a batch of transactions into Neo4j, returning
+/// the number created.
+pub async fn impl_batch_relation_insert(
+    pool: &Graph,
+    batch_txs: &[WarehouseTxMaster],
+) -> Result<u64> {
+    // each relationship type needs its own query
+    let mut by_relation: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    batch_txs.iter().for_each(|t| {
+        by_relation
+            .entry(t.relation_label.to_cypher_label())
+            .or_default()
+            .push(t.to_cypher_json());
+    });
+
+    let mut created_tx = 0;
+    for (relation_type, records) in by_relation {
+        let cypher_string = write_batch_tx_string(&relation_type);
+
+        // Execute the query
+        let row = execute_batch(pool, &cypher_string, json_list_to_bolt(&records))
+            .await
+            .context(format!("could not insert {} relations", relation_type))?;
+        let created: u64 = row.get("created_tx").context("no created_tx field")?;
+        created_tx += created;
+    }
+
+    Ok(created_tx)
+}
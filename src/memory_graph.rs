@@ -0,0 +1,737 @@
+//! An in-process graph store.
+//!
+//! `MemoryGraph` implements the same load paths as the Neo4j writer, so the
+//! ETL can be tested offline, and a single archive can be investigated
+//! without running a database. Records are kept as the same property maps
+//! the Cypher queries receive, and the whole store can be saved to a JSON
+//! file and loaded again.
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    analytics::enrich_account_funding::BalanceTracker,
+    batch_tx_type::BatchTxReturn,
+    enrich_exchange_onboarding::ExchangeOnRamp,
+    enrich_whitepages::Whitepages,
+    graph_sink::GraphSink,
+    ingest_report::IngestRun,
+    lifetime::{is_success, LifetimeTotal},
+    provenance::Provenance,
+    purge::PurgeReport,
+    queue::{DeadLetter, QueueStatus},
+    schema_account_state::{WarehouseAccState, WarehouseTime},
+    schema_balance_delta::WarehouseBalanceDelta,
+    schema_epoch::WarehouseEpoch,
+    schema_exchange_orders::ExchangeOrder,
+    schema_transaction::{WarehouseEvent, WarehouseTxMaster},
+};
+
+/// A transaction relationship between two accounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryEdge {
+    pub from: String,
+    pub to: String,
+    /// The relationship type, one of `RelationLabel::to_cypher_label`.
+    pub relation_type: String,
+    pub properties: Value,
+}
+
+/// The records of a `MemoryGraph`, keyed like the `MERGE` clauses of the
+/// Neo4j writer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryStore {
+    pub accounts: BTreeSet<String>,
+    /// Transaction edges by `tx_hash` and recipient.
+    pub transactions: BTreeMap<String, BTreeMap<String, MemoryEdge>>,
+    /// Totals of the coins sent between two accounts, by `(from, to)`.
+    pub lifetime: BTreeMap<String, BTreeMap<String, LifetimeTotal>>,
+    /// Events by `tx_hash` and index.
+    pub events: BTreeMap<String, BTreeMap<u64, Value>>,
+    /// Balance deltas by `tx_hash` and address.
+    pub balance_deltas: BTreeMap<String, BTreeMap<String, Value>>,
+    /// Snapshots by address and version.
+    pub snapshots: BTreeMap<String, BTreeMap<u64, Value>>,
+    /// Epochs by number, with their validator sets.
+    pub epochs: BTreeMap<u64, Value>,
+    pub swap_accounts: BTreeSet<u64>,
+    /// Swaps by their serialized properties, since all of them are the key.
+    pub swaps: BTreeMap<String, Value>,
+    /// Daily ledgers by swap id and date.
+    pub user_ledgers: BTreeMap<u64, BTreeMap<String, Value>>,
+    /// `(owner, address)` links.
+    pub owners: BTreeSet<(String, String)>,
+    /// `(address, swap_id)` links.
+    pub onramps: BTreeSet<(String, u64)>,
+    /// Completion of each batch, by archive id and batch number.
+    pub queue: BTreeMap<String, BTreeMap<u64, bool>>,
+    /// Batches which failed after their retries, by archive id and batch number.
+    #[serde(default)]
+    pub dead_letters: BTreeMap<String, BTreeMap<u64, DeadLetter>>,
+    /// Last update of the queue of each archive id, in unix milliseconds.
+    #[serde(default)]
+    pub queue_updated_at: BTreeMap<String, i64>,
+    /// Start of the write of each started batch, by archive id and batch
+    /// number, in unix milliseconds.
+    #[serde(default)]
+    pub queue_started_at: BTreeMap<String, BTreeMap<u64, i64>>,
+    /// Ingest runs by run id.
+    #[serde(default)]
+    pub ingest_runs: BTreeMap<String, IngestRun>,
+}
+
+impl MemoryStore {
+    /// All the transaction edges.
+    pub fn tx_edges(&self) -> impl Iterator<Item = &MemoryEdge> {
+        self.transactions.values().flat_map(|edges| edges.values())
+    }
+
+    /// Aggregates the lifetime totals of some `(from, to)` pairs again from
+    /// their transactions. A pair without transactions with coins has none.
+    pub fn rebuild_lifetime_pairs(&mut self, pairs: &BTreeSet<(String, String)>) {
+        for (from, to) in pairs {
+            if let Some(totals) = self.lifetime.get_mut(from) {
+                totals.remove(to);
+                if totals.is_empty() {
+                    self.lifetime.remove(from);
+                }
+            }
+        }
+        // the fields are borrowed apart, so not through `tx_edges`
+        for edge in self.transactions.values().flat_map(|e| e.values()) {
+            if pairs.contains(&(edge.from.clone(), edge.to.clone())) {
+                add_to_lifetime(&mut self.lifetime, edge);
+            }
+        }
+    }
+
+    /// Aggregates all the lifetime totals again from the transactions.
+    pub fn rebuild_lifetime(&mut self) {
+        self.lifetime.clear();
+        for edge in self.transactions.values().flat_map(|e| e.values()) {
+            add_to_lifetime(&mut self.lifetime, edge);
+        }
+    }
+}
+
+/// Adds a transaction to the lifetime total of its accounts, if it
+/// succeeded and sent coins.
+fn add_to_lifetime(
+    lifetime: &mut BTreeMap<String, BTreeMap<String, LifetimeTotal>>,
+    edge: &MemoryEdge,
+) {
+    if !is_success(edge.properties["status"].as_str()) {
+        return;
+    }
+    let Some(coins) = edge.properties["coins"].as_f64().filter(|c| *c > 0.0) else {
+        return;
+    };
+    let Some(seen) = edge.properties["block_datetime"]
+        .as_str()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+    else {
+        return;
+    };
+    lifetime
+        .entry(edge.from.clone())
+        .or_default()
+        .entry(edge.to.clone())
+        .or_default()
+        .add(
+            coins,
+            edge.properties["coins_normalized"].as_u64(),
+            seen.with_timezone(&Utc),
+        );
+}
+
+/// Adds the archive of a record to the `archive_ids` of the record it
+/// replaces, like `provenance::cypher_add_archive_id`.
+fn merge_archive_ids(old: Option<&Value>, record: &mut Value) {
+    let mut ids = old
+        .and_then(|o| o["archive_ids"].as_array().cloned())
+        .unwrap_or_default();
+    let id = record["archive_id"].clone();
+    if !id.is_null() && !ids.contains(&id) {
+        ids.push(id);
+    }
+    record["archive_ids"] = Value::Array(ids);
+}
+
+/// Removes an archive from the `archive_ids` of a record, like the Cypher
+/// purge. Returns true if the record was only loaded from that archive, so
+/// it is deleted.
+fn release_archive(record: &mut Value, archive_id: &str) -> bool {
+    let Some(ids) = record["archive_ids"].as_array() else {
+        return record["archive_id"] == archive_id;
+    };
+    if record["archive_id"] != archive_id && !ids.iter().any(|id| id == archive_id) {
+        return false;
+    }
+    let others: Vec<Value> = ids.iter().filter(|id| *id != archive_id).cloned().collect();
+    if others.is_empty() {
+        return true;
+    }
+    if record["archive_id"] == archive_id {
+        record["archive_id"] = others[others.len() - 1].clone();
+        record["manifest_path"] = Value::Null;
+        record["chunk_sha256"] = Value::Null;
+    }
+    record["archive_ids"] = Value::Array(others);
+    false
+}
+
+/// An in-process `GraphSink`.
+///
+/// Clones share the same store, so it can be handed to concurrent loaders.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryGraph(Arc<Mutex<MemoryStore>>);
+
+impl MemoryGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks the store, e.g. to inspect the records directly.
+    pub fn store(&self) -> MutexGuard<'_, MemoryStore> {
+        // a panic while holding the lock can't leave a record half written
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Saves the store to a JSON file.
+    pub fn save_to_file(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string(&*self.store())?;
+        fs::write(path, json).context(format!("could not write file at {:?}", path))?;
+        Ok(())
+    }
+
+    /// Loads a store saved with `save_to_file`.
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let s = fs::read_to_string(path).context(format!("could not read file at {:?}", path))?;
+        let store: MemoryStore = serde_json::from_str(&s)?;
+        Ok(Self(Arc::new(Mutex::new(store))))
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.store().accounts.len()
+    }
+
+    pub fn tx_count(&self) -> usize {
+        self.store().transactions.len()
+    }
+
+    /// The transactions sent by an account.
+    pub fn outgoing(&self, address: &str) -> Vec<MemoryEdge> {
+        self.store()
+            .tx_edges()
+            .filter(|e| e.from == address)
+            .cloned()
+            .collect()
+    }
+
+    /// The transactions received by an account.
+    pub fn incoming(&self, address: &str) -> Vec<MemoryEdge> {
+        self.store()
+            .tx_edges()
+            .filter(|e| e.to == address)
+            .cloned()
+            .collect()
+    }
+
+    /// The accounts which sent transactions to, or received them from, an account.
+    pub fn neighbors(&self, address: &str) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        for e in self.store().tx_edges() {
+            if e.from == address && e.to != address {
+                found.insert(e.to.clone());
+            }
+            if e.to == address && e.from != address {
+                found.insert(e.from.clone());
+            }
+        }
+        found
+    }
+
+    /// Finds the shortest chain of accounts from `from` to `to`, following
+    /// transactions in the direction they were sent, of at most `max_hops`.
+    pub fn shortest_path(&self, from: &str, to: &str, max_hops: usize) -> Option<Vec<String>> {
+        let store = self.store();
+        let mut adjacent: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for e in store.tx_edges() {
+            adjacent
+                .entry(e.from.as_str())
+                .or_default()
+                .insert(e.to.as_str());
+        }
+
+        // breadth first, remembering how each account was reached
+        let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut queue: VecDeque<(&str, usize)> = VecDeque::from([(from, 0)]);
+        while let Some((current, hops)) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![to.to_string()];
+                let mut step = to;
+                while let Some(&p) = previous.get(step) {
+                    path.push(p.to_string());
+                    step = p;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if hops == max_hops {
+                continue;
+            }
+            for &next in adjacent.get(current).into_iter().flatten() {
+                if next != from && !previous.contains_key(next) {
+                    previous.insert(next, current);
+                    queue.push_back((next, hops + 1));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Reads a string property of a record.
+fn get_str(record: &Value, key: &str) -> Result<String> {
+    record[key]
+        .as_str()
+        .map(|s| s.to_string())
+        .context(format!("no {} field", key))
+}
+
+/// Reads an integer property of a record.
+fn get_u64(record: &Value, key: &str) -> Result<u64> {
+    record[key].as_u64().context(format!("no {} field", key))
+}
+
+impl GraphSink for MemoryGraph {
+    async fn upsert_accounts(&self, txs: &[WarehouseTxMaster]) -> Result<BatchTxReturn> {
+        let mut store = self.store();
+        let mut res = BatchTxReturn::new();
+
+        let mut unique = BTreeSet::new();
+        for t in txs {
+            let record = t.to_cypher_json();
+            unique.insert(get_str(&record, "sender")?);
+            for r in t.recipients() {
+                unique.insert(r.to_hex_literal());
+            }
+        }
+        for addr in unique {
+            res.unique_accounts += 1;
+            if store.accounts.insert(addr) {
+                res.created_accounts += 1;
+            } else {
+                res.modified_accounts += 1;
+            }
+        }
+        Ok(res)
+    }
+
+    async fn upsert_transactions(&self, txs: &[WarehouseTxMaster]) -> Result<u64> {
+        let mut store = self.store();
+        let mut created = 0;
+        for t in txs {
+            let record = t.to_cypher_json();
+            let tx_hash = get_str(&record, "tx_hash")?;
+            // an edge to each recipient, like the Cypher template
+            for recipient in t.recipients() {
+                let to = recipient.to_hex_literal();
+                let mut record = record.clone();
+                let edges = store.transactions.entry(tx_hash.clone()).or_default();
+                merge_archive_ids(edges.get(&to).map(|e| &e.properties), &mut record);
+                let edge = MemoryEdge {
+                    from: get_str(&record, "sender")?,
+                    to: to.clone(),
+                    relation_type: t.relation_label.to_cypher_label(),
+                    properties: record,
+                };
+
+                // like the Cypher template, only new edges are added to the
+                // lifetime totals
+                let is_new = !edges.contains_key(&to);
+                edges.insert(to, edge.clone());
+                if is_new {
+                    created += 1;
+                    add_to_lifetime(&mut store.lifetime, &edge);
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    async fn upsert_events(&self, events: &[WarehouseEvent]) -> Result<BatchTxReturn> {
+        let mut store = self.store();
+        for ev in events {
+            let mut record = ev.to_cypher_json();
+            let account = get_str(&record, "account")?;
+            store.accounts.insert(account);
+            let events = store
+                .events
+                .entry(get_str(&record, "tx_hash")?)
+                .or_default();
+            let index = get_u64(&record, "index")?;
+            merge_archive_ids(events.get(&index), &mut record);
+            events.insert(index, record);
+        }
+        Ok(BatchTxReturn {
+            created_tx: events.len() as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn upsert_balance_deltas(
+        &self,
+        deltas: &[WarehouseBalanceDelta],
+    ) -> Result<BatchTxReturn> {
+        let mut store = self.store();
+        for d in deltas {
+            let mut record = d.to_cypher_json();
+            let address = get_str(&record, "address")?;
+            store.accounts.insert(address.clone());
+            let deltas = store
+                .balance_deltas
+                .entry(get_str(&record, "tx_hash")?)
+                .or_default();
+            merge_archive_ids(deltas.get(&address), &mut record);
+            deltas.insert(address, record);
+        }
+        Ok(BatchTxReturn {
+            created_tx: deltas.len() as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn upsert_snapshots(
+        &self,
+        time: &WarehouseTime,
+        provenance: Option<&Provenance>,
+        snapshots: &[WarehouseAccState],
+    ) -> Result<BatchTxReturn> {
+        let mut store = self.store();
+        for s in snapshots {
+            let mut record = s.to_record_json(time, provenance);
+            let address = get_str(&record, "address")?;
+            store.accounts.insert(address.clone());
+            let snapshots = store.snapshots.entry(address).or_default();
+            merge_archive_ids(snapshots.get(&time.version), &mut record);
+            snapshots.insert(time.version, record);
+        }
+        Ok(BatchTxReturn {
+            created_tx: snapshots.len() as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn upsert_epochs(&self, epochs: &[WarehouseEpoch]) -> Result<BatchTxReturn> {
+        let mut store = self.store();
+        for ep in epochs {
+            for v in &ep.validators {
+                store.accounts.insert(v.address.to_hex_literal());
+            }
+            let mut record = ep.to_cypher_json();
+            merge_archive_ids(store.epochs.get(&ep.epoch), &mut record);
+            store.epochs.insert(ep.epoch, record);
+        }
+        Ok(BatchTxReturn {
+            created_tx: epochs.len() as u64,
+            ..Default::default()
+        })
+    }
+
+    async fn upsert_exchange_orders(&self, orders: &[ExchangeOrder]) -> Result<(u64, u64)> {
+        let mut store = self.store();
+        let mut merged = 0;
+        let mut ignored = 0;
+        for o in orders {
+            store.swap_accounts.insert(o.user as u64);
+            store.swap_accounts.insert(o.accepter as u64);
+
+            let record = o.to_cypher_json();
+            let key = record.to_string();
+            if store.swaps.insert(key, record).is_none() {
+                merged += 1;
+            } else {
+                ignored += 1;
+            }
+        }
+        Ok((merged, ignored))
+    }
+
+    async fn upsert_user_ledger(&self, ledger: &BalanceTracker, id: u32) -> Result<u64> {
+        let list = ledger.to_cypher_json(id)?;
+        let mut store = self.store();
+        store.swap_accounts.insert(id as u64);
+        let merged = list.len() as u64;
+        for record in list {
+            store
+                .user_ledgers
+                .entry(id as u64)
+                .or_default()
+                .insert(get_str(&record, "date")?, record);
+        }
+        Ok(merged)
+    }
+
+    async fn link_owners(&self, owners: &[Whitepages]) -> Result<u64> {
+        let mut store = self.store();
+        let mut linked = 0;
+        for record in owners.iter().filter_map(|w| w.to_cypher_json()) {
+            let address = get_str(&record, "address")?;
+            // owners are only linked to accounts which exist
+            if store.accounts.contains(&address) {
+                store.owners.insert((get_str(&record, "owner")?, address));
+                linked += 1;
+            }
+        }
+        Ok(linked)
+    }
+
+    async fn link_onramps(&self, onramps: &[ExchangeOnRamp]) -> Result<u64> {
+        let mut store = self.store();
+        let mut linked = 0;
+        for record in onramps.iter().filter_map(|o| o.to_cypher_json()) {
+            let address = get_str(&record, "address")?;
+            let swap_id = get_u64(&record, "user_id")?;
+            if store.accounts.contains(&address) && store.swap_accounts.contains(&swap_id) {
+                store.onramps.insert((address, swap_id));
+                linked += 1;
+            }
+        }
+        Ok(linked)
+    }
+
+    async fn record_ingest_run(&self, run: &IngestRun) -> Result<()> {
+        self.store()
+            .ingest_runs
+            .insert(run.run_id.clone(), run.clone());
+        Ok(())
+    }
+
+    async fn update_task(&self, archive_id: &str, completed: bool, batch: usize) -> Result<String> {
+        let mut store = self.store();
+        store
+            .queue
+            .entry(archive_id.to_string())
+            .or_default()
+            .insert(batch as u64, completed);
+        store
+            .queue_updated_at
+            .insert(archive_id.to_string(), Utc::now().timestamp_millis());
+        if completed {
+            if let Some(batches) = store.dead_letters.get_mut(archive_id) {
+                batches.remove(&(batch as u64));
+            }
+        } else if let Some(batches) = store.queue_started_at.get_mut(archive_id) {
+            batches.remove(&(batch as u64));
+        }
+        Ok(archive_id.to_string())
+    }
+
+    async fn start_task(&self, archive_id: &str, batch: usize) -> Result<()> {
+        let mut store = self.store();
+        let now = Utc::now().timestamp_millis();
+        store
+            .queue
+            .entry(archive_id.to_string())
+            .or_default()
+            .entry(batch as u64)
+            .or_insert(false);
+        store
+            .queue_started_at
+            .entry(archive_id.to_string())
+            .or_default()
+            .insert(batch as u64, now);
+        store.queue_updated_at.insert(archive_id.to_string(), now);
+        Ok(())
+    }
+
+    async fn mark_dead_letter(
+        &self,
+        archive_id: &str,
+        batch: usize,
+        error: &str,
+        attempts: u32,
+    ) -> Result<()> {
+        let mut store = self.store();
+        store
+            .queue
+            .entry(archive_id.to_string())
+            .or_default()
+            .insert(batch as u64, false);
+        store
+            .queue_updated_at
+            .insert(archive_id.to_string(), Utc::now().timestamp_millis());
+        let dead_letter = DeadLetter {
+            archive_id: archive_id.to_string(),
+            batch: batch as u64,
+            error: error.to_string(),
+            attempts,
+        };
+        store
+            .dead_letters
+            .entry(archive_id.to_string())
+            .or_default()
+            .insert(batch as u64, dead_letter);
+        Ok(())
+    }
+
+    async fn set_all_batches(&self, archive_id: &str, completed: bool) -> Result<u64> {
+        let mut store = self.store();
+        let Some(batches) = store.queue.get_mut(archive_id) else {
+            return Ok(0);
+        };
+        batches.values_mut().for_each(|c| *c = completed);
+        let count = batches.len() as u64;
+        store.dead_letters.remove(archive_id);
+        store.queue_started_at.remove(archive_id);
+        store
+            .queue_updated_at
+            .insert(archive_id.to_string(), Utc::now().timestamp_millis());
+        Ok(count)
+    }
+
+    async fn queue_status(&self) -> Result<Vec<QueueStatus>> {
+        let store = self.store();
+        Ok(store
+            .queue
+            .iter()
+            .map(|(id, batches)| {
+                let failed = store.dead_letters.get(id).map_or(0, |d| d.len() as u64);
+                let completed = batches.values().filter(|c| **c).count() as u64;
+                let batch_count = batches.len() as u64;
+                QueueStatus {
+                    archive_id: id.clone(),
+                    batches: batch_count,
+                    completed,
+                    pending: batch_count.saturating_sub(completed + failed),
+                    failed,
+                    updated_at: store.queue_updated_at.get(id).copied(),
+                }
+            })
+            .collect())
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        Ok(self
+            .store()
+            .dead_letters
+            .values()
+            .flat_map(|batches| batches.values().cloned())
+            .collect())
+    }
+
+    async fn is_batch_complete(&self, archive_id: &str, batch: usize) -> Result<Option<bool>> {
+        match self
+            .store()
+            .queue
+            .get(archive_id)
+            .and_then(|batches| batches.get(&(batch as u64)))
+        {
+            Some(completed) => Ok(Some(*completed)),
+            None => bail!("not found"),
+        }
+    }
+
+    async fn are_all_completed(&self, archive_id: &str) -> Result<bool> {
+        Ok(match self.store().queue.get(archive_id) {
+            Some(batches) => !batches.is_empty() && batches.values().all(|c| *c),
+            None => false,
+        })
+    }
+
+    async fn get_queued(&self) -> Result<Vec<String>> {
+        Ok(self
+            .store()
+            .queue
+            .iter()
+            .filter(|(_, batches)| batches.values().any(|c| !*c))
+            .map(|(id, _)| id.clone())
+            .collect())
+    }
+
+    async fn clear_queue(&self) -> Result<()> {
+        let mut store = self.store();
+        store.queue.clear();
+        store.dead_letters.clear();
+        store.queue_updated_at.clear();
+        store.queue_started_at.clear();
+        Ok(())
+    }
+
+    async fn remove_queued(&self, archive_id: &str) -> Result<u64> {
+        let mut store = self.store();
+        store.dead_letters.remove(archive_id);
+        store.queue_updated_at.remove(archive_id);
+        store.queue_started_at.remove(archive_id);
+        Ok(store
+            .queue
+            .remove(archive_id)
+            .map_or(0, |batches| batches.len() as u64))
+    }
+
+    async fn purge_records(&self, archive_id: &str) -> Result<PurgeReport> {
+        let mut guard = self.store();
+        let store = &mut *guard;
+        let mut report = PurgeReport {
+            archive_id: archive_id.to_string(),
+            ..Default::default()
+        };
+        // records also loaded from another archive are kept for it
+        let release = |record: &mut Value| release_archive(record, archive_id);
+
+        let mut pairs = BTreeSet::new();
+        for edges in store.transactions.values_mut() {
+            let before = edges.len();
+            edges.retain(|_, edge| {
+                let purged = release(&mut edge.properties);
+                if purged {
+                    pairs.insert((edge.from.clone(), edge.to.clone()));
+                }
+                !purged
+            });
+            // a transaction is purged with its last edge
+            if before > 0 && edges.is_empty() {
+                report.transactions += 1;
+            }
+        }
+        store.transactions.retain(|_, edges| !edges.is_empty());
+        store.rebuild_lifetime_pairs(&pairs);
+        report.lifetime_adjusted = pairs.len() as u64;
+
+        for events in store.events.values_mut() {
+            let before = events.len();
+            events.retain(|_, e| !release(e));
+            report.events += (before - events.len()) as u64;
+        }
+        store.events.retain(|_, events| !events.is_empty());
+
+        for deltas in store.balance_deltas.values_mut() {
+            let before = deltas.len();
+            deltas.retain(|_, d| !release(d));
+            report.balance_deltas += (before - deltas.len()) as u64;
+        }
+        store.balance_deltas.retain(|_, deltas| !deltas.is_empty());
+
+        for snapshots in store.snapshots.values_mut() {
+            let before = snapshots.len();
+            snapshots.retain(|_, s| !release(s));
+            report.snapshots += (before - snapshots.len()) as u64;
+        }
+        store.snapshots.retain(|_, snapshots| !snapshots.is_empty());
+
+        let before = store.epochs.len();
+        store.epochs.retain(|_, ep| !release(ep));
+        report.epochs = (before - store.epochs.len()) as u64;
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use neo4rs::{query, Graph};
+
+use crate::schema_transaction::TX_RELATION_TYPES;
+
+/// Rewrites the legacy generic `:Tx` edges into the relationship type named
+/// by their `relation` property, e.g. `:Transfer` or `:Vouch`.
+///
+/// Edges are moved in batches, so the migration can be interrupted and run
+/// again. Returns the number of edges migrated.
+pub async fn migrate_tx_relations(pool: &Graph, batch_size: usize) -> Result<u64> {
+    let cypher_query = query(
+        r#"
+MATCH ()-[r:Tx]->()
+RETURN DISTINCT COALESCE(r.relation, "Unknown") AS relation
+"#,
+    );
+    let mut res = pool
+        .execute(cypher_query)
+        .await
+        .context("execute query error")?;
+
+    let mut relations: Vec<String> = vec![];
+    while let Some(row) = res.next().await? {
+        let relation: String = row.get("relation").context("no relation field")?;
+        relations.push(relation);
+    }
+    info!("legacy Tx relations found: {:?}", relations);
+
+    let mut total = 0;
+    for relation in relations {
+        // the relation becomes a relationship type, so only known labels are
+        // interpolated into the query
+        if !TX_RELATION_TYPES.contains(&relation.as_str()) {
+            warn!("unknown relation {}, leaving edges as Tx", relation);
+            continue;
+        }
+
+        let cypher_string = migrate_tx_relation_string(&relation);
+        total += migrate_batches(pool, &cypher_string, &relation, batch_size).await?;
+
+        // a MERGE on a null property fails, so edges without a `tx_hash` are
+        // created as they are
+        let cypher_string = migrate_tx_relation_no_hash_string(&relation);
+        let no_hash = migrate_batches(pool, &cypher_string, &relation, batch_size).await?;
+        if no_hash > 0 {
+            warn!(
+                "{} {} edges had no tx_hash, migrated without merging duplicates",
+                no_hash, relation
+            );
+        }
+        total += no_hash;
+    }
+
+    Ok(total)
+}
+
+/// Runs a migration query in batches until no edges are left to move.
+async fn migrate_batches(
+    pool: &Graph,
+    cypher_string: &str,
+    relation: &str,
+    batch_size: usize,
+) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        let cypher_query = query(cypher_string)
+            .param("relation", relation)
+            .param("limit", batch_size as i64);
+        let mut res = pool
+            .execute(cypher_query)
+            .await
+            .context("execute query error")?;
+        let row = res.next().await?.context("no row returned")?;
+        let migrated: u64 = row.get("migrated").context("no migrated field")?;
+
+        total += migrated;
+        info!("{} edges migrated: {}", relation, migrated);
+
+        if migrated == 0 {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Moves a batch of `:Tx` edges with a given `relation` to that relationship
+/// type, merged on their `tx_hash`.
+pub fn migrate_tx_relation_string(relation_type: &str) -> String {
+    format!(
+        r#"
+MATCH (from)-[old:Tx]->(to)
+WHERE COALESCE(old.relation, "Unknown") = $relation
+  AND old.tx_hash IS NOT NULL
+WITH from, to, old
+LIMIT $limit
+MERGE (from)-[new:{relation_type} {{tx_hash: old.tx_hash}}]->(to)
+SET new += properties(old)
+DELETE old
+RETURN COUNT(*) AS migrated
+"#
+    )
+}
+
+/// Moves a batch of `:Tx` edges with a given `relation` and no `tx_hash` to
+/// that relationship type. There is no key to merge them on, so they are
+/// created, and the old edge deleted in the same statement.
+pub fn migrate_tx_relation_no_hash_string(relation_type: &str) -> String {
+    format!(
+        r#"
+MATCH (from)-[old:Tx]->(to)
+WHERE COALESCE(old.relation, "Unknown") = $relation
+  AND old.tx_hash IS NULL
+WITH from, to, old
+LIMIT $limit
+CREATE (from)-[new:{relation_type}]->(to)
+SET new += properties(old)
+DELETE old
+RETURN COUNT(*) AS migrated
+"#
+    )
+}
@@ -1,6 +1,8 @@
 use anyhow::{Context, Result};
 use neo4rs::Graph;
 
+use crate::{graph_migrations, schema_transaction::TX_RELATION_TYPES};
+
 pub static URI_ENV: &str = "LIBRA_GRAPH_DB_URI";
 pub static USER_ENV: &str = "LIBRA_GRAPH_DB_USER";
 pub static PASS_ENV: &str = "LIBRA_GRAPH_DB_PASS";
@@ -20,20 +22,43 @@ pub static TX_CONSTRAINT: &str =
 pub static INDEX_HEX_ADDR: &str =
     "CREATE TEXT INDEX hex_addr IF NOT EXISTS FOR (n:Account) ON (n.address)";
 
-pub static INDEX_TX_TIMESTAMP: &str =
-    "CREATE INDEX tx_timestamp IF NOT EXISTS FOR ()-[r:Tx]-() ON (r.block_datetime)";
-
-pub static INDEX_TX_HASH: &str =
-    "CREATE INDEX tx_function IF NOT EXISTS FOR ()-[r:Tx]-() ON (r.tx_hash)";
-
-pub static INDEX_TX_AMOUNT: &str =
-    "CREATE INDEX tx_function IF NOT EXISTS FOR ()-[r:Tx]-() ON (r.amount)";
-
-pub static INDEX_TX_FRAMEWORK: &str =
-    "CREATE INDEX tx_function IF NOT EXISTS FOR ()-[r:Tx]-() ON (r.framework_version)";
-
-pub static INDEX_TX_RELATION: &str =
-    "CREATE INDEX tx_relation IF NOT EXISTS FOR ()-[r:Tx]-() ON (r.relation)";
+// properties indexed on each of the transaction relationship types: the
+// `tx_hash` which the writer and the migrations merge on, and the ledger
+// `version` which orders transactions and finds them by version range
+pub static TX_INDEXED_PROPERTIES: &[&str] = &["tx_hash", "version"];
+
+// the relationship types which move coins, and the properties the analytics
+// look them up by: time ranges and amounts
+pub static TX_COIN_RELATION_TYPES: &[&str] = &["Transfer", "Onboarding"];
+pub static TX_COIN_INDEXED_PROPERTIES: &[&str] = &["block_datetime", "coins"];
+
+/// Index statements for the transaction relationship types, since
+/// relationship indexes only apply to a single type. Only the properties
+/// queries look up are indexed, e.g. `archive_id` is not, since a purge
+/// also matches the `archive_ids` lists, which no index can serve.
+pub fn tx_relation_indexes() -> Vec<String> {
+    let mut indexes = vec![];
+    for rel_type in TX_RELATION_TYPES {
+        let mut props = TX_INDEXED_PROPERTIES.to_vec();
+        if TX_COIN_RELATION_TYPES.contains(rel_type) {
+            props.extend(TX_COIN_INDEXED_PROPERTIES);
+        }
+        for prop in props {
+            // `TX_CONSTRAINT` is backed by an index already
+            if *rel_type == "Transfer" && prop == "tx_hash" {
+                continue;
+            }
+            indexes.push(format!(
+                "CREATE INDEX {}_{} IF NOT EXISTS FOR ()-[r:{}]-() ON (r.{})",
+                rel_type.to_lowercase(),
+                prop,
+                rel_type,
+                prop
+            ));
+        }
+    }
+    indexes
+}
 
 pub static INDEX_SWAP_ID: &str =
     "CREATE INDEX swap_account_id IF NOT EXISTS FOR (n:SwapAccount) ON (n.swap_id)";
@@ -49,11 +74,38 @@ pub static INDEX_EXCHANGE_LINK_LEDGER: &str = "
     CREATE INDEX link_ledger IF NOT EXISTS FOR ()-[r:DailyLedger]->() ON (r.date)
     ";
 
-pub static INDEX_LIFETIME: &str = "
-    CREATE INDEX link_ledger IF NOT EXISTS FOR ()-[r:Lifetime]->() ON (r.amount)
-    ";
+pub static INDEX_LIFETIME: &str =
+    "CREATE INDEX lifetime_coins IF NOT EXISTS FOR ()-[r:Lifetime]->() ON (r.coins)";
+
+pub static EPOCH_UNIQUE: &str =
+    "CREATE CONSTRAINT unique_epoch IF NOT EXISTS FOR (n:Epoch) REQUIRE n.epoch IS UNIQUE";
+
+pub static INDEX_EPOCH_VERSION: &str =
+    "CREATE INDEX epoch_first_version IF NOT EXISTS FOR (n:Epoch) ON (n.first_version)";
+
+pub static INDEX_EVENT: &str =
+    "CREATE INDEX event_tx_hash IF NOT EXISTS FOR (n:Event) ON (n.tx_hash, n.index)";
+
+pub static INDEX_EVENT_TYPE: &str =
+    "CREATE INDEX event_type IF NOT EXISTS FOR (n:Event) ON (n.event_type)";
+
+pub static INDEX_BALANCE_DELTA: &str = "CREATE INDEX balance_delta_tx_hash IF NOT EXISTS FOR (n:BalanceDelta) ON (n.tx_hash, n.address)";
+
+pub static INDEX_BALANCE_DELTA_VERSION: &str =
+    "CREATE INDEX balance_delta_version IF NOT EXISTS FOR (n:BalanceDelta) ON (n.version)";
 
 pub static INDEX_SNAPSHOT: &str = "CREATE INDEX snapshot_account_id IF NOT EXISTS FOR (n:Snapshot) ON (n.address, n.epoch, n.version)";
+
+// provenance, to find the records of an archive
+pub static INDEX_SNAPSHOT_ARCHIVE: &str =
+    "CREATE INDEX snapshot_archive_id IF NOT EXISTS FOR (n:Snapshot) ON (n.archive_id)";
+
+pub static INDEX_EVENT_ARCHIVE: &str =
+    "CREATE INDEX event_archive_id IF NOT EXISTS FOR (n:Event) ON (n.archive_id)";
+
+pub static INDEX_BALANCE_DELTA_ARCHIVE: &str =
+    "CREATE INDEX balance_delta_archive_id IF NOT EXISTS FOR (n:BalanceDelta) ON (n.archive_id)";
+
 /// get the testing neo4j connection
 pub async fn get_neo4j_localhost_pool(port: u16) -> Result<Graph> {
     let uri = format!("127.0.0.1:{port}");
@@ -77,25 +129,12 @@ pub fn get_credentials_from_env() -> Result<(String, String, String)> {
 }
 
 /// Initializes the database with constraints and indexes if they don't already exist.
+///
+/// Runs the schema statements of all the migrations, see `graph_migrations`,
+/// and warns if the data migrations of `db migrate` are pending.
 pub async fn maybe_create_indexes(graph: &Graph) -> Result<()> {
-    let mut txn = graph.start_txn().await?;
-
-    txn.run_queries([
-        ACCOUNT_UNIQUE,
-        TX_CONSTRAINT,
-        INDEX_HEX_ADDR,
-        INDEX_TX_TIMESTAMP,
-        INDEX_TX_HASH,
-        INDEX_TX_AMOUNT,
-        INDEX_TX_FRAMEWORK,
-        INDEX_TX_RELATION,
-        INDEX_SWAP_ID,
-        INDEX_EXCHANGE_LEDGER,
-        INDEX_EXCHANGE_LINK_LEDGER,
-        INDEX_LIFETIME,
-        INDEX_SNAPSHOT,
-    ])
-    .await?;
-    txn.commit().await?;
+    graph_migrations::run_schema_statements(graph, graph_migrations::all_schema_statements())
+        .await?;
+    graph_migrations::warn_pending_migrations(graph).await?;
     Ok(())
 }
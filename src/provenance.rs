@@ -0,0 +1,116 @@
+//! Where a loaded record came from.
+//!
+//! Every record is tagged with the archive it was extracted from, the
+//! manifest which lists its chunk, and the SHA-256 of that chunk file. So an
+//! edge in the graph can be traced back to the exact file it was decoded
+//! from, and all the records of a bad archive can be found by `archive_id`.
+//!
+//! The manifest path is relative to the directory holding the archives,
+//! e.g. `transaction_38100001-.541f/transaction.manifest`, like the file
+//! handles in the manifests. So the same archive gives the same provenance
+//! wherever it is mounted, and when it is decompressed to a temp folder.
+//!
+//! Records are merged by their key, so one record may be loaded from several
+//! archives, e.g. an epoch at the boundary of two epoch-ending archives, or a
+//! transaction in two overlapping transaction archives. The provenance is of
+//! the last archive loaded, and `archive_ids` lists all of them.
+use std::{fs::File, io, path::Path};
+
+use anyhow::{Context, Result};
+use neo4rs::BoltType;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::cypher_templates::json_to_bolt;
+
+/// The source archive, manifest and chunk file of a record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub archive_id: String,
+    /// the manifest, relative to the parent of the archive directory
+    pub manifest_path: String,
+    /// hex SHA-256 of the (decompressed) chunk file the record was read from
+    pub chunk_sha256: String,
+}
+
+impl Provenance {
+    /// The provenance of the records of a chunk file, listed in a manifest
+    /// of an archive directory.
+    pub fn from_chunk(
+        archive_path: &Path,
+        manifest_file: &Path,
+        chunk_file: &Path,
+    ) -> Result<Self> {
+        let archive_id = archive_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("could not read archive directory name")?
+            .to_owned();
+        let manifest_name = manifest_file
+            .file_name()
+            .context("could not read manifest file name")?;
+        let manifest_path = Path::new(&archive_id).join(manifest_name);
+
+        Ok(Self {
+            archive_id,
+            manifest_path: manifest_path.to_string_lossy().into_owned(),
+            chunk_sha256: sha256_file(chunk_file)?,
+        })
+    }
+
+    /// The Cypher properties of the provenance, which are also merged into
+    /// the properties of each record.
+    pub fn to_cypher_json(&self) -> Value {
+        json!({
+            "archive_id": self.archive_id,
+            "manifest_path": self.manifest_path,
+            "chunk_sha256": self.chunk_sha256,
+        })
+    }
+}
+
+/// Converts an optional provenance into a Bolt map for the `$provenance`
+/// parameter, with null properties when there is none.
+pub fn provenance_to_cypher_map(provenance: Option<&Provenance>) -> BoltType {
+    json_to_bolt(&with_provenance(json!({}), provenance))
+}
+
+/// A Cypher `SET` item which adds the archive `archive_id`, e.g.
+/// `tx.archive_id`, to the `archive_ids` of the record `var`. Records loaded
+/// before `archive_ids` start from their `archive_id`, so the item goes
+/// before the provenance is set.
+pub fn cypher_add_archive_id(var: &str, archive_id: &str) -> String {
+    let ids = format!(
+        "COALESCE({var}.archive_ids, CASE WHEN {var}.archive_id IS NULL THEN [] ELSE [{var}.archive_id] END)"
+    );
+    format!(
+        "{var}.archive_ids = CASE WHEN {archive_id} IS NULL OR {archive_id} IN {ids} THEN {ids} ELSE {ids} + {archive_id} END"
+    )
+}
+
+/// Adds the provenance properties to the Cypher properties of a record. A
+/// record without provenance gets them as nulls, so every record has the
+/// same columns.
+pub fn with_provenance(mut record: Value, provenance: Option<&Provenance>) -> Value {
+    let fields = match provenance {
+        Some(p) => p.to_cypher_json(),
+        None => json!({
+            "archive_id": null,
+            "manifest_path": null,
+            "chunk_sha256": null,
+        }),
+    };
+    if let (Some(obj), Value::Object(p)) = (record.as_object_mut(), fields) {
+        obj.extend(p);
+    }
+    record
+}
+
+/// The hex SHA-256 of a file, read in a stream.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context(format!("could not open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).context(format!("could not read {}", path.display()))?;
+    Ok(hex::encode(hasher.finalize()))
+}
@@ -0,0 +1,150 @@
+//! Removes the records of one archive, e.g. one which was corrupt or decoded
+//! by a buggy version, so it can be loaded again cleanly.
+//!
+//! Records are found by the `archive_ids` of their provenance, see
+//! `provenance.rs`. A record which was also loaded from another archive,
+//! like an epoch shared by two adjacent epoch-ending archives, is kept for
+//! it, and only loses the purged archive from its `archive_ids`. The
+//! `Lifetime` totals between the accounts of the deleted transactions are
+//! aggregated again without them, and the queue entries of the archive are
+//! replaced by a single pending batch, so the next `ingest-all` reloads it.
+//! Accounts are kept, since other archives may refer to them.
+use anyhow::{Context, Result};
+use log::info;
+use neo4rs::Graph;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph_sink::GraphSink, lifetime::cypher_aggregate_pair_str, queue::archive_queue_ids,
+    schema_transaction::TX_RELATION_TYPES,
+};
+
+/// The records removed by a purge.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub archive_id: String,
+    pub transactions: u64,
+    /// `Lifetime` totals aggregated again without the transactions
+    pub lifetime_adjusted: u64,
+    pub events: u64,
+    pub balance_deltas: u64,
+    pub snapshots: u64,
+    pub epochs: u64,
+    /// queue batches of the archive, its events and balance deltas
+    pub queue_batches: u64,
+}
+
+/// Cypher which removes the archive from the `archive_ids` of the record
+/// `var` in scope, and passes on `others`, the archives left. A record which
+/// is kept for another archive takes its `archive_id`; the manifest and
+/// chunk of the purged archive are removed, until the other is reloaded.
+fn cypher_release_archive_str(var: &str) -> String {
+    format!(
+        r#"
+WITH *, [id IN COALESCE({var}.archive_ids, [{var}.archive_id]) WHERE id <> $archive_id] AS others
+FOREACH (_ IN CASE WHEN size(others) > 0 THEN [1] ELSE [] END |
+    SET {var}.archive_ids = others
+)
+FOREACH (_ IN CASE WHEN size(others) > 0 AND {var}.archive_id = $archive_id THEN [1] ELSE [] END |
+    SET {var}.archive_id = others[size(others) - 1]
+    REMOVE {var}.manifest_path, {var}.chunk_sha256
+)"#
+    )
+}
+
+/// Deletes the transactions of an archive of one relationship type, and
+/// aggregates the `Lifetime` totals of their accounts again from the
+/// transactions which are left. A total with none left is deleted.
+/// Transactions also loaded from another archive are kept.
+pub fn cypher_purge_tx_str(relation_type: &str) -> String {
+    format!(
+        r#"
+MATCH (from:Account)-[rel:{relation_type}]->(to:Account)
+WHERE rel.archive_id = $archive_id OR $archive_id IN rel.archive_ids
+{}
+WITH from, to, collect(CASE WHEN size(others) = 0 THEN rel END) AS rels
+FOREACH (r IN rels | DELETE r)
+WITH from, to, size(rels) AS deleted
+WHERE deleted > 0
+{}
+RETURN sum(deleted) AS deleted_tx, count(*) AS adjusted_lifetime
+"#,
+        cypher_release_archive_str("rel"),
+        cypher_aggregate_pair_str()
+    )
+}
+
+/// Deletes the nodes of one label loaded from an archive, with their
+/// relationships. Nodes also loaded from another archive are kept.
+pub fn cypher_purge_nodes_str(label: &str) -> String {
+    format!(
+        r#"
+MATCH (n:{label})
+WHERE n.archive_id = $archive_id OR $archive_id IN n.archive_ids
+{}
+WITH n, others
+WHERE size(others) = 0
+DETACH DELETE n
+RETURN count(*) AS deleted
+"#,
+        cypher_release_archive_str("n")
+    )
+}
+
+async fn execute_count(pool: &Graph, cypher: &str, archive_id: &str, field: &str) -> Result<u64> {
+    let mut res = pool
+        .execute(neo4rs::query(cypher).param("archive_id", archive_id))
+        .await
+        .context("execute query error")?;
+    let row = res.next().await?.context("no row returned")?;
+    Ok(row.get::<i64>(field)? as u64)
+}
+
+/// Deletes the records of an archive from Neo4j. The queue is left as is.
+pub async fn impl_purge_records(pool: &Graph, archive_id: &str) -> Result<PurgeReport> {
+    let mut report = PurgeReport {
+        archive_id: archive_id.to_string(),
+        ..Default::default()
+    };
+
+    for relation_type in TX_RELATION_TYPES {
+        let mut res = pool
+            .execute(
+                neo4rs::query(&cypher_purge_tx_str(relation_type)).param("archive_id", archive_id),
+            )
+            .await
+            .context("execute query error")?;
+        let row = res.next().await?.context("no row returned")?;
+        report.transactions += row.get::<i64>("deleted_tx")? as u64;
+        report.lifetime_adjusted += row.get::<i64>("adjusted_lifetime")? as u64;
+    }
+
+    let label_counts = [
+        ("Event", &mut report.events),
+        ("BalanceDelta", &mut report.balance_deltas),
+        ("Snapshot", &mut report.snapshots),
+        ("Epoch", &mut report.epochs),
+    ];
+    for (label, count) in label_counts {
+        *count = execute_count(pool, &cypher_purge_nodes_str(label), archive_id, "deleted").await?;
+    }
+
+    Ok(report)
+}
+
+/// Purges the records of an archive from a sink, and re-queues it for a
+/// clean reload.
+///
+/// The old queue entries are deleted rather than reset, since the reload
+/// may split the archive into a different number of batches.
+pub async fn purge_archive(sink: &impl GraphSink, archive_id: &str) -> Result<PurgeReport> {
+    let mut report = sink.purge_records(archive_id).await?;
+
+    for id in archive_queue_ids(archive_id) {
+        report.queue_batches += sink.remove_queued(&id).await?;
+    }
+    sink.update_task(archive_id, false, 0).await?;
+
+    info!("purged archive {}: {:?}", archive_id, report);
+    Ok(report)
+}
@@ -1,26 +1,65 @@
+use std::collections::BTreeSet;
+
 use anyhow::{bail, Context, Result};
 use log::info;
 use neo4rs::Graph;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graph_sink::GraphSink, load_balance_deltas::balance_delta_queue_id,
+    load_events::event_queue_id, scan::ArchiveMap,
+};
 
-use crate::scan::ArchiveMap;
+/// A batch which could not be written after its retries, see `retry.rs`.
+///
+/// It stays incomplete in the queue, so the next run writes it again.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub archive_id: String,
+    pub batch: u64,
+    /// the last error of the batch
+    pub error: String,
+    pub attempts: u32,
+}
+
+/// The load progress of one queue id, e.g. an archive or its events.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    pub archive_id: String,
+    pub batches: u64,
+    pub completed: u64,
+    /// incomplete batches without an error
+    pub pending: u64,
+    /// dead letters, which failed after their retries
+    pub failed: u64,
+    /// last update of any of the batches, in unix milliseconds
+    pub updated_at: Option<i64>,
+}
 
 /// Updates or creates a task in the Neo4j queue.
+///
+/// Completing a task clears the error of a dead letter, and queueing it
+/// again clears when it was started.
 pub async fn update_task(
     pool: &Graph,
     archive_id: &str,
     completed: bool,
     batch: usize,
 ) -> Result<String> {
-    let cypher_string = format!(
-        r#"MERGE (a:Queue {{ archive_id: "{}", batch: {} }})
-        SET a.completed = {}
-        RETURN a.archive_id AS archive_id"#,
-        archive_id,
-        batch,
-        completed.to_string().to_lowercase(),
-    );
+    let cypher_string = r#"MERGE (a:Queue { archive_id: $archive_id, batch: $batch })
+        SET a.completed = $completed, a.updated_at = timestamp()
+        FOREACH (_ IN CASE WHEN $completed THEN [1] ELSE [] END |
+            REMOVE a.error, a.attempts
+        )
+        FOREACH (_ IN CASE WHEN $completed THEN [] ELSE [1] END |
+            REMOVE a.started_at
+        )
+        RETURN a.archive_id AS archive_id"#;
 
-    let cypher_query = neo4rs::query(&cypher_string);
+    let cypher_query = neo4rs::query(cypher_string)
+        .param("archive_id", archive_id)
+        .param("batch", batch as i64)
+        .param("completed", completed);
 
     let mut res = pool
         .execute(cypher_query)
@@ -32,6 +71,134 @@ pub async fn update_task(
     Ok(task_id)
 }
 
+/// Records when the write of a task of the Neo4j queue started, so a write
+/// which never finished can be told from a task which was never reached.
+pub async fn start_task(pool: &Graph, archive_id: &str, batch: usize) -> Result<()> {
+    let cypher_string = r#"MERGE (a:Queue { archive_id: $archive_id, batch: $batch })
+        ON CREATE SET a.completed = false
+        SET a.started_at = timestamp(), a.updated_at = timestamp()"#;
+
+    let cypher_query = neo4rs::query(cypher_string)
+        .param("archive_id", archive_id)
+        .param("batch", batch as i64);
+
+    pool.run(cypher_query)
+        .await
+        .context("execute query error")?;
+    Ok(())
+}
+
+/// Marks a task of the Neo4j queue as incomplete, with the error of its last attempt.
+pub async fn mark_dead_letter(
+    pool: &Graph,
+    archive_id: &str,
+    batch: usize,
+    error: &str,
+    attempts: u32,
+) -> Result<()> {
+    let cypher_string = r#"MERGE (a:Queue { archive_id: $archive_id, batch: $batch })
+        SET a.completed = false, a.error = $error, a.attempts = $attempts,
+          a.updated_at = timestamp()
+        RETURN a.archive_id AS archive_id"#;
+
+    let cypher_query = neo4rs::query(cypher_string)
+        .param("archive_id", archive_id)
+        .param("batch", batch as i64)
+        .param("error", error)
+        .param("attempts", attempts as i64);
+
+    let mut res = pool
+        .execute(cypher_query)
+        .await
+        .context("execute query error")?;
+
+    res.next().await?.context("no row returned")?;
+    Ok(())
+}
+
+/// Lists the dead letters of the Neo4j queue.
+pub async fn get_dead_letters(pool: &Graph) -> Result<Vec<DeadLetter>> {
+    let cypher_string = r#"
+      MATCH (a:Queue)
+      WHERE a.completed = false AND a.error IS NOT NULL
+      RETURN a.archive_id AS archive_id, a.batch AS batch, a.error AS error, a.attempts AS attempts
+      ORDER BY archive_id, batch
+    "#;
+
+    let mut res = pool
+        .execute(neo4rs::query(cypher_string))
+        .await
+        .context("execute query error")?;
+
+    let mut dead_letters = vec![];
+    while let Some(row) = res.next().await? {
+        dead_letters.push(DeadLetter {
+            archive_id: row.get("archive_id")?,
+            batch: row.get::<i64>("batch")? as u64,
+            error: row.get("error")?,
+            attempts: row.get::<i64>("attempts").unwrap_or_default() as u32,
+        });
+    }
+    Ok(dead_letters)
+}
+
+/// Counts the batches of each queue id in the Neo4j queue.
+pub async fn get_status(pool: &Graph) -> Result<Vec<QueueStatus>> {
+    let cypher_string = r#"
+      MATCH (a:Queue)
+      RETURN
+        a.archive_id AS archive_id,
+        COUNT(a) AS batches,
+        COUNT(CASE WHEN a.completed THEN 1 END) AS completed,
+        COUNT(CASE WHEN NOT a.completed AND a.error IS NULL THEN 1 END) AS pending,
+        COUNT(CASE WHEN NOT a.completed AND a.error IS NOT NULL THEN 1 END) AS failed,
+        MAX(a.updated_at) AS updated_at
+      ORDER BY archive_id
+    "#;
+
+    let mut res = pool
+        .execute(neo4rs::query(cypher_string))
+        .await
+        .context("execute query error")?;
+
+    let mut status = vec![];
+    while let Some(row) = res.next().await? {
+        status.push(QueueStatus {
+            archive_id: row.get("archive_id")?,
+            batches: row.get("batches")?,
+            completed: row.get("completed")?,
+            pending: row.get("pending")?,
+            failed: row.get("failed")?,
+            // queues from before `updated_at` was tracked have none
+            updated_at: row.get::<i64>("updated_at").ok(),
+        });
+    }
+    Ok(status)
+}
+
+/// Sets all the batches of a queue id in Neo4j as incomplete, or as complete,
+/// clearing their errors. Returns the number of batches.
+pub async fn set_all_batches(pool: &Graph, archive_id: &str, completed: bool) -> Result<u64> {
+    let cypher_string = r#"
+        MATCH (a:Queue { archive_id: $archive_id })
+        SET a.completed = $completed, a.updated_at = timestamp()
+        REMOVE a.error, a.attempts, a.started_at
+        RETURN COUNT(a) AS batches
+      "#;
+
+    let cypher_query = neo4rs::query(cypher_string)
+        .param("archive_id", archive_id)
+        .param("completed", completed);
+
+    let mut res = pool
+        .execute(cypher_query)
+        .await
+        .context("execute query error")?;
+
+    let row = res.next().await?.context("no row returned")?;
+    Ok(row.get("batches")?)
+}
+
 /// Retrieves all archive IDs that have incomplete tasks in the queue.
 pub async fn get_queued(pool: &Graph) -> Result<Vec<String>> {
     let cypher_string = r#"
@@ -68,15 +235,14 @@ pub async fn is_batch_complete(
     archive_id: &str,
     batch: usize,
 ) -> Result<Option<bool>> {
-    let cypher_string = format!(
-        r#"
-        MATCH (a:Queue {{ archive_id: "{}", batch: {} }})
+    let cypher_string = r#"
+        MATCH (a:Queue { archive_id: $archive_id, batch: $batch })
         RETURN DISTINCT a.completed;
-      "#,
-        archive_id, batch
-    );
+      "#;
 
-    let cypher_query = neo4rs::query(&cypher_string);
+    let cypher_query = neo4rs::query(cypher_string)
+        .param("archive_id", archive_id)
+        .param("batch", batch as i64);
 
     let mut res = pool
         .execute(cypher_query)
@@ -93,19 +259,16 @@ pub async fn is_batch_complete(
 
 /// Checks if all batches for a given archive ID are completed.
 pub async fn are_all_completed(pool: &Graph, archive_id: &str) -> Result<bool> {
-    let cypher_string = format!(
-        r#"
-        MATCH (a:Queue {{archive_id: '{}' }})
+    let cypher_string = r#"
+        MATCH (a:Queue {archive_id: $archive_id })
         WITH COLLECT(a.completed) AS completedStatuses, COUNT(a) AS totalTasks
         RETURN CASE
           WHEN totalTasks = 0 THEN false
           ELSE ALL(status IN completedStatuses WHERE status = true)
         END AS allCompleted;
-      "#,
-        archive_id,
-    );
+      "#;
 
-    let cypher_query = neo4rs::query(&cypher_string);
+    let cypher_query = neo4rs::query(cypher_string).param("archive_id", archive_id);
 
     let mut res = pool
         .execute(cypher_query)
@@ -137,12 +300,99 @@ pub async fn clear_queue(pool: &Graph) -> Result<()> {
     Ok(())
 }
 
+/// Deletes all the batches of a queue id from the Neo4j queue, returning
+/// the number deleted.
+pub async fn remove_queued(pool: &Graph, archive_id: &str) -> Result<u64> {
+    let cypher_string = r#"
+        MATCH (a:Queue { archive_id: $archive_id })
+        DELETE a
+        RETURN COUNT(*) AS batches
+      "#;
+
+    let cypher_query = neo4rs::query(cypher_string).param("archive_id", archive_id);
+
+    let mut res = pool
+        .execute(cypher_query)
+        .await
+        .context("execute query error")?;
+
+    let row = res.next().await?.context("no row returned")?;
+    Ok(row.get("batches")?)
+}
+
 /// Populates the queue from an `ArchiveMap`, marking the first batch (0) as pending.
-pub async fn push_queue_from_archive_map(map: &ArchiveMap, pool: &Graph) -> Result<()> {
+pub async fn push_queue_from_archive_map(map: &ArchiveMap, sink: &impl GraphSink) -> Result<()> {
     for (_, a) in map.0.iter() {
         // set at least one batch of each archive_id to false, so it gets picked up in the queue
-        update_task(pool, &a.archive_id, false, 0).await?;
+        sink.update_task(&a.archive_id, false, 0).await?;
         info!("enqueued archive {}, batch #0", &a.archive_id);
     }
     Ok(())
 }
+
+/// The queue ids of an archive: its transactions, snapshots or epochs, and
+/// the events and balance changes which are queued apart from them.
+pub fn archive_queue_ids(archive_id: &str) -> Vec<String> {
+    vec![
+        archive_id.to_string(),
+        event_queue_id(archive_id),
+        balance_delta_queue_id(archive_id),
+    ]
+}
+
+/// The archive a queue id belongs to, e.g. `archive` for the events queued
+/// as `archive_events`.
+pub fn queue_archive_id(queue_id: &str) -> &str {
+    for suffix in [event_queue_id(""), balance_delta_queue_id("")] {
+        if let Some(archive_id) = queue_id.strip_suffix(suffix.as_str()) {
+            return archive_id;
+        }
+    }
+    queue_id
+}
+
+/// Lists the archives with incomplete batches, of their own or of their
+/// events or balance changes.
+pub async fn get_queued_archives(sink: &impl GraphSink) -> Result<BTreeSet<String>> {
+    Ok(sink
+        .get_queued()
+        .await?
+        .iter()
+        .map(|id| queue_archive_id(id).to_string())
+        .collect())
+}
+
+/// Re-queues all the batches of an archive, so the next `ingest-all` loads
+/// it again. Returns the number of batches.
+pub async fn reset_archive(sink: &impl GraphSink, archive_id: &str) -> Result<u64> {
+    let mut batches = 0;
+    for id in archive_queue_ids(archive_id) {
+        batches += sink.set_all_batches(&id, false).await?;
+    }
+    if batches == 0 {
+        // queue the first batch, so the archive is picked up
+        sink.update_task(archive_id, false, 0).await?;
+        batches = 1;
+    }
+    info!("re-queued archive {}, {} batches", archive_id, batches);
+    Ok(batches)
+}
+
+/// Marks all the batches of an archive as complete, so `ingest-all` skips
+/// it. Returns the number of batches.
+pub async fn mark_archive_complete(sink: &impl GraphSink, archive_id: &str) -> Result<u64> {
+    let mut batches = 0;
+    for id in archive_queue_ids(archive_id) {
+        batches += sink.set_all_batches(&id, true).await?;
+    }
+    if batches == 0 {
+        // an archive is done once all its queued batches are
+        sink.update_task(archive_id, true, 0).await?;
+        batches = 1;
+    }
+    info!(
+        "marked archive {} complete, {} batches",
+        archive_id, batches
+    );
+    Ok(batches)
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 
@@ -31,6 +31,8 @@ pub fn load_tx_chunk_manifest(path: &Path) -> anyhow::Result<TransactionBackup>
 /// All vectors are of the same length, representing a tabular structure.
 pub struct TransactionArchiveChunk {
     pub manifest: TransactionChunk,
+    /// the chunk file the records were read from
+    pub path: PathBuf,
     pub txns: Vec<Transaction>,
     pub txn_infos: Vec<TransactionInfo>,
     pub event_vecs: Vec<Vec<ContractEvent>>,
@@ -80,6 +82,7 @@ pub async fn load_chunk(
 
     Ok(TransactionArchiveChunk {
         manifest,
+        path: full_handle,
         txns,
         txn_infos,
         event_vecs,
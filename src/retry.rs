@@ -0,0 +1,142 @@
+//! Retries of batch writes, and the dead letters of batches which still fail.
+//!
+//! A batch which fails with a transient error, like a dropped connection or
+//! a Neo4j `TransientError`, is written again after an exponential backoff.
+//! Once the retries run out, or on any other error, the batch is left
+//! incomplete in the queue with the error text, as a dead letter. The next
+//! `ingest-all` skips the completed batches, so only the dead letters are
+//! written again.
+use std::{env, future::Future, time::Duration};
+
+use anyhow::Result;
+use log::{error, info, warn};
+
+use crate::graph_sink::GraphSink;
+
+/// Environment variable for the number of retries of a failed batch.
+pub static RETRIES_ENV: &str = "LIBRA_BATCH_RETRIES";
+/// Environment variable for the first backoff, in milliseconds.
+pub static BACKOFF_ENV: &str = "LIBRA_BATCH_BACKOFF_MS";
+
+/// How often, and how long apart, a failed batch write is retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// retries after the first attempt
+    pub max_retries: u32,
+    /// wait before the first retry, doubled for each one after
+    pub initial_backoff: Duration,
+    /// longest wait between two retries
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy, with the retries and first backoff overridden by
+    /// `LIBRA_BATCH_RETRIES` and `LIBRA_BATCH_BACKOFF_MS` when they are set.
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(n) = env::var(RETRIES_ENV).ok().and_then(|v| v.parse().ok()) {
+            policy.max_retries = n;
+        }
+        if let Some(ms) = env::var(BACKOFF_ENV).ok().and_then(|v| v.parse().ok()) {
+            policy.initial_backoff = Duration::from_millis(ms);
+        }
+        policy
+    }
+
+    /// The wait before the retry number `retry`, counting from zero.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Runs `op` until it succeeds, retrying transient errors. On failure,
+    /// returns the last error and the number of attempts made.
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T, (anyhow::Error, u32)>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match op().await {
+                Ok(t) => return Ok(t),
+                Err(e) if is_transient(&e) && attempts <= self.max_retries => {
+                    let wait = self.backoff(attempts - 1);
+                    warn!(
+                        "attempt {} failed, retrying in {:?}: {:?}",
+                        attempts, wait, e
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+                Err(e) => return Err((e, attempts)),
+            }
+        }
+    }
+}
+
+/// Checks if an error is worth retrying: IO and connection errors, and the
+/// errors Neo4j classifies as transient, e.g. deadlocks.
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(neo) = cause.downcast_ref::<neo4rs::Error>() {
+            if matches!(
+                neo,
+                neo4rs::Error::IOError { .. } | neo4rs::Error::ConnectionError
+            ) {
+                return true;
+            }
+        }
+        if let Some(sql) = cause.downcast_ref::<sqlx::Error>() {
+            if matches!(sql, sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut) {
+                return true;
+            }
+        }
+        cause.is::<std::io::Error>() || cause.to_string().contains("Neo.TransientError")
+    })
+}
+
+/// Writes one queued batch with the retry policy. Marks the batch started,
+/// then complete on success, or as a dead letter with the error once the
+/// retries run out, in which case `None` is returned and the loader moves on.
+pub async fn load_batch<T, F, Fut>(
+    sink: &impl GraphSink,
+    policy: &RetryPolicy,
+    queue_id: &str,
+    batch: usize,
+    op: F,
+) -> Result<Option<T>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    sink.start_task(queue_id, batch).await?;
+    match policy.run(op).await {
+        Ok(t) => {
+            sink.update_task(queue_id, true, batch).await?;
+            info!("...success");
+            Ok(Some(t))
+        }
+        Err((e, attempts)) => {
+            error!(
+                "batch #{} of {} failed after {} attempts, adding to dead letters: {:?}",
+                batch, queue_id, attempts, e
+            );
+            sink.mark_dead_letter(queue_id, batch, &format!("{:#}", e), attempts)
+                .await?;
+            Ok(None)
+        }
+    }
+}
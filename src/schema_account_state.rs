@@ -1,6 +1,13 @@
 use libra_types::exports::AccountAddress;
+use neo4rs::BoltType;
+use serde_json::{json, Value};
 
-use crate::scan::FrameworkVersion;
+use crate::{
+    coin_normalize::CoinAmount,
+    cypher_templates::{json_list_to_bolt, json_to_bolt},
+    provenance::{cypher_add_archive_id, with_provenance, Provenance},
+    scan::FrameworkVersion,
+};
 
 /// Metadata for the time and version of an account state snapshot.
 #[derive(Debug, Clone, Default)]
@@ -11,14 +18,38 @@ pub struct WarehouseTime {
     pub epoch: u64,
 }
 
-/// The warehouse record for an account's state at a specific version.
+impl WarehouseTime {
+    /// Converts the snapshot time into a JSON object, for the `$time` parameter.
+    pub fn to_cypher_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "epoch": self.epoch,
+            "timestamp": self.timestamp,
+            "framework_version": self.framework_version.to_string(),
+        })
+    }
+
+    /// Converts the snapshot time into a Bolt map for the `$time` parameter.
+    pub fn to_cypher_map(&self) -> BoltType {
+        json_to_bolt(&self.to_cypher_json())
+    }
+}
+
+/// The warehouse record for an account's state. The version it was taken at
+/// is the `WarehouseTime` of its snapshot, which is shared by all its accounts.
 #[derive(Debug, Clone)]
 pub struct WarehouseAccState {
     pub address: AccountAddress,
-    pub time: WarehouseTime,
     pub sequence_num: u64,
+    /// the normalized balance in coins, for display
     pub balance: f64,
+    /// the balance in base units of the snapshot's framework version
+    pub balance_raw: u64,
+    /// the balance in current base units, see `coin_normalize`
+    pub balance_normalized: u64,
+    /// normalized, in coins
     pub slow_wallet_unlocked: Option<f64>,
+    /// normalized, in coins
     pub slow_wallet_transferred: Option<f64>,
     pub slow_wallet_acc: bool,
     pub donor_voice_acc: bool,
@@ -31,12 +62,13 @@ impl Default for WarehouseAccState {
             address: AccountAddress::ZERO,
             sequence_num: 0,
             balance: 0.0,
+            balance_raw: 0,
+            balance_normalized: 0,
             slow_wallet_unlocked: None,
             slow_wallet_transferred: None,
             slow_wallet_acc: false,
             donor_voice_acc: false,
             miner_height: None,
-            time: WarehouseTime::default(),
         }
     }
 }
@@ -48,78 +80,84 @@ impl WarehouseAccState {
             ..Default::default()
         }
     }
-    pub fn set_time(&mut self, timestamp: u64, version: u64, epoch: u64) {
-        self.time.timestamp = timestamp;
-        self.time.version = version;
-        self.time.epoch = epoch;
+
+    /// Sets the balance from base units of a framework version.
+    pub fn set_balance(&mut self, raw: u64, version: &FrameworkVersion) {
+        let amount = CoinAmount::new(raw, version);
+        self.balance_raw = amount.raw;
+        self.balance_normalized = amount.normalized;
+        self.balance = amount.coins();
+    }
+
+    /// Sets the slow wallet amounts from base units of a framework version.
+    pub fn set_slow_wallet(&mut self, unlocked: u64, transferred: u64, version: &FrameworkVersion) {
+        self.slow_wallet_acc = true;
+        self.slow_wallet_unlocked = Some(CoinAmount::new(unlocked, version).coins());
+        self.slow_wallet_transferred = Some(CoinAmount::new(transferred, version).coins());
     }
 }
 
 impl WarehouseAccState {
-    /// Converts the account state into a Cypher-compatible object string.
-    pub fn acc_state_to_cypher_map(&self) -> String {
-        let slow_wallet_unlocked_literal = match self.slow_wallet_unlocked {
-            Some(n) => n.to_string(),
-            None => "NULL".to_string(),
-        };
-        let slow_wallet_transferred_literal = match self.slow_wallet_transferred {
-            Some(n) => n.to_string(),
-            None => "NULL".to_string(),
-        };
-
-        let miner_height_literal = match self.miner_height {
-            Some(n) => n.to_string(),
-            None => "NULL".to_string(),
-        };
-
-        format!(
-            r#"{{address: "{}", balance: {}, version: {}, epoch: {},sequence_num: {}, slow_unlocked: {}, slow_transfer: {}, framework_version: "{}", slow_wallet: {}, donor_voice: {}, miner_height: {}}}"#,
-            self.address.to_hex_literal(),
-            self.balance,
-            self.time.version,
-            self.time.epoch,
-            self.sequence_num,
-            slow_wallet_unlocked_literal,
-            slow_wallet_transferred_literal,
-            self.time.framework_version,
-            self.slow_wallet_acc,
-            self.donor_voice_acc,
-            miner_height_literal
-        )
+    /// Converts the account state into a JSON object of its Cypher properties.
+    pub fn acc_state_to_cypher_json(&self) -> Value {
+        json!({
+            "address": self.address.to_hex_literal(),
+            "balance": self.balance,
+            "balance_raw": self.balance_raw,
+            "balance_normalized": self.balance_normalized,
+            "sequence_num": self.sequence_num,
+            "slow_unlocked": self.slow_wallet_unlocked,
+            "slow_transfer": self.slow_wallet_transferred,
+            "slow_wallet": self.slow_wallet_acc,
+            "donor_voice": self.donor_voice_acc,
+            "miner_height": self.miner_height,
+        })
     }
 
-    /// Converts a slice of account states into a Cypher map string.
-    pub fn to_cypher_map(list: &[Self]) -> String {
-        let mut list_literal = "".to_owned();
-        for el in list {
-            let s = el.acc_state_to_cypher_map();
-            list_literal.push_str(&s);
-            list_literal.push(',');
+    /// The Cypher properties of the account state with the time and
+    /// provenance of its snapshot, for sinks which store each snapshot as
+    /// one flat record.
+    pub fn to_record_json(&self, time: &WarehouseTime, provenance: Option<&Provenance>) -> Value {
+        let mut record = self.acc_state_to_cypher_json();
+        if let (Some(obj), Value::Object(t)) = (record.as_object_mut(), time.to_cypher_json()) {
+            obj.extend(t);
         }
-        list_literal.pop(); // need to drop last comma ","
-        format!("[{}]", list_literal)
+        with_provenance(record, provenance)
+    }
+
+    /// Converts a slice of account states into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        let list: Vec<Value> = list.iter().map(|s| s.acc_state_to_cypher_json()).collect();
+        json_list_to_bolt(&list)
     }
 
     /// Generates a Cypher query for batch inserting account states and snapshots.
-    pub fn cypher_batch_insert_str(list_str: &str) -> String {
+    /// The snapshot time and provenance are the `$time` and `$provenance`
+    /// parameters, sent once for the batch.
+    pub fn cypher_batch_insert_str() -> String {
         format!(
             r#"
-WITH {list_str} AS tx_data
-UNWIND tx_data AS tx
+UNWIND $batch AS tx
 
 MERGE (addr:Account {{address: tx.address}})
 MERGE (snap:Snapshot {{
     address: tx.address,
-    epoch: tx.epoch,
-    version: tx.version
+    epoch: $time.epoch,
+    version: $time.version
 }})
 
 SET
+  {},
   snap.balance = tx.balance,
-  snap.framework_version = tx.framework_version,
+  snap.balance_raw = tx.balance_raw,
+  snap.balance_normalized = tx.balance_normalized,
+  snap.framework_version = $time.framework_version,
   snap.sequence_num = tx.sequence_num,
   snap.slow_wallet = tx.slow_wallet,
-  snap.donor_voice = tx.donor_voice
+  snap.donor_voice = tx.donor_voice,
+  snap.archive_id = $provenance.archive_id,
+  snap.manifest_path = $provenance.manifest_path,
+  snap.chunk_sha256 = $provenance.chunk_sha256
 
 // Conditionally add `tx.miner_height` if it exists
 FOREACH (_ IN CASE WHEN tx.miner_height IS NOT NULL THEN [1] ELSE [] END |
@@ -136,11 +174,16 @@ FOREACH (_ IN CASE WHEN tx.slow_transfer IS NOT NULL THEN [1] ELSE [] END |
     SET snap.slow_transfer = tx.slow_transfer
 )
 
-MERGE (addr)-[rel:State {{version: tx.version}}]->(snap)
+MERGE (addr)-[rel:State {{version: $time.version}}]->(snap)
+SET
+  rel.archive_id = $provenance.archive_id,
+  rel.manifest_path = $provenance.manifest_path,
+  rel.chunk_sha256 = $provenance.chunk_sha256
 
 RETURN COUNT(snap) AS merged_snapshots
 
-"#
+"#,
+            cypher_add_archive_id("snap", "$provenance.archive_id")
         )
     }
 }
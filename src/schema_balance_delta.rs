@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use diem_crypto::HashValue;
+use libra_types::exports::AccountAddress;
+use neo4rs::BoltType;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    coin_normalize::{units_to_coins, COIN_DECIMAL_PRECISION},
+    cypher_templates::json_list_to_bolt,
+    provenance::{cypher_add_archive_id, with_provenance, Provenance},
+};
+
+/// Coin and slow wallet values of an account, as written by a transaction,
+/// in base units. Write sets are decoded from V6+ archives, so these are
+/// current units. `None` when the resource does not exist.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarehouseBalance {
+    pub balance: Option<u64>,
+    pub slow_wallet_unlocked: Option<u64>,
+    pub slow_wallet_transferred: Option<u64>,
+}
+
+/// The change in an account's balances caused by one transaction.
+///
+/// Decoded from the transaction's write set. The `after` values are exact,
+/// the `before` values are the last ones written earlier in the same archive.
+/// Archives are read independently, so the first time an account is touched
+/// in an archive `before` is unknown: it is `None`, stored as
+/// `before_known = false`, and the `change` is null.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarehouseBalanceDelta {
+    pub tx_hash: HashValue,
+    pub version: u64,
+    pub address: AccountAddress,
+    /// `None` when the account was not touched earlier in the archive
+    pub before: Option<WarehouseBalance>,
+    pub after: WarehouseBalance,
+    /// the archive and chunk file the write set was read from
+    pub provenance: Option<Arc<Provenance>>,
+}
+
+impl WarehouseBalanceDelta {
+    /// Net change of the coin balance in base units, if both sides are known.
+    pub fn balance_change(&self) -> Option<i64> {
+        let before = self.before.as_ref()?.balance?;
+        let after = self.after.balance?;
+        i64::try_from(after as i128 - before as i128).ok()
+    }
+
+    /// Net change of the coin balance in coins, for display.
+    pub fn balance_change_coins(&self) -> Option<f64> {
+        self.balance_change()
+            .map(|c| c as f64 / COIN_DECIMAL_PRECISION as f64)
+    }
+
+    /// Converts the balance delta into a JSON object of its Cypher properties.
+    pub fn to_cypher_json(&self) -> Value {
+        let before = self.before.clone().unwrap_or_default();
+        let change = self.balance_change();
+        let record = json!({
+            "tx_hash": self.tx_hash.to_hex_literal(),
+            "version": self.version,
+            "address": self.address.to_hex_literal(),
+            "before_known": self.before.is_some(),
+            "before_balance": before.balance.map(units_to_coins),
+            "after_balance": self.after.balance.map(units_to_coins),
+            "change": self.balance_change_coins(),
+            "before_balance_raw": before.balance,
+            "after_balance_raw": self.after.balance,
+            "change_raw": change,
+            "before_unlocked": before.slow_wallet_unlocked.map(units_to_coins),
+            "after_unlocked": self.after.slow_wallet_unlocked.map(units_to_coins),
+            "before_transferred": before.slow_wallet_transferred.map(units_to_coins),
+            "after_transferred": self.after.slow_wallet_transferred.map(units_to_coins),
+        });
+        with_provenance(record, self.provenance.as_deref())
+    }
+
+    /// Converts a slice of balance deltas into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        let list: Vec<Value> = list.iter().map(|d| d.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
+    }
+
+    /// Generates a Cypher query for batch inserting balance deltas.
+    pub fn cypher_batch_insert_str() -> String {
+        format!(
+            r#"
+UNWIND $batch AS d
+
+MERGE (acc:Account {{address: d.address}})
+MERGE (bd:BalanceDelta {{tx_hash: d.tx_hash, address: d.address}})
+SET
+  {},
+  bd.version = d.version,
+  bd.before_known = d.before_known,
+  bd.before_balance = d.before_balance,
+  bd.after_balance = d.after_balance,
+  bd.change = d.change,
+  bd.before_balance_raw = d.before_balance_raw,
+  bd.after_balance_raw = d.after_balance_raw,
+  bd.change_raw = d.change_raw,
+  bd.before_unlocked = d.before_unlocked,
+  bd.after_unlocked = d.after_unlocked,
+  bd.before_transferred = d.before_transferred,
+  bd.after_transferred = d.after_transferred,
+  bd.archive_id = d.archive_id,
+  bd.manifest_path = d.manifest_path,
+  bd.chunk_sha256 = d.chunk_sha256
+
+MERGE (acc)-[:Delta]->(bd)
+
+RETURN COUNT(bd) AS merged_deltas
+"#,
+            cypher_add_archive_id("bd", "d.archive_id")
+        )
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use libra_types::exports::AccountAddress;
+use neo4rs::BoltType;
+use serde_json::{json, Value};
+
+use crate::{
+    cypher_templates::json_list_to_bolt,
+    provenance::{cypher_add_archive_id, with_provenance, Provenance},
+};
+
+/// A member of an epoch's validator set and its voting power.
+#[derive(Debug, Clone)]
+pub struct WarehouseValidator {
+    pub address: AccountAddress,
+    pub voting_power: u64,
+}
+
+/// The warehouse record for an epoch boundary.
+///
+/// Decoded from the ledger info which ends the previous epoch, and which
+/// carries the validator set for the epoch that follows.
+#[derive(Debug, Clone, Default)]
+pub struct WarehouseEpoch {
+    pub epoch: u64,
+    pub first_version: u64,
+    pub timestamp: u64,
+    pub validators: Vec<WarehouseValidator>,
+    /// the archive and chunk file the ledger info was read from
+    pub provenance: Option<Arc<Provenance>>,
+}
+
+impl WarehouseEpoch {
+    /// Converts the epoch into a JSON object of its Cypher properties.
+    pub fn to_cypher_json(&self) -> Value {
+        let validators: Vec<Value> = self
+            .validators
+            .iter()
+            .map(|v| {
+                json!({
+                    "address": v.address.to_hex_literal(),
+                    "voting_power": v.voting_power,
+                })
+            })
+            .collect();
+
+        let datetime =
+            DateTime::<Utc>::from_timestamp_micros(self.timestamp as i64).unwrap_or_default();
+
+        let record = json!({
+            "epoch": self.epoch,
+            "first_version": self.first_version,
+            "timestamp": self.timestamp,
+            "start_datetime": datetime.to_rfc3339(),
+            "validators": validators,
+        });
+        with_provenance(record, self.provenance.as_deref())
+    }
+
+    /// Converts a slice of epochs into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        let list: Vec<Value> = list.iter().map(|e| e.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
+    }
+
+    /// Generates a Cypher query for batch inserting epochs and their validator sets.
+    pub fn cypher_batch_insert_str() -> String {
+        format!(
+            r#"
+UNWIND $batch AS ep
+
+MERGE (e:Epoch {{epoch: ep.epoch}})
+SET
+  {},
+  e.first_version = ep.first_version,
+  e.timestamp = ep.timestamp,
+  e.start_datetime = datetime(ep.start_datetime),
+  e.archive_id = ep.archive_id,
+  e.manifest_path = ep.manifest_path,
+  e.chunk_sha256 = ep.chunk_sha256
+
+// the ledger info which starts this epoch also closes the previous one, so
+// the previous epoch is shared with this archive
+MERGE (prev:Epoch {{epoch: ep.epoch - 1}})
+SET
+  prev.last_version = ep.first_version - 1,
+  {}
+MERGE (prev)-[:NextEpoch]->(e)
+
+FOREACH (val IN ep.validators |
+    MERGE (acc:Account {{address: val.address}})
+    SET acc:Validator
+    MERGE (acc)-[rel:ValidatorSet {{epoch: ep.epoch}}]->(e)
+    SET
+      rel.voting_power = val.voting_power,
+      rel.archive_id = ep.archive_id,
+      rel.manifest_path = ep.manifest_path,
+      rel.chunk_sha256 = ep.chunk_sha256
+)
+
+RETURN COUNT(e) AS merged_epochs
+"#,
+            cypher_add_archive_id("e", "ep.archive_id"),
+            cypher_add_archive_id("prev", "ep.archive_id")
+        )
+    }
+}
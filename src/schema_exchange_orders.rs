@@ -2,8 +2,11 @@ use std::fmt;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-
+use neo4rs::BoltType;
 use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+
+use crate::cypher_templates::json_list_to_bolt;
 
 /// The type of an exchange order (Buy or Sell).
 #[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
@@ -83,55 +86,46 @@ impl Default for ExchangeOrder {
 }
 
 impl ExchangeOrder {
-    /// Converts the exchange order into a Cypher-compatible object string.
-    pub fn to_cypher_object_template(&self) -> String {
-        format!(
-            r#"{{user: {}, accepter: {}, order_type: "{}", amount: {}, price:{}, created_at: datetime("{}"), created_at_ts: {}, filled_at: datetime("{}"), filled_at_ts: {}, accepter_shill_down: {}, accepter_shill_up: {}, rms_hour: {}, rms_24hour: {}, price_vs_rms_hour: {}, price_vs_rms_24hour: {} }}"#,
-            self.user,
-            self.accepter,
-            self.order_type,
-            self.amount,
-            self.price,
-            self.created_at.to_rfc3339(),
-            self.created_at.timestamp_micros(),
-            self.filled_at.to_rfc3339(),
-            self.filled_at.timestamp_micros(),
-            self.accepter_shill_down,
-            self.accepter_shill_up,
-            self.rms_hour,
-            self.rms_24hour,
-            self.price_vs_rms_hour,
-            self.price_vs_rms_24hour,
-        )
+    /// Converts the exchange order into a JSON object of its Cypher properties.
+    pub fn to_cypher_json(&self) -> Value {
+        json!({
+            "user": self.user,
+            "accepter": self.accepter,
+            "order_type": self.order_type.to_string(),
+            "amount": self.amount,
+            "price": self.price,
+            "created_at": self.created_at.to_rfc3339(),
+            "created_at_ts": self.created_at.timestamp_micros(),
+            "filled_at": self.filled_at.to_rfc3339(),
+            "filled_at_ts": self.filled_at.timestamp_micros(),
+            "accepter_shill_down": self.accepter_shill_down,
+            "accepter_shill_up": self.accepter_shill_up,
+            "rms_hour": self.rms_hour,
+            "rms_24hour": self.rms_24hour,
+            "price_vs_rms_hour": self.price_vs_rms_hour,
+            "price_vs_rms_24hour": self.price_vs_rms_24hour,
+        })
     }
 
-    /// Converts a slice of exchange orders into a Cypher map string.
-    pub fn to_cypher_map(list: &[Self]) -> String {
-        let mut list_literal = "".to_owned();
-        for el in list {
-            let s = el.to_cypher_object_template();
-            list_literal.push_str(&s);
-            list_literal.push(',');
-        }
-        list_literal.pop(); // need to drop last comma ","
-        format!("[{}]", list_literal)
+    /// Converts a slice of exchange orders into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        let list: Vec<Value> = list.iter().map(|o| o.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
     }
 
     /// Generates a Cypher query for batch inserting exchange orders.
-    pub fn cypher_batch_insert_str(list_str: String) -> String {
-        format!(
-            r#"
-  WITH {list_str} AS tx_data
-  UNWIND tx_data AS tx
-  MERGE (maker:SwapAccount {{swap_id: tx.user}})
-  MERGE (taker:SwapAccount {{swap_id: tx.accepter}})
-  MERGE (maker)-[rel:Swap {{
+    pub fn cypher_batch_insert_str() -> String {
+        r#"
+  UNWIND $batch AS tx
+  MERGE (maker:SwapAccount {swap_id: tx.user})
+  MERGE (taker:SwapAccount {swap_id: tx.accepter})
+  MERGE (maker)-[rel:Swap {
     order_type: tx.order_type,
     amount: tx.amount,
     price: tx.price,
-    created_at: tx.created_at,
+    created_at: datetime(tx.created_at),
     created_at_ts: tx.created_at_ts,
-    filled_at: tx.filled_at,
+    filled_at: datetime(tx.filled_at),
     filled_at_ts: tx.filled_at_ts,
     accepter_shill_up: tx.accepter_shill_up,
     accepter_shill_down: tx.accepter_shill_down,
@@ -139,7 +133,7 @@ impl ExchangeOrder {
     rms_24hour: tx.rms_24hour,
     price_vs_rms_hour: tx.price_vs_rms_hour,
     price_vs_rms_24hour: tx.price_vs_rms_24hour
-  }}]->(taker)
+  }]->(taker)
 
   ON CREATE SET rel.created = true
   ON MATCH SET rel.created = false
@@ -148,7 +142,7 @@ impl ExchangeOrder {
       COUNT(CASE WHEN rel.created = true THEN 1 END) AS merged_tx_count,
       COUNT(CASE WHEN rel.created = false THEN 1 END) AS ignored_tx_count
 "#
-        )
+        .to_string()
     }
 }
 
@@ -1,5 +1,9 @@
 use crate::{
-    cypher_templates::to_cypher_object, scan::FrameworkVersion, util::COIN_DECIMAL_PRECISION,
+    coin_normalize::CoinAmount,
+    cypher_templates::{json_list_to_bolt, to_cypher_object},
+    provenance::{cypher_add_archive_id, with_provenance, Provenance},
+    scan::FrameworkVersion,
+    schema_balance_delta::WarehouseBalanceDelta,
 };
 
 use chrono::{DateTime, Utc};
@@ -12,19 +16,57 @@ use libra_backwards_compatibility::sdk::{
     v7_libra_framework_sdk_builder::EntryFunctionCall as V7EntryFunctionCall,
 };
 use libra_types::{exports::AccountAddress, move_resource::coin_register_event::CoinRegisterEvent};
+use neo4rs::BoltType;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::sync::Arc;
 
+/// The relationship types transaction edges are loaded as, one for each
+/// `RelationLabel::to_cypher_label`.
+pub static TX_RELATION_TYPES: &[&str] = &[
+    "Unknown",
+    "Transfer",
+    "Onboarding",
+    "Vouch",
+    "DonorVoice",
+    "CommunityWallet",
+    "Multisig",
+    "SlowWallet",
+    "ProofOfFeeBid",
+    "ValidatorRegistration",
+    "VouchRevoke",
+    "Unjail",
+    "Configuration",
+    "Miner",
+];
+
 /// High-level categorization of transaction relationships.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RelationLabel {
     Unknown, // undefined tx
-    // NOTE: amount u64 includes:
-    // - legacy multiplier in v6 rebase (for all pre-v6 data)
-    // - decimal precision scaling
+    // NOTE: the amount is in base units of the transaction's framework
+    // version, see `WarehouseTxMaster::coin_amount` to compare across versions
     Transfer(AccountAddress, u64),
     Onboarding(AccountAddress, u64),
     Vouch(AccountAddress),
+    // the counterparty is the donor voice account the proposal or veto is for
+    DonorVoice(AccountAddress),
+    // a proposed payment from the donor voice account, to the payee, of an
+    // amount in base units. Nothing moves until the payment is executed, so
+    // it is not a transfer.
+    DonorVoicePayment(AccountAddress, AccountAddress, u64),
+    // the signers a community wallet is initialized with, or offered to. The
+    // sender is the community wallet, so an edge goes to each signer.
+    CommunityWallet(Vec<AccountAddress>),
+    // the multisig account whose authority offer is claimed
+    Multisig(AccountAddress),
+    // the following are actions on the sender's own account, so their edge
+    // points to the framework account rather than back to the sender
+    SlowWallet,
+    ProofOfFeeBid,
+    ValidatorRegistration,
+    VouchRevoke(AccountAddress),
+    Unjail(AccountAddress),
     Configuration,
     Miner,
 }
@@ -36,39 +78,70 @@ impl RelationLabel {
             RelationLabel::Transfer(..) => "Transfer".to_owned(),
             RelationLabel::Onboarding(..) => "Onboarding".to_owned(),
             RelationLabel::Vouch(..) => "Vouch".to_owned(),
+            RelationLabel::DonorVoice(..) | RelationLabel::DonorVoicePayment(..) => {
+                "DonorVoice".to_owned()
+            }
+            RelationLabel::CommunityWallet(..) => "CommunityWallet".to_owned(),
+            RelationLabel::Multisig(..) => "Multisig".to_owned(),
+            RelationLabel::SlowWallet => "SlowWallet".to_owned(),
+            RelationLabel::ProofOfFeeBid => "ProofOfFeeBid".to_owned(),
+            RelationLabel::ValidatorRegistration => "ValidatorRegistration".to_owned(),
+            RelationLabel::VouchRevoke(..) => "VouchRevoke".to_owned(),
+            RelationLabel::Unjail(..) => "Unjail".to_owned(),
             RelationLabel::Configuration => "Configuration".to_owned(),
             RelationLabel::Miner => "Miner".to_owned(),
         }
     }
 
+    /// The recipient of the transaction, or the first of them when it has
+    /// several, see `get_recipients`.
     pub fn get_recipient(&self) -> Option<AccountAddress> {
         match &self {
             RelationLabel::Unknown => None,
             RelationLabel::Transfer(account_address, _) => Some(*account_address),
             RelationLabel::Onboarding(account_address, _) => Some(*account_address),
             RelationLabel::Vouch(account_address) => Some(*account_address),
+            RelationLabel::DonorVoice(account_address) => Some(*account_address),
+            RelationLabel::DonorVoicePayment(account_address, ..) => Some(*account_address),
+            RelationLabel::CommunityWallet(signers) => signers.first().copied(),
+            RelationLabel::Multisig(account_address) => Some(*account_address),
+            RelationLabel::SlowWallet => Some(AccountAddress::ONE),
+            RelationLabel::ProofOfFeeBid => Some(AccountAddress::ONE),
+            RelationLabel::ValidatorRegistration => Some(AccountAddress::ONE),
+            RelationLabel::VouchRevoke(account_address) => Some(*account_address),
+            RelationLabel::Unjail(account_address) => Some(*account_address),
             RelationLabel::Configuration => None,
             RelationLabel::Miner => None,
         }
     }
 
-    pub fn get_coins_human_readable(&self) -> Option<f64> {
+    /// All the recipients of the transaction, each of which gets an edge.
+    /// Only community wallet offers have more than one.
+    pub fn get_recipients(&self) -> Vec<AccountAddress> {
         match &self {
-            RelationLabel::Transfer(_, amount) => {
-                if *amount > 0 {
-                    let human = (*amount as f64) / COIN_DECIMAL_PRECISION as f64;
-                    return Some(human);
-                }
-            }
-            RelationLabel::Onboarding(_, amount) => {
-                if *amount > 0 {
-                    let human = (*amount as f64) / COIN_DECIMAL_PRECISION as f64;
-                    return Some(human);
-                }
+            RelationLabel::CommunityWallet(signers) => signers.clone(),
+            _ => self.get_recipient().into_iter().collect(),
+        }
+    }
+
+    /// Coins sent, in base units of the transaction's framework version,
+    /// if any.
+    pub fn get_coins_raw(&self) -> Option<u64> {
+        match &self {
+            RelationLabel::Transfer(_, amount) | RelationLabel::Onboarding(_, amount) => {
+                Some(*amount).filter(|a| *a > 0)
             }
-            _ => {}
+            _ => None,
+        }
+    }
+
+    /// The payee and amount of a proposed donor voice payment, in base
+    /// units, if any.
+    pub fn get_payment(&self) -> Option<(AccountAddress, u64)> {
+        match &self {
+            RelationLabel::DonorVoicePayment(_, payee, amount) => Some((*payee, *amount)),
+            _ => None,
         }
-        None
     }
 }
 
@@ -76,9 +149,77 @@ impl RelationLabel {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WarehouseEvent {
     pub tx_hash: HashValue,
+    /// position of the event in the transaction's event list
+    pub index: u64,
+    /// the account whose event handle emitted the event
+    pub account: AccountAddress,
     pub event: UserEventTypes,
     pub event_name: String,
     pub data: serde_json::Value,
+    /// the archive and chunk file the event was read from
+    pub provenance: Option<Arc<Provenance>>,
+}
+
+impl WarehouseEvent {
+    /// Coins moved by a withdraw or deposit event, in base units.
+    pub fn get_amount(&self) -> Option<u64> {
+        match &self.event {
+            UserEventTypes::Withdraw(e) => Some(e.amount()),
+            UserEventTypes::Deposit(e) => Some(e.amount()),
+            _ => None,
+        }
+    }
+
+    /// Converts the event into a JSON object of its Cypher properties.
+    pub fn to_cypher_json(&self) -> Value {
+        let record = json!({
+            "tx_hash": self.tx_hash.to_hex_literal(),
+            "index": self.index,
+            "account": self.account.to_hex_literal(),
+            "event_type": self.event.to_cypher_label(),
+            "event_name": self.event_name,
+            "amount": self.get_amount(),
+            // the JSON data is stored as a string property
+            "data": self.data.to_string(),
+        });
+        with_provenance(record, self.provenance.as_deref())
+    }
+
+    /// Converts a slice of events into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(list: &[Self]) -> BoltType {
+        let list: Vec<Value> = list.iter().map(|e| e.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
+    }
+
+    /// Generates a Cypher query for batch inserting events.
+    pub fn cypher_batch_insert_str() -> String {
+        format!(
+            r#"
+UNWIND $batch AS ev
+
+MERGE (acc:Account {{address: ev.account}})
+MERGE (e:Event {{tx_hash: ev.tx_hash, index: ev.index}})
+SET
+  {},
+  e.event_type = ev.event_type,
+  e.event_name = ev.event_name,
+  e.data = ev.data,
+  e.archive_id = ev.archive_id,
+  e.manifest_path = ev.manifest_path,
+  e.chunk_sha256 = ev.chunk_sha256
+
+// Conditionally add `ev.amount` if it exists
+FOREACH (_ IN CASE WHEN ev.amount IS NOT NULL THEN [1] ELSE [] END |
+    SET e.amount = ev.amount
+)
+
+MERGE (acc)-[:Emitted]->(e)
+
+RETURN COUNT(e) AS merged_events
+"#,
+            cypher_add_archive_id("e", "ev.archive_id")
+        )
+    }
 }
 
 /// Supported user event types.
@@ -90,6 +231,17 @@ pub enum UserEventTypes {
     Other,
 }
 
+impl UserEventTypes {
+    pub fn to_cypher_label(&self) -> String {
+        match self {
+            UserEventTypes::Withdraw(_) => "Withdraw".to_owned(),
+            UserEventTypes::Deposit(_) => "Deposit".to_owned(),
+            UserEventTypes::Onboard(_) => "Onboard".to_owned(),
+            UserEventTypes::Other => "Other".to_owned(),
+        }
+    }
+}
+
 /// Arguments for different versions of entry functions.
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub enum EntryFunctionArgs {
@@ -108,14 +260,29 @@ pub struct WarehouseTxMaster {
     pub relation_label: RelationLabel,
     pub sender: AccountAddress,
     pub function: String,
+    /// global ledger version, gives a total order of transactions
+    pub version: u64,
     pub epoch: u64,
     pub round: u64,
     pub block_timestamp: u64,
     pub block_datetime: DateTime<Utc>,
     pub expiration_timestamp: u64,
+    pub sequence_number: u64,
+    pub gas_used: u64,
+    pub gas_unit_price: u64,
+    pub max_gas_amount: u64,
+    /// VM execution status, e.g. `Success` or `MoveAbort`
+    pub status: String,
+    pub abort_code: Option<u64>,
+    /// module where a failed transaction aborted
+    pub abort_location: Option<String>,
     pub entry_function: Option<EntryFunctionArgs>,
     pub events: Vec<WarehouseEvent>,
+    /// balances changed by the transaction's write set
+    pub balance_deltas: Vec<WarehouseBalanceDelta>,
     pub framework_version: FrameworkVersion,
+    /// the archive and chunk file the transaction was read from
+    pub provenance: Option<Arc<Provenance>>,
 }
 
 impl Default for WarehouseTxMaster {
@@ -125,59 +292,97 @@ impl Default for WarehouseTxMaster {
             relation_label: RelationLabel::Configuration,
             sender: AccountAddress::ZERO,
             function: "none".to_owned(),
+            version: 0,
             epoch: 0,
             round: 0,
             block_timestamp: 0,
             block_datetime: DateTime::<Utc>::from_timestamp_micros(0).unwrap(),
             expiration_timestamp: 0,
+            sequence_number: 0,
+            gas_used: 0,
+            gas_unit_price: 0,
+            max_gas_amount: 0,
+            status: "Success".to_owned(),
+            abort_code: None,
+            abort_location: None,
             entry_function: None,
             events: vec![],
+            balance_deltas: vec![],
             framework_version: FrameworkVersion::Unknown,
+            provenance: None,
         }
     }
 }
 
 impl WarehouseTxMaster {
-    /// Converts the transaction into a Cypher-compatible object string.
-    pub fn to_cypher_object_template(&self) -> String {
-        // make blank string or nest the arguments
-        let mut tx_args = "NULL".to_string();
-        if let Some(args) = &self.entry_function {
-            if let Ok(st) = to_cypher_object(args) {
-                tx_args = st;
-            }
-        };
-        let mut coins_literal = "NULL".to_string();
-        if let Some(c) = &self.relation_label.get_coins_human_readable() {
-            if c > &0.0 {
-                coins_literal = format!("{:.2}", c);
-            }
-        };
-        format!(
-            r#"{{ args: {tx_args}, coins: {coins_literal}, tx_hash: "{}", block_datetime: datetime("{}"), block_timestamp: {}, relation: "{}", function: "{}", sender: "{}", recipient: "{}", framework_version: "{}"}}"#,
-            self.tx_hash.to_hex_literal(),
-            self.block_datetime.to_rfc3339(),
-            self.block_timestamp,
-            self.relation_label.to_cypher_label(),
-            self.function,
-            self.sender.to_hex_literal(),
-            self.relation_label
+    /// The accounts the transaction's edges point to: its recipients, or the
+    /// sender when it has none, like the `recipient` property.
+    pub fn recipients(&self) -> Vec<AccountAddress> {
+        let recipients = self.relation_label.get_recipients();
+        if recipients.is_empty() {
+            vec![self.sender]
+        } else {
+            recipients
+        }
+    }
+
+    /// Coins sent, as read and normalized by the framework version, if any.
+    pub fn coin_amount(&self) -> Option<CoinAmount> {
+        self.relation_label
+            .get_coins_raw()
+            .map(|raw| CoinAmount::new(raw, &self.framework_version))
+    }
+
+    /// Converts the transaction into a JSON object of its Cypher properties.
+    pub fn to_cypher_json(&self) -> Value {
+        // flatten the arguments, or leave them null
+        let tx_args = self
+            .entry_function
+            .as_ref()
+            .and_then(|args| to_cypher_object(args).ok());
+
+        // the exact amounts are in base units, `coins` is rounded for display
+        let amount = self.coin_amount();
+        let coins = amount.map(|a| (a.coins() * 100.0).round() / 100.0);
+        let payment = self.relation_label.get_payment();
+
+        let record = json!({
+            "args": tx_args,
+            "coins": coins,
+            "coins_raw": amount.map(|a| a.raw),
+            "coins_normalized": amount.map(|a| a.normalized),
+            "payee": payment.map(|(payee, _)| payee.to_hex_literal()),
+            "payment_coins_raw": payment.map(|(_, amount)| amount),
+            "tx_hash": self.tx_hash.to_hex_literal(),
+            "version": self.version,
+            "sequence_number": self.sequence_number,
+            "gas_used": self.gas_used,
+            "gas_unit_price": self.gas_unit_price,
+            "max_gas_amount": self.max_gas_amount,
+            "status": self.status,
+            "abort_code": self.abort_code,
+            "abort_location": self.abort_location,
+            "block_datetime": self.block_datetime.to_rfc3339(),
+            "block_timestamp": self.block_timestamp,
+            "relation": self.relation_label.to_cypher_label(),
+            "function": self.function,
+            "sender": self.sender.to_hex_literal(),
+            "recipient": self.relation_label
                 .get_recipient()
                 .unwrap_or(self.sender)
                 .to_hex_literal(),
-            self.framework_version
-        )
+            "recipients": self.recipients()
+                .iter()
+                .map(|a| a.to_hex_literal())
+                .collect::<Vec<_>>(),
+            "framework_version": self.framework_version.to_string(),
+        });
+        with_provenance(record, self.provenance.as_deref())
     }
 
-    /// Converts a slice of transactions into a Cypher map string.
-    pub fn to_cypher_map(txs: &[Self]) -> String {
-        let mut list_literal = "".to_owned();
-        for el in txs {
-            let s = el.to_cypher_object_template();
-            list_literal.push_str(&s);
-            list_literal.push(',');
-        }
-        list_literal.pop(); // need to drop last comma ","
-        format!("[{}]", list_literal)
+    /// Converts a slice of transactions into a Bolt list for the `$batch` parameter.
+    pub fn to_cypher_map(txs: &[Self]) -> BoltType {
+        let list: Vec<Value> = txs.iter().map(|t| t.to_cypher_json()).collect();
+        json_list_to_bolt(&list)
     }
 }
@@ -1,21 +0,0 @@
-use sqlx::{PgPool, SqlitePool};
-
-pub async fn maybe_init(pool: &SqlitePool) -> anyhow::Result<()> {
-    // run through all the migrations in ./sql/migrations.
-    // note: the path is relative to project root
-    // and the naming of the files follow a pattern, see migrations/README.md
-    sqlx::migrate!("tests/fixtures/sql/migrations")
-        .run(pool)
-        .await?;
-    Ok(())
-}
-
-pub async fn maybe_init_pg(pool: &PgPool) -> anyhow::Result<()> {
-    // run through all the migrations in ./sql/migrations.
-    // note: the path is relative to project root
-    // and the naming of the files follow a pattern, see migrations/README.md
-    sqlx::migrate!("tests/fixtures/sql/migrations")
-        .run(pool)
-        .await?;
-    Ok(())
-}
@@ -73,3 +73,17 @@ pub fn v6_tx_manifest_fixtures_path() -> PathBuf {
     );
     dir
 }
+
+pub fn v7_epoch_ending_fixtures_path() -> PathBuf {
+    let p = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .canonicalize()
+        .unwrap();
+    assert!(&p.exists(), "cannot find project root dir");
+    let dir = p.join("tests/fixtures/v7/epoch_ending_116-.be9b");
+    assert!(
+        &dir.exists(),
+        "fixtures for backup archive cannot be found at path {}",
+        &dir.display()
+    );
+    dir
+}
@@ -1,12 +1,13 @@
 mod support;
 use anyhow::Result;
+use diem_types::account_address::AccountAddress;
 use std::path::PathBuf;
 
 use libra_forensic_db::{
     analytics::{
         self,
         enrich_account_funding::BalanceTracker,
-        offline_matching::{self, Matching},
+        offline_matching::{self, Deposit, Matching, MinFunding},
     },
     extract_exchange_orders, load_exchange_orders,
     neo4j_init::{self, get_neo4j_localhost_pool, maybe_create_indexes},
@@ -291,3 +292,22 @@ async fn test_easy_sellers() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_match_exact_sellers_base_units() {
+    let user = MinFunding {
+        user_id: 1,
+        funded: 100.0,
+    };
+    let exact: AccountAddress = "0x1".parse().unwrap();
+    let over: AccountAddress = "0x2".parse().unwrap();
+    // 100.000001 coins rounds to the funding in two decimals, but is more
+    let deposits = vec![
+        Deposit::new(exact, 100_000_000),
+        Deposit::new(over, 100_000_001),
+    ];
+
+    let mut m = Matching::new();
+    m.match_exact_sellers(&[user], &deposits, 1.05);
+    assert!(m.definite[&1] == over);
+}
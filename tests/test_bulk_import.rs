@@ -0,0 +1,55 @@
+mod support;
+
+use anyhow::Result;
+use diem_temppath::TempPath;
+use libra_forensic_db::{bulk_import::export_archive_map, scan::scan_dir_archive};
+use support::fixtures;
+
+/// Counts the rows of a CSV, without its header.
+fn count_rows(path: &std::path::Path) -> Result<usize> {
+    Ok(std::fs::read_to_string(path)?.lines().count() - 1)
+}
+
+#[tokio::test]
+async fn test_bulk_import_csv() -> Result<()> {
+    let mut map = scan_dir_archive(&fixtures::v6_tx_manifest_fixtures_path(), None)?;
+    let state = scan_dir_archive(&fixtures::v7_state_manifest_fixtures_path(), None)?;
+    map.0.extend(state.0);
+    assert!(map.0.len() == 2);
+
+    let temp = TempPath::new();
+    temp.create_as_dir()?;
+    let out_dir = temp.path();
+    let summary = export_archive_map(&map, out_dir, false).await?;
+
+    assert!(summary.archives == 2);
+    assert!(summary.transactions == 25);
+    assert!(summary.lifetime == 18);
+    assert!(summary.snapshots > 0);
+
+    // accounts are deduplicated across the archives
+    let accounts = count_rows(&out_dir.join("accounts.csv"))?;
+    assert!(accounts as u64 == summary.accounts);
+    assert!(accounts as u64 <= 25 + summary.snapshots);
+    assert!(count_rows(&out_dir.join("lifetime.csv"))? == 18);
+    assert!(count_rows(&out_dir.join("queue.csv"))? == 2);
+
+    // every written file is in the import arguments
+    let args = std::fs::read_to_string(out_dir.join("import.args"))?;
+    assert!(args.contains("--nodes=Account="));
+    assert!(args.contains("--relationships=Transfer="));
+    assert!(args.contains("--nodes=Snapshot="));
+    assert!(args.contains("--relationships=State="));
+
+    let tx_files = glob::glob(out_dir.join("tx/*/*.csv").to_str().unwrap())?;
+    let mut tx_rows = 0;
+    for path in tx_files {
+        let path = path?;
+        let header = std::fs::read_to_string(&path)?;
+        assert!(header.starts_with(":START_ID(Account),:END_ID(Account),tx_hash"));
+        tx_rows += count_rows(&path)?;
+    }
+    assert!(tx_rows == 25);
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use libra_forensic_db::{
+    coin_normalize::{scale_whole_coins, LEGACY_REBASE_MULTIPLIER},
+    scan::FrameworkVersion,
+    schema_account_state::WarehouseAccState,
+    schema_transaction::{RelationLabel, WarehouseTxMaster},
+};
+use libra_types::exports::AccountAddress;
+
+#[test]
+fn test_normalize_transfers_across_versions() {
+    let recipient: AccountAddress = "0xb0b".parse().unwrap();
+    let v5 = WarehouseTxMaster {
+        relation_label: RelationLabel::Transfer(recipient, scale_whole_coins(1)),
+        framework_version: FrameworkVersion::V5,
+        ..Default::default()
+    };
+    let v7 = WarehouseTxMaster {
+        relation_label: RelationLabel::Transfer(recipient, scale_whole_coins(35)),
+        framework_version: FrameworkVersion::V7,
+        ..Default::default()
+    };
+
+    // one V5 coin is worth 35 current coins
+    let record = v5.to_cypher_json();
+    assert!(record["coins_raw"] == 1_000_000);
+    assert!(record["coins_normalized"] == 35_000_000);
+    assert!(record["coins"] == 35.0);
+    assert!(v5.coin_amount().unwrap().normalized == v7.coin_amount().unwrap().normalized);
+
+    let record = v7.to_cypher_json();
+    assert!(record["coins_raw"] == record["coins_normalized"]);
+
+    // transactions without coins have no amounts
+    let vouch = WarehouseTxMaster {
+        relation_label: RelationLabel::Vouch(recipient),
+        ..Default::default()
+    };
+    assert!(vouch.to_cypher_json()["coins_normalized"].is_null());
+}
+
+#[test]
+fn test_normalize_snapshot_balances() {
+    let mut v5 = WarehouseAccState::default();
+    v5.set_balance(1_500_000, &FrameworkVersion::V5);
+    v5.set_slow_wallet(1_000_000, 0, &FrameworkVersion::V5);
+    assert!(v5.balance_raw == 1_500_000);
+    assert!(v5.balance_normalized == 1_500_000 * LEGACY_REBASE_MULTIPLIER);
+    assert!(v5.balance == 52.5);
+    assert!(v5.slow_wallet_unlocked == Some(35.0));
+
+    let mut v7 = WarehouseAccState::default();
+    v7.set_balance(52_500_000, &FrameworkVersion::V7);
+    assert!(v7.balance_normalized == v5.balance_normalized);
+    assert!(v7.balance == v5.balance);
+}
@@ -0,0 +1,127 @@
+mod support;
+
+use anyhow::Result;
+use libra_forensic_db::{
+    db_doctor::run_doctor,
+    neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
+};
+use neo4rs::query;
+use support::neo4j_testcontainer::start_neo4j_container;
+
+#[tokio::test]
+async fn test_db_doctor_repair() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+    graph.run(query("CALL db.awaitIndexes()")).await?;
+
+    // a healthy graph has no problems
+    let report = run_doctor(&graph, false, 24).await?;
+    assert!(report.unresolved() == 0);
+
+    // the leftovers of a crashed load
+    graph
+        .run(query(
+            r#"
+        MERGE (a:Account {address: "0xa11ce"})
+        MERGE (b:Account {address: "0xb0b"})
+        MERGE (a)-[:Transfer {tx_hash: "0x01", coins: 10.0}]->(b)
+        MERGE (a)-[:Lifetime {coins: 30.0}]->(b)
+        CREATE (a)-[:Transfer {coins: 5.0}]->(b)
+        CREATE (:Snapshot {address: "0xb0b", epoch: 1, version: 100})
+        CREATE (:SwapAccount {swap_id: 7})-[:Swap {amount: 1.0}]->(:SwapAccount {swap_id: 8})
+        CREATE (:SwapAccount {swap_id: 7})
+        CREATE (:Queue {archive_id: "archive", batch: 0, completed: false, started_at: 0, updated_at: 0})
+        CREATE (:Queue {archive_id: "archive", batch: 1, completed: false, updated_at: 0})
+        "#,
+        ))
+        .await?;
+
+    let report = run_doctor(&graph, false, 24).await?;
+    let problems: Vec<u64> = report.checks.iter().map(|c| c.problems).collect();
+    // schema, tx_hash, snapshots, lifetime, swap accounts, queue
+    assert!(problems == vec![0, 1, 1, 1, 1, 1]);
+    assert!(report.unresolved() == 5);
+
+    let report = run_doctor(&graph, true, 24).await?;
+    assert!(report.unresolved() == 0);
+
+    // the repairs hold
+    let report = run_doctor(&graph, false, 24).await?;
+    // the stuck batch is now a dead letter, which is not stuck
+    assert!(report.unresolved() == 0);
+
+    let mut result = graph
+        .execute(query(
+            "MATCH (:Account {address: '0xa11ce'})-[r:Lifetime]->() RETURN r.coins AS coins",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<f64>("coins").unwrap() == 10.0);
+
+    let mut result = graph
+        .execute(query(
+            "MATCH (s:SwapAccount {swap_id: 7})-[:Swap]->() RETURN count(s) AS swaps",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("swaps").unwrap() == 1);
+
+    // the batch which was queued but never started is left to the next load
+    let mut result = graph
+        .execute(query(
+            "MATCH (a:Queue {archive_id: 'archive', batch: 1}) RETURN a.error IS NULL AS pending",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<bool>("pending").unwrap());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_db_doctor_legacy_tx() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+    graph.run(query("CALL db.awaitIndexes()")).await?;
+
+    // a graph loaded before the typed relations, not migrated yet
+    graph
+        .run(query(
+            r#"
+        MERGE (a:Account {address: "0xa11ce"})
+        MERGE (b:Account {address: "0xb0b"})
+        MERGE (a)-[:Tx {tx_hash: "0x01", relation: "Transfer", coins: 10.0}]->(b)
+        MERGE (a)-[:Tx {tx_hash: "0x02", relation: "Transfer", coins: 5.0}]->(b)
+        MERGE (a)-[:Lifetime {coins: 15.0, tx_count: 2}]->(b)
+        "#,
+        ))
+        .await?;
+
+    // the totals agree with the legacy edges, so nothing is rebuilt
+    let report = run_doctor(&graph, true, 24).await?;
+    let lifetime = report
+        .checks
+        .iter()
+        .find(|c| c.name.contains("Lifetime"))
+        .unwrap();
+    assert!(lifetime.problems == 0);
+    assert!(lifetime.repaired == 0);
+
+    let mut result = graph
+        .execute(query(
+            "MATCH (:Account {address: '0xa11ce'})-[r:Lifetime]->() RETURN r.coins AS coins",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<f64>("coins").unwrap() == 15.0);
+
+    Ok(())
+}
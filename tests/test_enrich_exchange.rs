@@ -103,10 +103,12 @@ async fn test_swap_batch_cypher() -> Result<()> {
     };
 
     let list = vec![order1.clone(), order2];
-    let cypher_map = ExchangeOrder::to_cypher_map(&list);
-    let insert_query = ExchangeOrder::cypher_batch_insert_str(cypher_map);
+    let batch = ExchangeOrder::to_cypher_map(&list);
+    let insert_query = ExchangeOrder::cypher_batch_insert_str();
 
-    let mut res1 = graph.execute(query(&insert_query)).await?;
+    let mut res1 = graph
+        .execute(query(&insert_query).param("batch", batch))
+        .await?;
 
     while let Some(row) = res1.next().await? {
         let count: i64 = row.get("merged_tx_count").unwrap();
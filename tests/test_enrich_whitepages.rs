@@ -0,0 +1,59 @@
+mod support;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use libra_forensic_db::{
+    enrich_whitepages::{self, Whitepages},
+    neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
+};
+use neo4rs::query;
+use support::neo4j_testcontainer::start_neo4j_container;
+
+#[test]
+fn test_parse_whitepages_file() {
+    let path = env!("CARGO_MANIFEST_DIR");
+    let buf = PathBuf::from(path).join("tests/fixtures/whitepages.json");
+    let wp = Whitepages::parse_json_file(&buf).unwrap();
+    assert!(!wp.is_empty());
+}
+
+#[tokio::test]
+async fn test_whitepages_owner_with_quotes() -> Result<()> {
+    let path = env!("CARGO_MANIFEST_DIR");
+    let buf = PathBuf::from(path).join("tests/fixtures/whitepages_quotes.json");
+    let wp = Whitepages::parse_json_file(&buf)?;
+    assert!(wp.len() == 2);
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    // owners are only linked to accounts which exist
+    graph
+        .run(
+            query("MERGE (:Account {address: $address})")
+                .param("address", "0xaa7b724a51a8bd8e55208fef969209b3"),
+        )
+        .await?;
+
+    // the record without an owner is skipped
+    let merged = enrich_whitepages::impl_batch_tx_insert(&graph, &wp).await?;
+    assert!(merged == 1);
+
+    let mut res = graph
+        .execute(query(
+            "MATCH (o:Owner)-[:Owns]->(a:Account) RETURN o.alias AS alias, a.address AS address",
+        ))
+        .await?;
+    let row = res.next().await?.unwrap();
+    let alias: String = row.get("alias").unwrap();
+    assert!(alias == r#"o'brien "the whale""#);
+    let address: String = row.get("address").unwrap();
+    assert!(address == "0xaa7b724a51a8bd8e55208fef969209b3");
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+mod support;
+
+use anyhow::Result;
+use libra_forensic_db::{
+    extract_epoch_ending::extract_epoch_ending,
+    load::try_load_one_archive,
+    load_epoch::impl_batch_epoch_insert,
+    neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
+    scan::{scan_dir_archive, BundleContent},
+};
+use support::{
+    fixtures::v7_epoch_ending_fixtures_path, neo4j_testcontainer::start_neo4j_container,
+};
+
+#[tokio::test]
+async fn test_extract_epoch_ending() -> Result<()> {
+    let archive_dir = v7_epoch_ending_fixtures_path();
+    let epochs = extract_epoch_ending(&archive_dir).await?;
+    assert!(epochs.len() == 1);
+
+    // the ledger info at the waypoint ends epoch 116, and starts 117
+    let first = epochs.first().unwrap();
+    assert!(first.epoch == 117);
+    assert!(first.first_version == 38180076);
+    assert!(first.timestamp > 0);
+    assert!(!first.validators.is_empty());
+    assert!(first.validators.iter().all(|v| v.voting_power > 0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_load_epoch_ending() -> Result<()> {
+    libra_forensic_db::log_setup();
+    let archive_dir = v7_epoch_ending_fixtures_path();
+    let epochs = extract_epoch_ending(&archive_dir).await?;
+    let validator_count = epochs[0].validators.len() as i64;
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    let res = impl_batch_epoch_insert(&graph, &epochs).await?;
+    assert!(res.created_tx == 1);
+
+    let cypher_query = neo4rs::query(
+        "MATCH (v:Validator)-[r:ValidatorSet]->(e:Epoch {epoch: 117})
+         RETURN count(r) AS validator_count, min(e.first_version) AS first_version",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    let count: i64 = row.get("validator_count").unwrap();
+    assert!(count == validator_count);
+    let first_version: i64 = row.get("first_version").unwrap();
+    assert!(first_version == 38180076);
+
+    // previous epoch is closed out by the same ledger info
+    let cypher_query = neo4rs::query(
+        "MATCH (prev:Epoch {epoch: 116})-[:NextEpoch]->(:Epoch {epoch: 117})
+         RETURN prev.last_version AS last_version",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    let last_version: i64 = row.get("last_version").unwrap();
+    assert!(last_version == 38180075);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_epoch_ending_entry_point() -> Result<()> {
+    let archive_dir = v7_epoch_ending_fixtures_path();
+    let archive = scan_dir_archive(&archive_dir, None)?;
+    let (_, man) = archive.0.first_key_value().unwrap();
+    assert!(man.contents == BundleContent::EpochEnding);
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    let res = try_load_one_archive(man, &graph, 10, false).await?;
+    assert!(res.created_tx == 1);
+
+    Ok(())
+}
@@ -14,9 +14,11 @@ async fn test_extract_v5_from_manifest() -> Result<()> {
     let first = s.first().unwrap();
 
     assert!(&first.address.to_hex_literal() == "0x407d4d486fdc4e796504135e545be77");
-    assert!(first.balance == 100135.989588);
-    assert!(first.slow_wallet_unlocked == Some(140001.000000));
-    assert!(first.slow_wallet_transferred == Some(15999.000000));
+    // V5 amounts are read in V5 units, and normalized to current units
+    assert!(first.balance_raw == 100_135_989_588);
+    assert!(first.balance_normalized == 100_135_989_588 * 35);
+    assert!(first.slow_wallet_unlocked == Some(140001.000000 * 35.0));
+    assert!(first.slow_wallet_transferred == Some(15999.000000 * 35.0));
     assert!(first.sequence_num == 7);
 
     Ok(())
@@ -1,13 +1,29 @@
 mod support;
 
+use diem_crypto::{
+    ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
+    HashValue,
+};
+use diem_types::{
+    account_address::AccountAddress,
+    chain_id::ChainId,
+    transaction::{ExecutionStatus, RawTransaction, SignedTransaction},
+    vm_status::AbortLocation,
+};
+use libra_backwards_compatibility::sdk::v7_libra_framework_sdk_builder::EntryFunctionCall as V7EntryFunctionCall;
 use libra_forensic_db::{
-    extract_transactions::extract_current_transactions, scan::FrameworkVersion,
+    decode_entry_function::decode_entry_function_all_versions,
+    extract_balance_deltas::check_transfer_amount,
+    extract_transactions::{decode_execution_status, extract_current_transactions},
+    scan::FrameworkVersion,
+    schema_balance_delta::{WarehouseBalance, WarehouseBalanceDelta},
+    schema_transaction::{RelationLabel, WarehouseTxMaster},
 };
 
 #[tokio::test]
 async fn test_extract_tx_from_archive() -> anyhow::Result<()> {
     let archive_path = support::fixtures::v7_tx_manifest_fixtures_path();
-    let list = extract_current_transactions(&archive_path, &FrameworkVersion::V6).await?;
+    let list = extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
 
     assert!(list.0.len() == 6);
 
@@ -17,9 +33,284 @@ async fn test_extract_tx_from_archive() -> anyhow::Result<()> {
 #[tokio::test]
 async fn test_extract_v6_tx_from_archive() -> anyhow::Result<()> {
     let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
-    let list = extract_current_transactions(&archive_path, &FrameworkVersion::V6).await?;
+    let list = extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
     assert!(list.0.len() == 25);
     assert!(list.1.len() == 52);
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_extract_tx_version() -> anyhow::Result<()> {
+    let archive_path = support::fixtures::v7_tx_manifest_fixtures_path();
+    let (txs, _, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V7, false).await?;
+
+    // versions fall within the chunk, and give a total order
+    assert!(txs
+        .iter()
+        .all(|t| t.version >= 38100001 && t.version <= 38200000));
+    assert!(txs.windows(2).all(|w| w[0].version < w[1].version));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_extract_tx_gas_and_status() -> anyhow::Result<()> {
+    let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+    assert!(txs
+        .iter()
+        .all(|t| t.status == "Success" && t.abort_code.is_none()));
+    assert!(txs.iter().all(|t| t.gas_used > 0 && t.gas_unit_price > 0));
+    assert!(txs.iter().all(|t| t.gas_used <= t.max_gas_amount));
+
+    let (all_txs, _, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, true).await?;
+    assert!(all_txs.len() >= txs.len());
+    let failed: Vec<_> = all_txs.iter().filter(|t| t.status != "Success").collect();
+    assert!(all_txs.len() - txs.len() == failed.len());
+    for t in failed {
+        // a failed transaction still paid for its gas
+        assert!(t.gas_used > 0 && t.gas_used <= t.max_gas_amount);
+        assert!(t.abort_code.is_some() == (t.status == "MoveAbort"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_execution_status() {
+    let aborted = ExecutionStatus::MoveAbort {
+        location: AbortLocation::Script,
+        code: 65542,
+        info: None,
+    };
+    let (status, abort_code, abort_location) = decode_execution_status(&aborted);
+    assert!(status == "MoveAbort");
+    assert!(abort_code == Some(65542));
+    assert!(abort_location.as_deref() == Some("Script"));
+
+    let (status, abort_code, abort_location) = decode_execution_status(&ExecutionStatus::OutOfGas);
+    assert!(status == "OutOfGas");
+    assert!(abort_code.is_none() && abort_location.is_none());
+
+    let (status, abort_code, _) = decode_execution_status(&ExecutionStatus::Success);
+    assert!(status == "Success");
+    assert!(abort_code.is_none());
+}
+
+#[tokio::test]
+async fn test_extract_balance_deltas() -> anyhow::Result<()> {
+    let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _, deltas) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+    assert!(!deltas.is_empty());
+
+    // every user transaction pays gas, so it changes at least the sender's balance
+    assert!(txs.iter().all(|t| !t.balance_deltas.is_empty()));
+
+    // an account touched twice continues from the balance it was left with,
+    // but the archive does not know the balance before its first change
+    let mut last: std::collections::HashMap<_, _> = std::collections::HashMap::new();
+    for d in deltas.iter() {
+        match last.insert(d.address, d.after.clone()) {
+            Some(prev) => assert!(d.before.as_ref() == Some(&prev)),
+            None => {
+                assert!(d.before.is_none());
+                assert!(d.balance_change().is_none());
+            }
+        }
+    }
+
+    // decoded transfers agree with the recipient's balance change
+    for t in txs.iter() {
+        if let RelationLabel::Transfer(recipient, _) = &t.relation_label {
+            let amount = t.coin_amount().map_or(0, |a| a.normalized);
+            assert!(check_transfer_amount(recipient, amount, &t.balance_deltas));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_check_transfer_amount_is_exact() {
+    let recipient: AccountAddress = "0xb0b".parse().unwrap();
+    let balance = |b| WarehouseBalance {
+        balance: Some(b),
+        ..Default::default()
+    };
+    let delta = WarehouseBalanceDelta {
+        tx_hash: HashValue::zero(),
+        version: 1,
+        address: recipient,
+        before: Some(balance(90_000_000_000_000_001)),
+        after: balance(90_000_000_000_000_002),
+        provenance: None,
+    };
+    assert!(delta.balance_change() == Some(1));
+
+    // amounts a float in coins could not tell apart
+    let deltas = [delta];
+    assert!(check_transfer_amount(&recipient, 1, &deltas));
+    assert!(!check_transfer_amount(&recipient, 2, &deltas));
+
+    // the first change of an account in an archive has no change to check
+    let first = WarehouseBalanceDelta {
+        before: None,
+        ..deltas[0].clone()
+    };
+    assert!(first.balance_change().is_none());
+    assert!(first.to_cypher_json()["before_known"] == false);
+    assert!(first.to_cypher_json()["change_raw"].is_null());
+}
+
+#[test]
+fn test_extract_community_wallet_signers() {
+    let sender: AccountAddress = "0xa11ce".parse().unwrap();
+    let signers: Vec<AccountAddress> = vec!["0xb0b".parse().unwrap(), "0xca501".parse().unwrap()];
+
+    let call = V7EntryFunctionCall::CommunityWalletInitProposeOffer {
+        new_signers: signers.clone(),
+        num_signers: 2,
+    };
+    let (_, decoded) = decode_entry_function_all_versions(&signed_tx(sender, call), &[]).unwrap();
+    assert!(decoded == RelationLabel::CommunityWallet(signers.clone()));
+
+    // the sender is the community wallet, so the edges go to the signers
+    assert!(decoded.get_recipients() == signers);
+    assert!(decoded.get_recipients().iter().all(|r| r != &sender));
+
+    let tx = WarehouseTxMaster {
+        sender,
+        relation_label: decoded,
+        ..Default::default()
+    };
+    let record = tx.to_cypher_json();
+    assert!(record["recipient"] == signers[0].to_hex_literal());
+    assert!(
+        record["recipients"]
+            == serde_json::json!([signers[0].to_hex_literal(), signers[1].to_hex_literal()])
+    );
+}
+
+/// A transaction of `call`, sent by `sender`.
+fn signed_tx(sender: AccountAddress, call: V7EntryFunctionCall) -> SignedTransaction {
+    let private_key = Ed25519PrivateKey::try_from([7u8; 32].as_slice()).unwrap();
+    let public_key = Ed25519PublicKey::from(&private_key);
+    RawTransaction::new(sender, 0, call.encode(), 1_000, 100, 0, ChainId::test())
+        .sign(&private_key, public_key)
+        .unwrap()
+        .into_inner()
+}
+
+#[test]
+fn test_extract_typed_relations() {
+    let sender: AccountAddress = "0xa11ce".parse().unwrap();
+    let other: AccountAddress = "0xb0b".parse().unwrap();
+    let payee: AccountAddress = "0xca501".parse().unwrap();
+
+    let cases = [
+        (
+            V7EntryFunctionCall::DonorVoiceTxsProposePaymentTx {
+                multisig_address: other,
+                payee,
+                value: 5_000_000,
+                description: b"grant".to_vec(),
+            },
+            RelationLabel::DonorVoicePayment(other, payee, 5_000_000),
+            "DonorVoice",
+            other,
+        ),
+        (
+            V7EntryFunctionCall::DonorVoiceTxsProposeLiquidateTx {
+                multisig_address: other,
+            },
+            RelationLabel::DonorVoice(other),
+            "DonorVoice",
+            other,
+        ),
+        (
+            V7EntryFunctionCall::CommunityWalletInitInitCommunity {
+                initial_authorities: vec![other],
+                check_threshold: 1,
+            },
+            RelationLabel::CommunityWallet(vec![other]),
+            "CommunityWallet",
+            other,
+        ),
+        (
+            V7EntryFunctionCall::MultiActionClaimOffer {
+                multisig_address: other,
+            },
+            RelationLabel::Multisig(other),
+            "Multisig",
+            other,
+        ),
+        (
+            V7EntryFunctionCall::SlowWalletUserSetSlow {},
+            RelationLabel::SlowWallet,
+            "SlowWallet",
+            AccountAddress::ONE,
+        ),
+        (
+            V7EntryFunctionCall::ProofOfFeePofRetractBid {},
+            RelationLabel::ProofOfFeeBid,
+            "ProofOfFeeBid",
+            AccountAddress::ONE,
+        ),
+        (
+            V7EntryFunctionCall::ValidatorUniverseRegisterValidator {
+                consensus_pubkey: vec![],
+                proof_of_possession: vec![],
+                network_addresses: vec![],
+                fullnode_addresses: vec![],
+            },
+            RelationLabel::ValidatorRegistration,
+            "ValidatorRegistration",
+            AccountAddress::ONE,
+        ),
+        (
+            V7EntryFunctionCall::VouchRevoke {
+                friend_account: other,
+            },
+            RelationLabel::VouchRevoke(other),
+            "VouchRevoke",
+            other,
+        ),
+        (
+            V7EntryFunctionCall::JailUnjailByVoucher { addr: other },
+            RelationLabel::Unjail(other),
+            "Unjail",
+            other,
+        ),
+    ];
+
+    for (call, label, relation, recipient) in cases {
+        let (_, decoded) =
+            decode_entry_function_all_versions(&signed_tx(sender, call), &[]).unwrap();
+        assert!(decoded == label);
+
+        let tx = WarehouseTxMaster {
+            sender,
+            relation_label: decoded,
+            ..Default::default()
+        };
+        let record = tx.to_cypher_json();
+        assert!(record["relation"] == relation);
+        assert!(record["sender"] == sender.to_hex_literal());
+        assert!(record["recipient"] == recipient.to_hex_literal());
+        // none of them move coins, so none count towards the Lifetime totals
+        assert!(record["coins"].is_null());
+
+        match label {
+            RelationLabel::DonorVoicePayment(..) => {
+                assert!(record["payee"] == payee.to_hex_literal());
+                assert!(record["payment_coins_raw"] == 5_000_000);
+            }
+            _ => assert!(record["payee"].is_null()),
+        }
+    }
+}
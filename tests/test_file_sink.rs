@@ -0,0 +1,87 @@
+mod support;
+
+use std::{collections::BTreeSet, fs::File, path::Path};
+
+use anyhow::Result;
+use diem_temppath::TempPath;
+use libra_forensic_db::{
+    extract_snapshot::stream_current_snapshot,
+    file_sink::{export_one_archive, FileFormat, FileSink},
+    scan::scan_dir_archive,
+};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value;
+use support::fixtures;
+
+/// Reads all the NDJSON records of a table.
+fn read_ndjson_table(out_dir: &Path, table: &str) -> Result<Vec<Value>> {
+    let pattern = out_dir.join(table).join("*/*/*.ndjson");
+    let mut records = vec![];
+    for path in glob::glob(pattern.to_str().unwrap())? {
+        let s = std::fs::read_to_string(path?)?;
+        for line in s.lines() {
+            records.push(serde_json::from_str(line)?);
+        }
+    }
+    Ok(records)
+}
+
+#[tokio::test]
+async fn test_export_tx_ndjson() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let archive = scan_dir_archive(&archive_path, None)?;
+    let (_, man) = archive.0.first_key_value().unwrap();
+
+    let temp = TempPath::new();
+    temp.create_as_dir()?;
+    let sink = FileSink::new(temp.path(), FileFormat::Ndjson);
+    export_one_archive(man, &sink, false).await?;
+
+    let txs = read_ndjson_table(temp.path(), "transactions")?;
+    assert!(txs.len() == 25);
+
+    // each record is in the partition of its epoch
+    let first = &txs[0];
+    let epoch = first["epoch"].as_u64().unwrap();
+    assert!(sink
+        .partition_path("transactions", &man.archive_id, epoch)
+        .exists());
+
+    // events belong to the exported transactions
+    let hashes: BTreeSet<&str> = txs.iter().map(|t| t["tx_hash"].as_str().unwrap()).collect();
+    let events = read_ndjson_table(temp.path(), "events")?;
+    assert!(!events.is_empty());
+    assert!(events
+        .iter()
+        .all(|e| hashes.contains(e["tx_hash"].as_str().unwrap())));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_snapshot_parquet() -> Result<()> {
+    let archive_path = fixtures::v7_state_manifest_fixtures_path();
+    let stream = stream_current_snapshot(&archive_path).await?;
+    let time = stream.time.clone();
+    let batches = stream.collect_all().await?;
+    let accounts: usize = batches.iter().map(|b| b.accounts.len()).sum();
+
+    let temp = TempPath::new();
+    temp.create_as_dir()?;
+    let sink = FileSink::new(temp.path(), FileFormat::Parquet);
+    let count = sink.write_snapshots("snapshot", &time, &batches)?;
+    assert!(count == accounts as u64);
+
+    let path = sink.partition_path("snapshots", "snapshot", time.epoch);
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut rows = 0;
+    for batch in reader {
+        let batch = batch?;
+        assert!(batch.schema().field_with_name("balance").is_ok());
+        assert!(batch.schema().field_with_name("chunk_sha256").is_ok());
+        rows += batch.num_rows();
+    }
+    assert!(rows == accounts);
+
+    Ok(())
+}
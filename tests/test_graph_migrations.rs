@@ -0,0 +1,121 @@
+mod support;
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use libra_forensic_db::{
+    graph_migrations::{
+        all_schema_statements, get_schema_version, latest_version, migrate, schema_statement_name,
+        MIGRATIONS,
+    },
+    neo4j_init::{get_neo4j_localhost_pool, tx_relation_indexes},
+};
+use neo4rs::query;
+use support::neo4j_testcontainer::start_neo4j_container;
+
+#[test]
+fn test_migration_order_and_names() {
+    // versions start at 1 and increase by one
+    for (i, m) in MIGRATIONS.iter().enumerate() {
+        assert!(m.version == i as u32 + 1);
+    }
+
+    // a name used twice is silently skipped by IF NOT EXISTS
+    let mut names = BTreeSet::new();
+    for statement in all_schema_statements() {
+        let name = schema_statement_name(&statement).expect("not a schema statement");
+        assert!(names.insert(name.to_string()), "{} is used twice", name);
+    }
+    assert!(names.contains("swap_time"));
+    assert!(names.contains("lifetime_coins"));
+}
+
+#[test]
+fn test_tx_relation_indexes() {
+    let names: Vec<String> = tx_relation_indexes()
+        .iter()
+        .filter_map(|s| schema_statement_name(s).map(|n| n.to_string()))
+        .collect();
+    // every relationship type can be found by its ledger version
+    assert!(names.contains(&"transfer_version".to_string()));
+    assert!(names.contains(&"onboarding_version".to_string()));
+    // only the coin moving types are indexed by amount
+    assert!(names.contains(&"transfer_coins".to_string()));
+    // the unique constraint on Transfer tx_hash already backs an index
+    assert!(!names.contains(&"transfer_tx_hash".to_string()));
+    assert!(names.contains(&"vouch_tx_hash".to_string()));
+}
+
+#[tokio::test]
+async fn test_db_migrate() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+
+    // a graph loaded by a previous release
+    graph
+        .run(query(
+            r#"
+        MERGE (a:Account {address: "0xa11ce"})
+        MERGE (b:Account {address: "0xb0b"})
+        MERGE (c:Account {address: "0xc4"})
+        MERGE (a)-[:Tx {tx_hash: "0x01", relation: "Transfer", coins: 10.0}]->(b)
+        CREATE (a)-[:Tx {relation: "Vouch"}]->(c)
+        MERGE (a)-[:Lifetime {coins: 20.0}]->(b)
+        CREATE (:Snapshot {address: "0xa11ce", version: 1, framework_version: "V5", balance: 1.5})
+        "#,
+        ))
+        .await?;
+    assert!(get_schema_version(&graph).await? == 0);
+
+    let report = migrate(&graph, true).await?;
+    assert!(report.applied.len() == MIGRATIONS.len());
+    assert!(get_schema_version(&graph).await? == 0);
+
+    let report = migrate(&graph, false).await?;
+    assert!(report.from_version == 0);
+    assert!(report.to_version == latest_version());
+    assert!(report.applied[0]
+        .created
+        .contains(&"unique_address".to_string()));
+    assert!(report.applied[1].created.contains(&"swap_time".to_string()));
+    // an edge without a tx_hash is migrated too
+    assert!(report.applied[2].records == 2);
+    assert!(get_schema_version(&graph).await? == latest_version());
+    let mut result = graph
+        .execute(query(
+            "MATCH ()-[r:Tx]->() WITH count(r) AS legacy MATCH ()-[v:Vouch]->() RETURN legacy, count(v) AS vouches",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("legacy").unwrap() == 0);
+    assert!(row.get::<i64>("vouches").unwrap() == 1);
+
+    // the double counted total is rebuilt from the migrated edge
+    let mut result = graph
+        .execute(query(
+            "MATCH (:Account {address: \"0xa11ce\"})-[r:Lifetime]->(:Account {address: \"0xb0b\"}) RETURN r.coins AS coins, r.tx_count AS tx_count",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<f64>("coins").unwrap() == 10.0);
+    assert!(row.get::<i64>("tx_count").unwrap() == 1);
+
+    // the V5 balance is in current units, and the amount as read is kept
+    let mut result = graph
+        .execute(query(
+            "MATCH (s:Snapshot) RETURN s.balance_raw AS raw, s.balance_normalized AS normalized",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("raw").unwrap() == 1_500_000);
+    assert!(row.get::<i64>("normalized").unwrap() == 52_500_000);
+
+    // nothing is left to apply
+    let report = migrate(&graph, false).await?;
+    assert!(report.applied.is_empty());
+
+    Ok(())
+}
@@ -101,7 +101,7 @@ async fn test_rescue_v5_parse_set_wallet_tx() -> anyhow::Result<()> {
 
     // check there are transaction records with function args.
     let cypher_query = neo4rs::query(
-        "MATCH ()-[r:Tx]->()
+        "MATCH ()-[r:Transfer|Onboarding|Vouch]->()
         // WHERE r.args IS NOT NULL
         RETURN r
         LIMIT 1
@@ -1,10 +1,10 @@
 mod support;
 
 use libra_forensic_db::{
-    extract_snapshot::{extract_current_snapshot, extract_v5_snapshot},
+    extract_snapshot::{stream_current_snapshot, stream_v5_snapshot},
     load_account_state::{impl_batch_snapshot_insert, snapshot_batch},
     neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
-    schema_account_state::WarehouseAccState,
+    schema_account_state::{WarehouseAccState, WarehouseTime},
 };
 use support::{
     fixtures::{v5_state_manifest_fixtures_path, v7_state_manifest_fixtures_path},
@@ -29,7 +29,8 @@ async fn test_snapshot_unit() -> anyhow::Result<()> {
         .await
         .expect("could start index");
 
-    let merged_snapshots = impl_batch_snapshot_insert(&graph, &vec_snap).await?;
+    let merged_snapshots =
+        impl_batch_snapshot_insert(&graph, &WarehouseTime::default(), None, &vec_snap).await?;
     assert!(merged_snapshots.created_tx == 3);
 
     Ok(())
@@ -40,7 +41,11 @@ async fn test_snapshot_batch() -> anyhow::Result<()> {
     libra_forensic_db::log_setup();
     let archive_path = v7_state_manifest_fixtures_path();
     assert!(archive_path.exists());
-    let vec_snap = extract_current_snapshot(&archive_path).await?;
+    let stream = stream_current_snapshot(&archive_path).await?;
+    let time = stream.time.clone();
+    let batches = stream.collect_all().await?;
+    let first = &batches[0];
+    let n = first.accounts.len().min(100);
 
     let c = start_neo4j_container();
     let port = c.get_host_port_ipv4(7687);
@@ -51,9 +56,15 @@ async fn test_snapshot_batch() -> anyhow::Result<()> {
         .await
         .expect("could start index");
 
-    let merged_snapshots = impl_batch_snapshot_insert(&graph, &vec_snap[..100]).await?;
+    let merged_snapshots = impl_batch_snapshot_insert(
+        &graph,
+        &time,
+        first.provenance.as_deref(),
+        &first.accounts[..n],
+    )
+    .await?;
 
-    assert!(merged_snapshots.created_tx == 100);
+    assert!(merged_snapshots.created_tx == n as u64);
 
     // check DB to see what is persisted
     let cypher_query = neo4rs::query(
@@ -68,7 +79,7 @@ async fn test_snapshot_batch() -> anyhow::Result<()> {
     let row = result.next().await?.unwrap();
     let count: i64 = row.get("count_state_edges").unwrap();
 
-    assert!(count == 100i64);
+    assert!(count == n as i64);
 
     Ok(())
 }
@@ -78,7 +89,11 @@ async fn test_v5_snapshot_batch() -> anyhow::Result<()> {
     libra_forensic_db::log_setup();
     let archive_path = v5_state_manifest_fixtures_path();
     assert!(archive_path.exists());
-    let vec_snap = extract_v5_snapshot(&archive_path).await?;
+    let stream = stream_v5_snapshot(&archive_path).await?;
+    let time = stream.time.clone();
+    let batches = stream.collect_all().await?;
+    let first = &batches[0];
+    let n = first.accounts.len().min(100);
 
     let c = start_neo4j_container();
     let port = c.get_host_port_ipv4(7687);
@@ -89,8 +104,14 @@ async fn test_v5_snapshot_batch() -> anyhow::Result<()> {
         .await
         .expect("could start index");
 
-    let merged_snapshots = impl_batch_snapshot_insert(&graph, &vec_snap[..100]).await?;
-    assert!(merged_snapshots.created_tx == 100);
+    let merged_snapshots = impl_batch_snapshot_insert(
+        &graph,
+        &time,
+        first.provenance.as_deref(),
+        &first.accounts[..n],
+    )
+    .await?;
+    assert!(merged_snapshots.created_tx == n as u64);
 
     // check DB to see what is persisted
     let cypher_query = neo4rs::query(
@@ -104,7 +125,7 @@ async fn test_v5_snapshot_batch() -> anyhow::Result<()> {
     // Fetch the first row only
     let row = result.next().await?.unwrap();
     let count: i64 = row.get("count_state_edges").unwrap();
-    assert!(count == 100i64);
+    assert!(count == n as i64);
 
     Ok(())
 }
@@ -114,8 +135,7 @@ async fn test_snapshot_entrypoint() -> anyhow::Result<()> {
     libra_forensic_db::log_setup();
     let archive_dir = v5_state_manifest_fixtures_path();
     assert!(archive_dir.exists());
-    let vec_snap = extract_v5_snapshot(&archive_dir).await?;
-    assert!(vec_snap.len() == 17338);
+    let mut stream = stream_v5_snapshot(&archive_dir).await?;
 
     let c = start_neo4j_container();
     let port = c.get_host_port_ipv4(7687);
@@ -126,7 +146,7 @@ async fn test_snapshot_entrypoint() -> anyhow::Result<()> {
         .await
         .expect("could start index");
 
-    let merged_snapshots = snapshot_batch(&vec_snap, &graph, 1000, "test_v5_manifest").await?;
+    let merged_snapshots = snapshot_batch(&mut stream, &graph, 1000, "test_v5_manifest").await?;
 
     assert!(merged_snapshots.created_tx == 17338);
 
@@ -5,11 +5,15 @@ use diem_crypto::HashValue;
 use libra_forensic_db::{
     cypher_templates::{write_batch_tx_string, write_batch_user_create},
     extract_transactions::extract_current_transactions,
+    lifetime::rebuild_lifetime,
     load::{ingest_all, try_load_one_archive},
-    load_tx_cypher::tx_batch,
+    load_balance_deltas::balance_delta_batch,
+    load_events::event_batch,
+    load_tx_cypher::{impl_batch_tx_insert, tx_batch},
+    migrate_tx_relations::migrate_tx_relations,
     neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
     scan::{scan_dir_archive, FrameworkVersion},
-    schema_transaction::WarehouseTxMaster,
+    schema_transaction::{RelationLabel, WarehouseTxMaster},
 };
 use neo4rs::query;
 use support::{fixtures, neo4j_testcontainer::start_neo4j_container};
@@ -18,7 +22,8 @@ use support::{fixtures, neo4j_testcontainer::start_neo4j_container};
 async fn test_tx_batch() -> anyhow::Result<()> {
     libra_forensic_db::log_setup();
     let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
-    let (txs, _events) = extract_current_transactions(&archive_path, &FrameworkVersion::V6).await?;
+    let (txs, _events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
     assert!(txs.len() == 25);
 
     let c = start_neo4j_container();
@@ -41,7 +46,8 @@ async fn test_tx_batch() -> anyhow::Result<()> {
     assert!(res.created_tx == txs.len() as u64);
 
     let cypher_query = query(
-        "MATCH ()-[r:Tx]->()
+        "MATCH ()-[r]->()
+        WHERE r.tx_hash IS NOT NULL
         RETURN count(r) AS total_tx_count",
     );
 
@@ -84,6 +90,85 @@ async fn test_tx_batch() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_event_batch() -> anyhow::Result<()> {
+    libra_forensic_db::log_setup();
+    let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
+    let (_txs, events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+    assert!(events.len() == 52);
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph)
+        .await
+        .expect("could start index");
+
+    let archive_id = archive_path.file_name().unwrap().to_str().unwrap();
+    let res = event_batch(&events, &graph, 20, archive_id).await?;
+    assert!(res.created_tx == 52);
+
+    let cypher_query = query(
+        "MATCH (:Account)-[:Emitted]->(e:Event)
+        RETURN count(e) AS total_events",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    let total_events: i64 = row.get("total_events").unwrap();
+    assert!(total_events == 52);
+
+    // value movements carry an amount
+    let cypher_query = query(
+        "MATCH (e:Event)
+        WHERE e.event_type IN ['Withdraw', 'Deposit']
+        RETURN count(e) AS moves, count(e.amount) AS with_amount",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    let moves: i64 = row.get("moves").unwrap();
+    let with_amount: i64 = row.get("with_amount").unwrap();
+    assert!(moves > 0);
+    assert!(moves == with_amount);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_balance_delta_batch() -> anyhow::Result<()> {
+    libra_forensic_db::log_setup();
+    let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
+    let (_txs, _events, deltas) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+    assert!(!deltas.is_empty());
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph)
+        .await
+        .expect("could start index");
+
+    let archive_id = archive_path.file_name().unwrap().to_str().unwrap();
+    let res = balance_delta_batch(&deltas, &graph, 20, archive_id).await?;
+    assert!(res.created_tx == deltas.len() as u64);
+
+    let cypher_query = query(
+        "MATCH (:Account)-[:Delta]->(d:BalanceDelta)
+        RETURN count(d) AS total_deltas",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    let total_deltas: i64 = row.get("total_deltas").unwrap();
+    assert!(total_deltas == deltas.len() as i64);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_load_entry_point_tx() -> anyhow::Result<()> {
     let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
@@ -99,7 +184,7 @@ async fn test_load_entry_point_tx() -> anyhow::Result<()> {
         .await
         .expect("could start index");
 
-    let res = try_load_one_archive(man, &graph, 10).await?;
+    let res = try_load_one_archive(man, &graph, 10, false).await?;
 
     assert!(res.unique_accounts == 31);
     assert!(res.created_accounts == 25);
@@ -124,7 +209,7 @@ async fn test_gzip_archive_entry_point() -> Result<()> {
         .expect("could not get neo4j connection pool");
     maybe_create_indexes(&graph).await?;
 
-    ingest_all(&map, &graph, false, 250).await?;
+    ingest_all(&map, &graph, false, 250, false, None).await?;
     Ok(())
 }
 
@@ -148,9 +233,11 @@ async fn insert_with_cypher_string() -> Result<()> {
     // two tx records
     let list = vec![tx1, tx2, tx3];
 
-    let list_str = WarehouseTxMaster::to_cypher_map(&list);
+    let batch = WarehouseTxMaster::to_cypher_map(&list);
 
-    let cypher_string = write_batch_tx_string(&list_str);
+    // all default to the same relation label
+    let relation_type = list[0].relation_label.to_cypher_label();
+    let cypher_string = write_batch_tx_string(&relation_type);
 
     let c = start_neo4j_container();
     let port = c.get_host_port_ipv4(7687);
@@ -160,7 +247,7 @@ async fn insert_with_cypher_string() -> Result<()> {
     maybe_create_indexes(&graph).await?;
 
     // Execute the query
-    let cypher_query = query(&cypher_string);
+    let cypher_query = query(&cypher_string).param("batch", batch);
     let mut res = graph.execute(cypher_query).await?;
 
     let row = res.next().await?.unwrap();
@@ -179,6 +266,250 @@ async fn insert_with_cypher_string() -> Result<()> {
     let row = result.next().await?.unwrap();
     let total_tx_count: i64 = row.get("total_tx_count").unwrap();
     assert!(total_tx_count == 3);
+
+    let cypher_query = query(
+        "MATCH ()-[r:Configuration]->()
+         RETURN count(r) AS total_tx_count",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    let total_tx_count: i64 = row.get("total_tx_count").unwrap();
+    assert!(total_tx_count == 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_tx_edges() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    // edges as loaded by previous releases
+    graph
+        .run(query(
+            r#"
+        MERGE (a:Account {address: "0xa11ce"})
+        MERGE (b:Account {address: "0xb0b"})
+        MERGE (a)-[:Tx {tx_hash: "0x01", relation: "Transfer", coins: 10.0}]->(b)
+        MERGE (a)-[:Tx {tx_hash: "0x02", relation: "Transfer", coins: 5.0}]->(b)
+        MERGE (a)-[:Tx {tx_hash: "0x03", relation: "Vouch"}]->(b)
+        "#,
+        ))
+        .await?;
+
+    // a small batch size, so the migration loops
+    let migrated = migrate_tx_relations(&graph, 2).await?;
+    assert!(migrated == 3);
+
+    let cypher_query = query(
+        "MATCH ()-[r]->()
+         RETURN type(r) AS rel_type, count(r) AS total, sum(r.coins) AS coins
+         ORDER BY rel_type",
+    );
+    let mut result = graph.execute(cypher_query).await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<String>("rel_type").unwrap() == "Transfer");
+    assert!(row.get::<i64>("total").unwrap() == 2);
+    assert!(row.get::<f64>("coins").unwrap() == 15.0);
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<String>("rel_type").unwrap() == "Vouch");
+    assert!(result.next().await?.is_none());
+
+    // running again is a no-op
+    assert!(migrate_tx_relations(&graph, 2).await? == 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lifetime_idempotent() -> Result<()> {
+    let archive_path = support::fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    let lifetime_query = "MATCH ()-[r:Lifetime]->()
+        RETURN count(r) AS totals, sum(r.coins) AS coins, sum(r.tx_count) AS tx_count,
+          sum(r.coins_normalized) AS coins_normalized";
+    let coins_normalized: u64 = txs
+        .iter()
+        .filter_map(|t| t.coin_amount())
+        .map(|a| a.normalized)
+        .sum();
+
+    // the whole archive is written twice, bypassing the queue, like a retry
+    impl_batch_tx_insert(&graph, &txs).await?;
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    let coins: f64 = row.get("coins").unwrap();
+    let tx_count: i64 = row.get("tx_count").unwrap();
+    assert!(row.get::<i64>("totals").unwrap() == 18);
+    assert!(row.get::<i64>("coins_normalized").unwrap() as u64 == coins_normalized);
+
+    impl_batch_tx_insert(&graph, &txs).await?;
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("totals").unwrap() == 18);
+    assert!(row.get::<f64>("coins").unwrap() == coins);
+    assert!(row.get::<i64>("tx_count").unwrap() == tx_count);
+
+    // totals double counted by older versions are fixed by a rebuild
+    graph
+        .run(query("MATCH ()-[r:Lifetime]->() SET r.coins = r.coins * 2"))
+        .await?;
+    let totals = rebuild_lifetime(&graph, 5).await?;
+    assert!(totals == 18);
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    assert!((row.get::<f64>("coins").unwrap() - coins).abs() < 0.01);
+    assert!(row.get::<i64>("tx_count").unwrap() == tx_count);
+    assert!(row.get::<i64>("coins_normalized").unwrap() as u64 == coins_normalized);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rebuild_lifetime_legacy_tx() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+
+    // a graph loaded by the baseline loader, which double counted a reload
+    graph
+        .run(query(
+            r#"
+        MERGE (a:Account {address: "0xa11ce"})
+        MERGE (b:Account {address: "0xb0b"})
+        MERGE (a)-[:Tx {tx_hash: "0x01", relation: "Transfer", coins: 10.0}]->(b)
+        MERGE (a)-[:Tx {tx_hash: "0x02", relation: "Transfer", coins: 5.0}]->(b)
+        MERGE (a)-[:Lifetime {coins: 30.0}]->(b)
+        "#,
+        ))
+        .await?;
+
+    let lifetime_query =
+        "MATCH ()-[r:Lifetime]->() RETURN count(r) AS totals, sum(r.coins) AS coins, sum(r.tx_count) AS tx_count";
+
+    // the totals are rebuilt from the legacy edges, not deleted
+    let totals = rebuild_lifetime(&graph, 10).await?;
+    assert!(totals == 1);
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("totals").unwrap() == 1);
+    assert!(row.get::<f64>("coins").unwrap() == 15.0);
+    assert!(row.get::<i64>("tx_count").unwrap() == 2);
+
+    // and once the edges are migrated, they are counted once
+    migrate_tx_relations(&graph, 10).await?;
+    rebuild_lifetime(&graph, 10).await?;
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<f64>("coins").unwrap() == 15.0);
+    assert!(row.get::<i64>("tx_count").unwrap() == 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lifetime_legacy_total_stays_inexact() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    let sender = WarehouseTxMaster::default().sender;
+    let recipient = "0xb0b".parse()?;
+    // a total written before totals counted transactions
+    graph
+        .run(
+            query(
+                r#"
+        MERGE (a:Account {address: $sender})
+        MERGE (b:Account {address: $recipient})
+        MERGE (a)-[:Lifetime {coins: 30.0}]->(b)
+        "#,
+            )
+            .param("sender", sender.to_hex_literal())
+            .param("recipient", "0xb0b"),
+        )
+        .await?;
+
+    let sent = WarehouseTxMaster {
+        tx_hash: HashValue::random(),
+        relation_label: RelationLabel::Transfer(recipient, 10_000_000),
+        ..Default::default()
+    };
+    impl_batch_tx_insert(&graph, &[sent]).await?;
+
+    // the new transaction is added, but the exact sum is unknown
+    let mut result = graph
+        .execute(query(
+            "MATCH ()-[r:Lifetime]->() RETURN r.coins AS coins, r.coins_normalized IS NULL AS inexact",
+        ))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<f64>("coins").unwrap() == 40.0);
+    assert!(row.get::<bool>("inexact").unwrap());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lifetime_skips_failed_tx() -> Result<()> {
+    let recipient = "0xb0b".parse()?;
+    let sent = WarehouseTxMaster {
+        tx_hash: HashValue::random(),
+        relation_label: RelationLabel::Transfer(recipient, 10_000_000),
+        ..Default::default()
+    };
+    // an aborted transfer, loaded with `--include-failed`
+    let aborted = WarehouseTxMaster {
+        tx_hash: HashValue::random(),
+        relation_label: RelationLabel::Transfer(recipient, 5_000_000),
+        status: "MoveAbort".to_owned(),
+        abort_code: Some(65542),
+        ..Default::default()
+    };
+
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    impl_batch_tx_insert(&graph, &[sent, aborted]).await?;
+
+    let lifetime_query = r#"
+        MATCH ()-[r:Lifetime]->()
+        RETURN count(r) AS totals, sum(r.coins) AS coins, sum(r.tx_count) AS tx_count
+    "#;
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("totals").unwrap() == 1);
+    assert!(row.get::<f64>("coins").unwrap() == 10.0);
+    assert!(row.get::<i64>("tx_count").unwrap() == 1);
+
+    // the aborted edge keeps its amount, but a rebuild leaves it out too
+    rebuild_lifetime(&graph, 10).await?;
+    let mut result = graph.execute(query(lifetime_query)).await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<f64>("coins").unwrap() == 10.0);
+    assert!(row.get::<i64>("tx_count").unwrap() == 1);
+
     Ok(())
 }
 
@@ -202,9 +533,9 @@ async fn batch_users_create_unit() -> Result<()> {
     // two tx records
     let list = vec![tx1, tx2, tx3];
 
-    let list_str = WarehouseTxMaster::to_cypher_map(&list);
+    let batch = WarehouseTxMaster::to_cypher_map(&list);
 
-    let cypher_string = write_batch_user_create(&list_str);
+    let cypher_string = write_batch_user_create();
 
     let c = start_neo4j_container();
     let port = c.get_host_port_ipv4(7687);
@@ -214,7 +545,7 @@ async fn batch_users_create_unit() -> Result<()> {
     maybe_create_indexes(&graph).await?;
 
     // Execute the query
-    let cypher_query = query(&cypher_string);
+    let cypher_query = query(&cypher_string).param("batch", batch);
     let mut res = graph.execute(cypher_query).await?;
 
     let row = res.next().await?.unwrap();
@@ -0,0 +1,269 @@
+mod support;
+
+use anyhow::Result;
+use diem_crypto::HashValue;
+use diem_temppath::TempPath;
+use libra_forensic_db::{
+    extract_snapshot::{stream_current_snapshot, SnapshotStream},
+    extract_transactions::extract_current_transactions,
+    graph_sink::GraphSink,
+    load::{ingest_all, try_load_one_archive},
+    load_account_state::snapshot_batch,
+    load_events::event_queue_id,
+    load_tx_cypher::tx_batch,
+    memory_graph::MemoryGraph,
+    scan::{scan_dir_archive, FrameworkVersion},
+    schema_transaction::{RelationLabel, WarehouseTxMaster},
+};
+use support::fixtures;
+
+#[tokio::test]
+async fn test_memory_tx_batch() -> Result<()> {
+    libra_forensic_db::log_setup();
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+    assert!(txs.len() == 25);
+
+    let graph = MemoryGraph::new();
+    let archive_id = archive_path.file_name().unwrap().to_str().unwrap();
+    let res = tx_batch(&txs, &graph, 100, archive_id).await?;
+
+    assert!(res.unique_accounts == 25);
+    assert!(res.created_accounts == 25);
+    assert!(res.modified_accounts == 0);
+    assert!(res.created_tx == txs.len() as u64);
+    assert!(graph.tx_count() == txs.len());
+
+    let lifetime_count: usize = graph.store().lifetime.values().map(|to| to.len()).sum();
+    assert!(lifetime_count == 18);
+    let lifetime = graph.store().lifetime.clone();
+    let tx_count: u64 = lifetime
+        .values()
+        .flat_map(|to| to.values())
+        .map(|t| t.tx_count)
+        .sum();
+    assert!(tx_count > 0);
+
+    // the exact totals are the sums of the base units
+    let coins_normalized: u64 = txs
+        .iter()
+        .filter_map(|t| t.coin_amount())
+        .map(|a| a.normalized)
+        .sum();
+    let lifetime_normalized: u64 = lifetime
+        .values()
+        .flat_map(|to| to.values())
+        .map(|t| t.coins_normalized.expect("no coins_normalized"))
+        .sum();
+    assert!(lifetime_normalized == coins_normalized);
+
+    // the queue says this was loaded, so the batches are skipped
+    assert!(graph.are_all_completed(archive_id).await?);
+    let res = tx_batch(&txs, &graph, 100, archive_id).await?;
+    assert!(res.created_tx == 0);
+    assert!(graph.tx_count() == txs.len());
+
+    // writing the transactions again does not count them twice
+    graph.upsert_transactions(&txs).await?;
+    assert!(graph.store().lifetime == lifetime);
+    graph.store().rebuild_lifetime();
+    for (from, to, total) in lifetime
+        .iter()
+        .flat_map(|(from, to)| to.iter().map(move |(to, t)| (from, to, t)))
+    {
+        let rebuilt = &graph.store().lifetime[from][to];
+        assert!(rebuilt.tx_count == total.tx_count);
+        assert!(rebuilt.coins_normalized == total.coins_normalized);
+        assert!((rebuilt.coins - total.coins).abs() < 0.01);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_lifetime_skips_failed_tx() -> Result<()> {
+    let recipient = "0xb0b".parse()?;
+    let sent = WarehouseTxMaster {
+        tx_hash: HashValue::random(),
+        relation_label: RelationLabel::Transfer(recipient, 10_000_000),
+        ..Default::default()
+    };
+    let aborted = WarehouseTxMaster {
+        tx_hash: HashValue::random(),
+        relation_label: RelationLabel::Transfer(recipient, 5_000_000),
+        status: "MoveAbort".to_owned(),
+        abort_code: Some(65542),
+        ..Default::default()
+    };
+
+    let graph = MemoryGraph::new();
+    graph.upsert_transactions(&[sent, aborted]).await?;
+    assert!(graph.tx_count() == 2);
+
+    let mut store = graph.store();
+    for _ in 0..2 {
+        let totals: Vec<_> = store.lifetime.values().flat_map(|to| to.values()).collect();
+        assert!(totals.len() == 1);
+        assert!(totals[0].coins == 10.0);
+        assert!(totals[0].tx_count == 1);
+        // and a rebuild leaves the aborted transfer out too
+        store.rebuild_lifetime();
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_load_entry_point_tx() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let archive = scan_dir_archive(&archive_path, None)?;
+    let (_, man) = archive.0.first_key_value().unwrap();
+
+    let graph = MemoryGraph::new();
+    let res = try_load_one_archive(man, &graph, 10, false).await?;
+
+    assert!(res.unique_accounts == 31);
+    assert!(res.created_tx == 25);
+
+    let events_count: usize = graph.store().events.values().map(|e| e.len()).sum();
+    assert!(events_count == 52);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_memory_ingest_all_concurrent() -> Result<()> {
+    let mut map = scan_dir_archive(&fixtures::v6_tx_manifest_fixtures_path(), None)?;
+    let state = scan_dir_archive(&fixtures::v7_state_manifest_fixtures_path(), None)?;
+    map.0.extend(state.0);
+    let epochs = scan_dir_archive(&fixtures::v7_epoch_ending_fixtures_path(), None)?;
+    map.0.extend(epochs.0);
+    assert!(map.0.len() == 3);
+
+    // archives are extracted in parallel, and loaded by a single writer
+    let graph = MemoryGraph::new();
+    ingest_all(&map, &graph, false, 10, false, Some(2)).await?;
+
+    for man in map.0.values() {
+        assert!(graph.are_all_completed(&man.archive_id).await?);
+    }
+    assert!(graph.tx_count() == 25);
+    assert!(!graph.store().snapshots.is_empty());
+    assert!(!graph.store().epochs.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_ingest_all_retries_events() -> Result<()> {
+    let map = scan_dir_archive(&fixtures::v6_tx_manifest_fixtures_path(), None)?;
+    let (_, man) = map.0.first_key_value().unwrap();
+
+    let graph = MemoryGraph::new();
+    ingest_all(&map, &graph, false, 10, false, Some(1)).await?;
+    assert!(graph.are_all_completed(&man.archive_id).await?);
+
+    // only the events of the archive failed
+    graph.store().events.clear();
+    graph
+        .set_all_batches(&event_queue_id(&man.archive_id), false)
+        .await?;
+    graph
+        .mark_dead_letter(&event_queue_id(&man.archive_id), 0, "bad record", 1)
+        .await?;
+    assert!(graph.get_queued().await? == vec![event_queue_id(&man.archive_id)]);
+
+    // the next run retries them, though the transactions are complete
+    ingest_all(&map, &graph, false, 10, false, Some(1)).await?;
+    let events_count: usize = graph.store().events.values().map(|e| e.len()).sum();
+    assert!(events_count == 52);
+    assert!(graph.get_dead_letters().await?.is_empty());
+    assert!(graph.get_queued().await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_snapshot_batch() -> Result<()> {
+    let archive_path = fixtures::v7_state_manifest_fixtures_path();
+    let stream = stream_current_snapshot(&archive_path).await?;
+    let time = stream.time.clone();
+    let vec_snap: Vec<_> = stream
+        .collect_all()
+        .await?
+        .into_iter()
+        .flat_map(|b| b.accounts)
+        .collect();
+    let mut stream = SnapshotStream::from_vec(time, vec_snap[..100].to_vec());
+
+    let graph = MemoryGraph::new();
+    let res = snapshot_batch(&mut stream, &graph, 10, "snapshot").await?;
+    assert!(res.created_tx == 100);
+    assert!(graph.account_count() == 100);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_snapshot_stream() -> Result<()> {
+    let archive_path = fixtures::v7_state_manifest_fixtures_path();
+    let mut stream = stream_current_snapshot(&archive_path).await?;
+    let time = stream.time.clone();
+
+    // batches are never larger than asked for, nor span two chunk files
+    let first = stream.next_batch(1000).await?.unwrap().accounts;
+    assert!(!first.is_empty() && first.len() <= 1000);
+
+    let graph = MemoryGraph::new();
+    let res = snapshot_batch(&mut stream, &graph, 1000, "snapshot").await?;
+    assert!(res.created_tx == 24607 - first.len() as u64);
+    assert!(stream.next_batch(1000).await?.is_none());
+
+    // the time of the snapshot is on every record
+    let store = graph.store();
+    let (_, versions) = store.snapshots.first_key_value().unwrap();
+    let record = &versions[&time.version];
+    assert!(record["epoch"].as_u64() == Some(time.epoch));
+    assert!(record["framework_version"].as_str() == Some("V7"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_query_and_save() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+
+    let graph = MemoryGraph::new();
+    tx_batch(&txs, &graph, 100, "v6").await?;
+
+    // any transfer between two accounts is a path of one hop
+    let edge = graph
+        .store()
+        .tx_edges()
+        .find(|e| e.relation_type == "Transfer" && e.from != e.to)
+        .cloned()
+        .expect("no transfer found");
+
+    assert!(graph.neighbors(&edge.from).contains(&edge.to));
+    assert!(graph.outgoing(&edge.from).iter().any(|e| e.to == edge.to));
+    assert!(graph.incoming(&edge.to).iter().any(|e| e.from == edge.from));
+
+    let path = graph.shortest_path(&edge.from, &edge.to, 3).unwrap();
+    assert!(path == vec![edge.from.clone(), edge.to.clone()]);
+    assert!(graph
+        .shortest_path(&edge.from, "0xnotanaccount", 3)
+        .is_none());
+
+    // save and load the store
+    let temp = TempPath::new();
+    graph.save_to_file(temp.path())?;
+    let loaded = MemoryGraph::load_from_file(temp.path())?;
+    assert!(loaded.tx_count() == graph.tx_count());
+    assert!(loaded.account_count() == graph.account_count());
+    assert!(loaded.are_all_completed("v6").await?);
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+mod support;
+
+use anyhow::Result;
+use diem_temppath::TempPath;
+use libra_forensic_db::{
+    extract_snapshot::stream_current_snapshot,
+    extract_transactions::extract_current_transactions,
+    load::try_load_one_archive,
+    memory_graph::MemoryGraph,
+    provenance::sha256_file,
+    scan::{scan_dir_archive, FrameworkVersion},
+};
+use libra_storage::read_snapshot::load_snapshot_manifest;
+use support::fixtures;
+
+#[test]
+fn test_sha256_file() -> Result<()> {
+    let temp = TempPath::new();
+    temp.create_as_dir()?;
+    let path = temp.path().join("abc.chunk");
+    std::fs::write(&path, "abc")?;
+
+    assert!(
+        sha256_file(&path)? == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_extract_tx_provenance() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, events, deltas) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+
+    // the archive has a single chunk
+    let chunk_file = archive_path
+        .parent()
+        .unwrap()
+        .join("transaction_9900001-.e469/9900001-.chunk");
+    let chunk_sha256 = sha256_file(&chunk_file)?;
+
+    for tx in &txs {
+        let p = tx.provenance.as_ref().expect("no provenance");
+        assert!(p.archive_id == "transaction_9900001-.e469");
+        assert!(p.manifest_path == "transaction_9900001-.e469/transaction.manifest");
+        assert!(p.chunk_sha256 == chunk_sha256);
+    }
+    assert!(events.iter().all(|e| e.provenance.is_some()));
+    assert!(deltas.iter().all(|d| d.provenance.is_some()));
+
+    // the properties written to the sinks carry the provenance
+    let record = txs[0].to_cypher_json();
+    assert!(record["archive_id"] == "transaction_9900001-.e469");
+    assert!(record["chunk_sha256"] == chunk_sha256.as_str());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_load_provenance() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let archive = scan_dir_archive(&archive_path, None)?;
+    let (_, man) = archive.0.first_key_value().unwrap();
+
+    let graph = MemoryGraph::new();
+    try_load_one_archive(man, &graph, 10, false).await?;
+
+    let store = graph.store();
+    assert!(store
+        .tx_edges()
+        .all(|e| e.properties["archive_id"] == man.archive_id.as_str()));
+    assert!(store
+        .events
+        .values()
+        .flat_map(|e| e.values())
+        .all(|e| e["manifest_path"] == "transaction_9900001-.e469/transaction.manifest"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snapshot_provenance() -> Result<()> {
+    let archive_path = fixtures::v7_state_manifest_fixtures_path();
+    let manifest = load_snapshot_manifest(&archive_path.join("state.manifest"))?;
+    let chunk_hashes = manifest
+        .chunks
+        .iter()
+        .map(|c| sha256_file(&archive_path.parent().unwrap().join(&c.blobs)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // each batch is traced to the chunk file it was read from
+    let batches = stream_current_snapshot(&archive_path)
+        .await?
+        .collect_all()
+        .await?;
+    assert!(!batches.is_empty());
+    for b in &batches {
+        let p = b.provenance.as_ref().expect("no provenance");
+        assert!(p.archive_id == "state_epoch_116_ver_38180075.05af");
+        assert!(p.manifest_path == "state_epoch_116_ver_38180075.05af/state.manifest");
+        assert!(chunk_hashes.contains(&p.chunk_sha256));
+    }
+
+    Ok(())
+}
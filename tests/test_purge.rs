@@ -0,0 +1,201 @@
+mod support;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use anyhow::Result;
+use libra_forensic_db::{
+    extract_transactions::extract_current_transactions,
+    graph_sink::GraphSink,
+    load::try_load_one_archive,
+    load_tx_cypher::tx_batch,
+    memory_graph::MemoryGraph,
+    neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
+    provenance::Provenance,
+    purge::purge_archive,
+    scan::{scan_dir_archive, FrameworkVersion},
+    schema_epoch::WarehouseEpoch,
+    sql_sink::SqlSink,
+};
+use neo4rs::query;
+use serde_json::json;
+use sqlx::Row;
+use support::{fixtures, neo4j_testcontainer::start_neo4j_container};
+
+/// The epochs of an epoch-ending archive.
+fn archive_epochs(archive_id: &str, epochs: &[u64]) -> Vec<WarehouseEpoch> {
+    let provenance = Arc::new(Provenance {
+        archive_id: archive_id.to_string(),
+        manifest_path: format!("{}/epoch_ending.manifest", archive_id),
+        chunk_sha256: "00".to_string(),
+    });
+    epochs
+        .iter()
+        .map(|&epoch| WarehouseEpoch {
+            epoch,
+            first_version: epoch * 100,
+            timestamp: epoch * 1_000_000,
+            provenance: Some(provenance.clone()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_memory_purge_archive() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let archive = scan_dir_archive(&archive_path, None)?;
+    let (_, man) = archive.0.first_key_value().unwrap();
+
+    let graph = MemoryGraph::new();
+    try_load_one_archive(man, &graph, 10, false).await?;
+    assert!(graph.are_all_completed(&man.archive_id).await?);
+
+    // a transaction of another archive between the same accounts is kept
+    let edge = {
+        let mut store = graph.store();
+        let mut edge = store
+            .tx_edges()
+            .find(|e| e.properties["coins"].as_f64().unwrap_or_default() > 0.0)
+            .expect("no transaction with coins")
+            .clone();
+        edge.properties["tx_hash"] = "0xother".into();
+        edge.properties["archive_id"] = "other_archive".into();
+        edge.properties["archive_ids"] = json!(["other_archive"]);
+        store.transactions.insert(
+            "0xother".to_string(),
+            BTreeMap::from([(edge.to.clone(), edge.clone())]),
+        );
+        store.rebuild_lifetime();
+        edge
+    };
+
+    let report = purge_archive(&graph, &man.archive_id).await?;
+    assert!(report.transactions == 25);
+    assert!(report.lifetime_adjusted > 0);
+    assert!(report.queue_batches > 0);
+
+    let store = graph.store();
+    assert!(store.transactions.len() == 1);
+    assert!(store.events.is_empty());
+    assert!(store.balance_deltas.is_empty());
+    assert!(store.lifetime.len() == 1);
+    let total = &store.lifetime[&edge.from][&edge.to];
+    assert!(total.tx_count == 1);
+    assert!(Some(total.coins) == edge.properties["coins"].as_f64());
+    // the accounts may be used by other archives
+    assert!(!store.accounts.is_empty());
+    drop(store);
+
+    // the archive is queued for a clean reload
+    assert!(graph.get_queued().await? == vec![man.archive_id.clone()]);
+    assert!(!graph.are_all_completed(&man.archive_id).await?);
+
+    try_load_one_archive(man, &graph, 10, false).await?;
+    assert!(graph.tx_count() == 26);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_purge_shared_epoch() -> Result<()> {
+    // both archives hold the epoch at their boundary
+    let graph = MemoryGraph::new();
+    graph
+        .upsert_epochs(&archive_epochs("epoch_ending_1-", &[1, 2]))
+        .await?;
+    graph
+        .upsert_epochs(&archive_epochs("epoch_ending_2-", &[2, 3]))
+        .await?;
+
+    let report = purge_archive(&graph, "epoch_ending_1-").await?;
+    assert!(report.epochs == 1);
+    let epochs: Vec<u64> = graph.store().epochs.keys().copied().collect();
+    assert!(epochs == vec![2, 3]);
+    assert!(graph.store().epochs[&2]["archive_id"] == "epoch_ending_2-");
+
+    let report = purge_archive(&graph, "epoch_ending_2-").await?;
+    assert!(report.epochs == 2);
+    assert!(graph.store().epochs.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_purge_adjacent_epoch_archives() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let graph = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&graph).await?;
+
+    // epoch 3 of the second archive closes epoch 2 of the first
+    graph
+        .upsert_epochs(&archive_epochs("epoch_ending_1-", &[1, 2]))
+        .await?;
+    graph
+        .upsert_epochs(&archive_epochs("epoch_ending_3-", &[3, 4]))
+        .await?;
+
+    // the placeholder of epoch 0 and epoch 1 go, epoch 2 is kept
+    let report = purge_archive(&graph, "epoch_ending_1-").await?;
+    assert!(report.epochs == 2);
+
+    let mut result = graph
+        .execute(query(
+            "MATCH (e:Epoch) RETURN e.epoch AS epoch, e.archive_id AS archive_id ORDER BY epoch",
+        ))
+        .await?;
+    let mut epochs = vec![];
+    while let Some(row) = result.next().await? {
+        epochs.push((row.get::<i64>("epoch")?, row.get::<String>("archive_id")?));
+    }
+    assert!(epochs.iter().map(|(e, _)| *e).collect::<Vec<_>>() == vec![2, 3, 4]);
+    assert!(epochs.iter().all(|(_, a)| a == "epoch_ending_3-"));
+
+    let mut result = graph
+        .execute(query(
+            "MATCH (prev:Epoch {epoch: 2})-[:NextEpoch]->(:Epoch {epoch: 3}) RETURN prev.last_version AS last_version",
+        ))
+        .await?;
+    let row = result.next().await?.expect("epoch 2 was unlinked");
+    assert!(row.get::<i64>("last_version")? == 299);
+
+    let report = purge_archive(&graph, "epoch_ending_3-").await?;
+    assert!(report.epochs == 3);
+    let mut result = graph
+        .execute(query("MATCH (e:Epoch) RETURN count(e) AS epochs"))
+        .await?;
+    let row = result.next().await?.unwrap();
+    assert!(row.get::<i64>("epochs")? == 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sql_purge_archive() -> Result<()> {
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+
+    let sink = SqlSink::connect("sqlite::memory:").await?;
+    let archive_id = archive_path.file_name().unwrap().to_str().unwrap();
+    tx_batch(&txs, &sink, 10, archive_id).await?;
+
+    let report = purge_archive(&sink, archive_id).await?;
+    assert!(report.transactions == 25);
+    assert!(report.queue_batches == 3);
+
+    let row = sqlx::query("SELECT COUNT(*) AS num FROM transactions")
+        .fetch_one(sink.pool())
+        .await?;
+    assert!(row.try_get::<i64, _>("num")? == 0);
+
+    // only the first batch is queued, so the reload may batch differently
+    let status = sink.queue_status().await?;
+    assert!(status.len() == 1);
+    assert!(status[0].batches == 1);
+    assert!(status[0].pending == 1);
+
+    Ok(())
+}
@@ -2,6 +2,10 @@ mod support;
 
 use anyhow::Result;
 use libra_forensic_db::{
+    graph_sink::GraphSink,
+    load_balance_deltas::balance_delta_queue_id,
+    load_events::event_queue_id,
+    memory_graph::MemoryGraph,
     neo4j_init::{get_neo4j_localhost_pool, maybe_create_indexes},
     queue,
     scan::scan_dir_archive,
@@ -42,3 +46,79 @@ async fn test_queue() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_queue_dead_letter() -> Result<()> {
+    let c = start_neo4j_container();
+    let port = c.get_host_port_ipv4(7687);
+    let pool = get_neo4j_localhost_pool(port)
+        .await
+        .expect("could not get neo4j connection pool");
+    maybe_create_indexes(&pool).await?;
+
+    queue::update_task(&pool, "archive", false, 0).await?;
+    queue::mark_dead_letter(&pool, "archive", 0, "connection reset", 4).await?;
+
+    let dead_letters = queue::get_dead_letters(&pool).await?;
+    assert!(dead_letters.len() == 1);
+    assert!(dead_letters[0].error == "connection reset");
+    assert!(dead_letters[0].attempts == 4);
+    assert!(!queue::is_batch_complete(&pool, "archive", 0)
+        .await?
+        .unwrap());
+
+    // completing the batch clears the error
+    queue::update_task(&pool, "archive", true, 0).await?;
+    assert!(queue::get_dead_letters(&pool).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_queue_status_and_reset() -> Result<()> {
+    let graph = MemoryGraph::new();
+    graph.update_task("archive", true, 0).await?;
+    graph.update_task("archive", false, 1).await?;
+    graph.update_task("archive", false, 2).await?;
+    graph
+        .mark_dead_letter("archive", 2, "bad record", 1)
+        .await?;
+    graph
+        .update_task(&event_queue_id("archive"), true, 0)
+        .await?;
+
+    let status = graph.queue_status().await?;
+    let s = status.iter().find(|s| s.archive_id == "archive").unwrap();
+    assert!(s.batches == 3);
+    assert!(s.completed == 1);
+    assert!(s.pending == 1);
+    assert!(s.failed == 1);
+    assert!(s.updated_at.is_some());
+
+    // the events of the archive are re-queued with it
+    let batches = queue::reset_archive(&graph, "archive").await?;
+    assert!(batches == 4);
+    assert!(graph.get_dead_letters().await?.is_empty());
+    assert!(!graph.are_all_completed(&event_queue_id("archive")).await?);
+
+    let batches = queue::mark_archive_complete(&graph, "archive").await?;
+    assert!(batches == 4);
+    assert!(graph.are_all_completed("archive").await?);
+    assert!(graph.get_queued().await?.is_empty());
+
+    // the events and balance changes belong to their archive
+    assert!(queue::queue_archive_id(&event_queue_id("archive")) == "archive");
+    assert!(queue::queue_archive_id(&balance_delta_queue_id("archive")) == "archive");
+    assert!(queue::queue_archive_id("archive") == "archive");
+    graph
+        .mark_dead_letter(&balance_delta_queue_id("archive"), 0, "bad record", 1)
+        .await?;
+    let pending = queue::get_queued_archives(&graph).await?;
+    assert!(pending.into_iter().collect::<Vec<_>>() == vec!["archive".to_string()]);
+
+    // an archive which was never queued is skipped once marked complete
+    queue::mark_archive_complete(&graph, "other").await?;
+    assert!(graph.are_all_completed("other").await?);
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+mod support;
+
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use libra_forensic_db::{
+    graph_sink::GraphSink,
+    memory_graph::MemoryGraph,
+    retry::{is_transient, load_batch, RetryPolicy},
+};
+
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(4),
+    }
+}
+
+#[test]
+fn test_backoff_is_capped() {
+    let policy = fast_policy();
+    assert!(policy.backoff(0) == Duration::from_millis(1));
+    assert!(policy.backoff(1) == Duration::from_millis(2));
+    assert!(policy.backoff(2) == Duration::from_millis(4));
+    assert!(policy.backoff(10) == Duration::from_millis(4));
+}
+
+#[test]
+fn test_transient_errors() {
+    let io = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+    assert!(is_transient(
+        &anyhow::Error::new(io).context("execute query error")
+    ));
+    assert!(is_transient(&neo4rs::Error::ConnectionError.into()));
+    assert!(!is_transient(&anyhow!("no unique_accounts field")));
+}
+
+#[tokio::test]
+async fn test_retry_transient_then_succeed() -> Result<()> {
+    let graph = MemoryGraph::new();
+    graph.update_task("archive", false, 0).await?;
+
+    let calls = AtomicU32::new(0);
+    let res = load_batch(&graph, &fast_policy(), "archive", 0, || async {
+        if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+            Err(neo4rs::Error::ConnectionError.into())
+        } else {
+            Ok(7)
+        }
+    })
+    .await?;
+
+    assert!(res == Some(7));
+    assert!(calls.load(Ordering::SeqCst) == 3);
+    assert!(graph.are_all_completed("archive").await?);
+    assert!(graph.get_dead_letters().await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dead_letter_then_retry() -> Result<()> {
+    let graph = MemoryGraph::new();
+    graph.update_task("archive", false, 0).await?;
+
+    // retries run out
+    let calls = AtomicU32::new(0);
+    let res: Option<u64> = load_batch(&graph, &fast_policy(), "archive", 0, || async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(neo4rs::Error::ConnectionError.into())
+    })
+    .await?;
+    assert!(res.is_none());
+    assert!(calls.load(Ordering::SeqCst) == 4);
+
+    let dead_letters = graph.get_dead_letters().await?;
+    assert!(dead_letters.len() == 1);
+    assert!(dead_letters[0].attempts == 4);
+    assert!(graph.get_queued().await? == vec!["archive".to_string()]);
+
+    // errors which are not transient are not retried
+    let calls = AtomicU32::new(0);
+    let res: Option<u64> = load_batch(&graph, &fast_policy(), "archive", 1, || async {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(anyhow!("bad record"))
+    })
+    .await?;
+    assert!(res.is_none());
+    assert!(calls.load(Ordering::SeqCst) == 1);
+    assert!(graph.get_dead_letters().await?.len() == 2);
+
+    // a later run writes the dead letters again, which clears them
+    load_batch(&graph, &fast_policy(), "archive", 0, || async { Ok(()) }).await?;
+    load_batch(&graph, &fast_policy(), "archive", 1, || async { Ok(()) }).await?;
+    assert!(graph.get_dead_letters().await?.is_empty());
+    assert!(graph.are_all_completed("archive").await?);
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+mod support;
+
+use anyhow::Result;
+use libra_forensic_db::{
+    extract_snapshot::{stream_current_snapshot, SnapshotStream},
+    extract_transactions::extract_current_transactions,
+    graph_sink::GraphSink,
+    load_account_state::snapshot_batch,
+    load_tx_cypher::tx_batch,
+    scan::FrameworkVersion,
+    schema_exchange_orders::ExchangeOrder,
+    sql_sink::SqlSink,
+};
+use sqlx::Row;
+use support::fixtures;
+
+/// Counts the rows of a table.
+async fn count_rows(sink: &SqlSink, table: &str) -> Result<i64> {
+    let row = sqlx::query(&format!("SELECT COUNT(*) AS num FROM {table}"))
+        .fetch_one(sink.pool())
+        .await?;
+    Ok(row.try_get("num")?)
+}
+
+#[tokio::test]
+async fn test_sql_tx_batch() -> Result<()> {
+    libra_forensic_db::log_setup();
+    let archive_path = fixtures::v6_tx_manifest_fixtures_path();
+    let (txs, _events, _) =
+        extract_current_transactions(&archive_path, &FrameworkVersion::V6, false).await?;
+    assert!(txs.len() == 25);
+
+    let sink = SqlSink::connect("sqlite::memory:").await?;
+    let archive_id = archive_path.file_name().unwrap().to_str().unwrap();
+    let res = tx_batch(&txs, &sink, 100, archive_id).await?;
+
+    assert!(res.unique_accounts == 25);
+    assert!(res.created_accounts == 25);
+    assert!(res.modified_accounts == 0);
+    assert!(res.created_tx == txs.len() as u64);
+    assert!(count_rows(&sink, "accounts").await? == 25);
+    assert!(count_rows(&sink, "transactions").await? == 25);
+
+    // every transaction is tagged with its archive
+    let row = sqlx::query("SELECT COUNT(*) AS num FROM transactions WHERE archive_id = $1")
+        .bind(archive_id.to_string())
+        .fetch_one(sink.pool())
+        .await?;
+    assert!(row.try_get::<i64, _>("num")? == 25);
+
+    // the queue says this was loaded, so the batches are skipped
+    assert!(sink.are_all_completed(archive_id).await?);
+    let res = tx_batch(&txs, &sink, 100, archive_id).await?;
+    assert!(res.created_tx == 0);
+    assert!(count_rows(&sink, "transactions").await? == 25);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sql_snapshot_batch() -> Result<()> {
+    let archive_path = fixtures::v7_state_manifest_fixtures_path();
+    let stream = stream_current_snapshot(&archive_path).await?;
+    let time = stream.time.clone();
+    // the first 100 accounts, each with the chunk it was read from
+    let mut left = 100;
+    let batches = stream
+        .collect_all()
+        .await?
+        .into_iter()
+        .map_while(|mut b| {
+            if left == 0 {
+                return None;
+            }
+            b.accounts.truncate(left);
+            left -= b.accounts.len();
+            Some(b)
+        })
+        .collect();
+    let mut stream = SnapshotStream::from_batches(time, batches);
+
+    let sink = SqlSink::connect("sqlite::memory:").await?;
+    let res = snapshot_batch(&mut stream, &sink, 10, "snapshot").await?;
+    assert!(res.created_tx == 100);
+    assert!(count_rows(&sink, "accounts").await? == 100);
+    assert!(count_rows(&sink, "snapshots").await? == 100);
+
+    let row = sqlx::query("SELECT COUNT(*) AS num FROM snapshots WHERE chunk_sha256 IS NOT NULL")
+        .fetch_one(sink.pool())
+        .await?;
+    assert!(row.try_get::<i64, _>("num")? == 100);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sql_exchange_orders() -> Result<()> {
+    let sink = SqlSink::connect("sqlite::memory:").await?;
+    // three user ids exist in these two orders
+    let order1 = ExchangeOrder {
+        user: 1234,
+        accepter: 666,
+        ..Default::default()
+    };
+    let order2 = ExchangeOrder {
+        user: 4567,
+        accepter: 666,
+        ..Default::default()
+    };
+    let list = vec![order1, order2];
+
+    let (merged, ignored) = sink.upsert_exchange_orders(&list).await?;
+    assert!(merged == 2);
+    assert!(ignored == 0);
+    assert!(count_rows(&sink, "swap_accounts").await? == 3);
+
+    // loading the same orders again changes nothing
+    let (merged, ignored) = sink.upsert_exchange_orders(&list).await?;
+    assert!(merged == 0);
+    assert!(ignored == 2);
+    assert!(count_rows(&sink, "exchange_orders").await? == 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sql_queue() -> Result<()> {
+    let sink = SqlSink::connect("sqlite::memory:").await?;
+
+    // batches which were never queued are an error, like in Neo4j
+    assert!(sink.is_batch_complete("archive", 0).await.is_err());
+    assert!(!sink.are_all_completed("archive").await?);
+
+    sink.update_task("archive", false, 0).await?;
+    sink.update_task("archive", true, 1).await?;
+    assert!(sink.is_batch_complete("archive", 0).await? == Some(false));
+    assert!(sink.get_queued().await? == vec!["archive".to_string()]);
+    assert!(!sink.are_all_completed("archive").await?);
+
+    // a batch which failed after its retries keeps its error until completed
+    sink.mark_dead_letter("archive", 0, "connection reset", 4)
+        .await?;
+    let dead_letters = sink.get_dead_letters().await?;
+    assert!(dead_letters.len() == 1);
+    assert!(dead_letters[0].error == "connection reset");
+    assert!(dead_letters[0].attempts == 4);
+
+    let status = sink.queue_status().await?;
+    assert!(status.len() == 1);
+    assert!(status[0].batches == 2);
+    assert!(status[0].completed == 1);
+    assert!(status[0].failed == 1);
+    assert!(status[0].updated_at.is_some());
+
+    sink.update_task("archive", true, 0).await?;
+    assert!(sink.are_all_completed("archive").await?);
+    assert!(sink.get_queued().await?.is_empty());
+    assert!(sink.get_dead_letters().await?.is_empty());
+
+    assert!(sink.set_all_batches("archive", false).await? == 2);
+    assert!(sink.get_queued().await? == vec!["archive".to_string()]);
+
+    sink.clear_queue().await?;
+    assert!(count_rows(&sink, "queue").await? == 0);
+
+    Ok(())
+}